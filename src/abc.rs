@@ -0,0 +1,821 @@
+//! Reading tunes in ABC notation.
+//!
+//! Supported are the X:, T:, M:, L: and K: fields (also inline, like `[K:Ador]`), notes with
+//! accidentals, octave marks and lengths, rests, chords (the top note is used), ties,
+//! broken rhythms, triplets, repeats and first and second endings. Decorations, chord
+//! symbols, grace notes and lyrics are skipped.
+
+use crate::melody::{Score, TimedNote};
+use crate::pitch::Pitch;
+use crate::{pitch_class, Error};
+use std::collections::HashMap;
+
+/// longest note or rest read, in quarter notes
+const MAX_LENGTH: f64 = 4096.0;
+
+/// mode of a key, the scale degree of the major scale the tonic is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Ionian,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Aeolian,
+    Locrian,
+}
+
+impl Mode {
+    fn parse(s: &str) -> Option<Mode> {
+        let s = s.to_lowercase();
+        let mode = match s.get(..3).unwrap_or(&s) {
+            "" | "maj" | "ion" => Mode::Ionian,
+            "m" | "min" | "aeo" => Mode::Aeolian,
+            "dor" => Mode::Dorian,
+            "phr" => Mode::Phrygian,
+            "lyd" => Mode::Lydian,
+            "mix" => Mode::Mixolydian,
+            "loc" => Mode::Locrian,
+            _ => return None,
+        };
+        Some(mode)
+    }
+
+    /// semitones from the root of the major scale to the tonic
+    pub fn offset(self) -> usize {
+        match self {
+            Mode::Ionian => 0,
+            Mode::Dorian => 2,
+            Mode::Phrygian => 4,
+            Mode::Lydian => 5,
+            Mode::Mixolydian => 7,
+            Mode::Aeolian => 9,
+            Mode::Locrian => 11,
+        }
+    }
+
+    /// position the mode is played in on a harp in the key of its major scale
+    pub fn position(self) -> usize {
+        match self {
+            Mode::Ionian => 1,
+            Mode::Mixolydian => 2,
+            Mode::Dorian => 3,
+            Mode::Aeolian => 4,
+            Mode::Phrygian => 5,
+            Mode::Locrian => 6,
+            Mode::Lydian => 12,
+        }
+    }
+}
+
+/// key of a tune from its K: field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    /// pitch class of the tonic, 0 is C
+    pub tonic: usize,
+    pub mode: Mode,
+}
+
+/// a tune of an ABC file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tune {
+    /// reference number from the X: field
+    pub number: Option<u32>,
+    /// key from the first K: field, None for `K:none`
+    pub key: Option<Key>,
+    pub score: Score,
+}
+
+/// alterations of the notes C to B in a key
+type Signature = [i32; 7];
+
+const LETTERS: &str = "CDEFGAB";
+const SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// parses a K: field into the key and its signature
+fn parse_key(field: &str) -> Result<(Option<Key>, Signature), Error> {
+    let invalid = || Error::Abc(format!("invalid key \"{}\"", field));
+    let mut words = field.split_whitespace().peekable();
+    let mut signature = [0; 7];
+    let first = match words.next() {
+        None => return Ok((None, signature)),
+        Some(word) if word.eq_ignore_ascii_case("none") => return Ok((None, signature)),
+        Some(word) if word == "HP" || word == "Hp" => {
+            // highland pipes, written without a signature or with F# and C#, played in A mixolydian
+            if word == "Hp" {
+                signature[3] = 1;
+                signature[0] = 1;
+            }
+            let key = Key {
+                tonic: 9,
+                mode: Mode::Mixolydian,
+            };
+            return Ok((Some(key), signature));
+        }
+        Some(word) => word,
+    };
+
+    let split = first
+        .char_indices()
+        .find(|&(i, c)| i > 0 && c != '#' && c != 'b')
+        .map_or(first.len(), |(i, _)| i);
+    let (tonic_name, rest) = first.split_at(split);
+    let tonic = pitch_class(tonic_name).ok_or_else(invalid)?;
+    let mode_name = if rest.is_empty() {
+        match words.peek() {
+            Some(word) if Mode::parse(word).is_some() => words.next().unwrap(),
+            _ => "",
+        }
+    } else {
+        rest
+    };
+    let mode = Mode::parse(mode_name).ok_or_else(invalid)?;
+
+    // number of sharps, or flats if negative, of the major key
+    let major = (tonic + 12 - mode.offset()) % 12;
+    let mut fifths = (major * 7 % 12) as i32;
+    if fifths > 6 {
+        fifths -= 12;
+    }
+    if tonic_name.ends_with('b') && fifths > 0 {
+        fifths -= 12;
+    } else if tonic_name.ends_with('#') && fifths < 0 {
+        fifths += 12;
+    }
+    let (order, alter) = if fifths >= 0 {
+        ("FCGDAEB", 1)
+    } else {
+        ("BEADGCF", -1)
+    };
+    for letter in order.chars().take(fifths.unsigned_abs() as usize) {
+        signature[LETTERS.find(letter).unwrap()] = alter;
+    }
+
+    // explicit accidentals, e.g. "K:D ^c". Clefs and other options are skipped.
+    for word in words.filter(|word| word.starts_with(['^', '_', '='])) {
+        let accidental = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let letter = word[accidental.len()..].to_ascii_uppercase();
+        let index = LETTERS.find(&letter[..]).filter(|_| letter.len() == 1);
+        match (accidental_value(accidental), index) {
+            (Some(value), Some(index)) => signature[index] = value,
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok((Some(Key { tonic, mode }), signature))
+}
+
+fn accidental_value(accidental: &str) -> Option<i32> {
+    match accidental {
+        "^^" => Some(2),
+        "^" => Some(1),
+        "=" => Some(0),
+        "_" => Some(-1),
+        "__" => Some(-2),
+        _ => None,
+    }
+}
+
+/// parses the M: field into a time signature
+fn parse_meter(field: &str) -> Result<(u32, u32), Error> {
+    let field = field.trim();
+    match field {
+        "C" | "" | "none" => return Ok((4, 4)),
+        "C|" => return Ok((2, 2)),
+        _ => {}
+    }
+    let invalid = || Error::Abc(format!("invalid meter \"{}\"", field));
+    let (beats, unit) = field.split_once('/').ok_or_else(invalid)?;
+    // complex meters like (2+3)/8
+    let beats = beats
+        .trim_matches(|c| c == '(' || c == ')')
+        .split('+')
+        .map(|b| b.trim().parse::<u32>())
+        .sum::<Result<u32, _>>()
+        .map_err(|_| invalid())?;
+    let unit = unit.trim().parse().map_err(|_| invalid())?;
+    if beats == 0 || unit == 0 {
+        return Err(invalid());
+    }
+    Ok((beats, unit))
+}
+
+/// returns `length` in quarter notes if it is not too long to be meant
+fn checked_length(length: f64) -> Result<f64, Error> {
+    if length.is_finite() && length <= MAX_LENGTH {
+        Ok(length)
+    } else {
+        Err(Error::Abc(format!(
+            "note length of {} quarter notes is too long",
+            length
+        )))
+    }
+}
+
+/// parses the L: field into the unit note length in quarter notes
+fn parse_length(field: &str) -> Result<f64, Error> {
+    let invalid = || Error::Abc(format!("invalid unit note length \"{}\"", field));
+    let (num, den) = field.trim().split_once('/').unwrap_or((field.trim(), "1"));
+    let num: f64 = num.parse().map_err(|_| invalid())?;
+    let den: f64 = den.parse().map_err(|_| invalid())?;
+    if num <= 0.0 || den <= 0.0 {
+        return Err(invalid());
+    }
+    checked_length(num / den * 4.0).map_err(|_| invalid())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Note {
+        pitch: Pitch,
+        length: f64,
+        tie: bool,
+    },
+    Rest(f64),
+    RepeatStart,
+    RepeatEnd,
+    Ending(u32),
+}
+
+struct Parser {
+    unit: f64,
+    time: (u32, u32),
+    key: Option<Key>,
+    signature: Signature,
+    /// accidentals of the current bar by letter and octave
+    accidentals: HashMap<(usize, i32), i32>,
+    tokens: Vec<Token>,
+    /// length factor and number of notes left of a tuplet
+    tuplet: Option<(f64, usize)>,
+    /// length factor of the next note after a broken rhythm
+    broken: Option<f64>,
+}
+
+impl Parser {
+    fn field(&mut self, name: char, value: &str) -> Result<(), Error> {
+        match name {
+            'K' => {
+                let (key, signature) = parse_key(value)?;
+                self.key = self.key.or(key);
+                self.signature = signature;
+            }
+            'L' => self.unit = parse_length(value)?,
+            'M' => self.time = parse_meter(value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// parses a note length multiplier, e.g. "3", "/", "3/2" or "//"
+    fn length(chars: &[char], i: &mut usize) -> Result<f64, Error> {
+        let start = *i;
+        let number = |i: &mut usize| {
+            let start = *i;
+            while *i < chars.len() && chars[*i].is_ascii_digit() {
+                *i += 1;
+            }
+            chars[start..*i]
+                .iter()
+                .collect::<String>()
+                .parse::<f64>()
+                .ok()
+        };
+        let mut length = number(i).unwrap_or(1.0);
+        while *i < chars.len() && chars[*i] == '/' {
+            *i += 1;
+            let divisor = number(i).unwrap_or(2.0);
+            if divisor == 0.0 {
+                let text: String = chars[start..*i].iter().collect();
+                return Err(Error::Abc(format!("invalid note length \"{}\"", text)));
+            }
+            length /= divisor;
+        }
+        Ok(length)
+    }
+
+    /// parses a note starting with its accidentals, returns its pitch and length
+    fn note(&mut self, chars: &[char], i: &mut usize) -> Result<(Pitch, f64), Error> {
+        let start = *i;
+        while *i < chars.len() && matches!(chars[*i], '^' | '_' | '=') {
+            *i += 1;
+        }
+        let accidental: String = chars[start..*i].iter().collect();
+        let letter = chars
+            .get(*i)
+            .copied()
+            .filter(|c| "CDEFGABcdefgab".contains(*c))
+            .ok_or_else(|| Error::Abc(format!("expected a note after \"{}\"", accidental)))?;
+        *i += 1;
+        let index = LETTERS.find(letter.to_ascii_uppercase()).unwrap();
+        let mut octave = if letter.is_ascii_uppercase() { 4 } else { 5 };
+        while *i < chars.len() && matches!(chars[*i], '\'' | ',') {
+            octave += if chars[*i] == '\'' { 1 } else { -1 };
+            *i += 1;
+        }
+
+        let alter = if accidental.is_empty() {
+            self.accidentals
+                .get(&(index, octave))
+                .copied()
+                .unwrap_or(self.signature[index])
+        } else {
+            let alter = accidental_value(&accidental)
+                .ok_or_else(|| Error::Abc(format!("invalid accidental \"{}\"", accidental)))?;
+            self.accidentals.insert((index, octave), alter);
+            alter
+        };
+        let pitch = Pitch((octave + 1) * 12 + SEMITONES[index] + alter);
+        Ok((pitch, Parser::length(chars, i)?))
+    }
+
+    /// applies tuplets and broken rhythms to a note or rest length in unit lengths
+    fn scale(&mut self, length: f64) -> Result<f64, Error> {
+        let mut length = length * self.unit;
+        if let Some(factor) = self.broken.take() {
+            length *= factor;
+        }
+        if let Some((factor, left)) = self.tuplet {
+            length *= factor;
+            self.tuplet = if left > 1 {
+                Some((factor, left - 1))
+            } else {
+                None
+            };
+        }
+        checked_length(length)
+    }
+
+    fn bar(&mut self) {
+        self.accidentals.clear();
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), Error> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '^' | '_' | '=' | 'A'..='G' | 'a'..='g' => {
+                    let (pitch, length) = self.note(&chars, &mut i)?;
+                    let length = self.scale(length)?;
+                    self.tokens.push(Token::Note {
+                        pitch,
+                        length,
+                        tie: false,
+                    });
+                    continue;
+                }
+                'z' | 'x' => {
+                    i += 1;
+                    let length = Parser::length(&chars, &mut i)?;
+                    let length = self.scale(length)?;
+                    self.tokens.push(Token::Rest(length));
+                    continue;
+                }
+                'Z' | 'X' => {
+                    i += 1;
+                    let measures = Parser::length(&chars, &mut i)?;
+                    let measure = self.time.0 as f64 * 4.0 / self.time.1 as f64;
+                    let length = checked_length(measures * measure)?;
+                    self.tokens.push(Token::Rest(length));
+                    continue;
+                }
+                '-' => {
+                    if let Some(Token::Note { tie, .. }) = self.tokens.last_mut() {
+                        *tie = true;
+                    }
+                }
+                '>' | '<' => {
+                    let start = i;
+                    while i < chars.len() && chars[i] == c {
+                        i += 1;
+                    }
+                    let short = 0.5f64.powi((i - start) as i32);
+                    let (before, after) = if c == '>' {
+                        (2.0 - short, short)
+                    } else {
+                        (short, 2.0 - short)
+                    };
+                    match self.tokens.last_mut() {
+                        Some(Token::Note { length, .. }) | Some(Token::Rest(length)) => {
+                            *length *= before
+                        }
+                        _ => {}
+                    }
+                    self.broken = Some(after);
+                    continue;
+                }
+                '(' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                    // (p:q:r puts p notes into the time of q for the next r notes
+                    let start = i;
+                    i += 1;
+                    let mut numbers = Vec::new();
+                    loop {
+                        let digits = i;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                        let number: String = chars[digits..i].iter().collect();
+                        numbers.push(number.parse::<usize>().ok());
+                        if numbers.len() == 3 || chars.get(i) != Some(&':') {
+                            break;
+                        }
+                        i += 1;
+                    }
+                    let tuplet: String = chars[start..i].iter().collect();
+                    let invalid = || Error::Abc(format!("invalid tuplet \"{}\"", tuplet));
+                    let p = numbers[0].ok_or_else(invalid)?;
+                    let q = match numbers.get(1).copied().flatten() {
+                        Some(q) => q,
+                        None => match p {
+                            2 | 4 | 8 => 3,
+                            3 | 6 => 2,
+                            _ if self.time.1 == 8 && self.time.0.is_multiple_of(3) => 3,
+                            _ => 2,
+                        },
+                    };
+                    let r = numbers.get(2).copied().flatten().unwrap_or(p);
+                    if p == 0 || q == 0 || r == 0 {
+                        return Err(invalid());
+                    }
+                    self.tuplet = Some((q as f64 / p as f64, r));
+                    continue;
+                }
+                '"' | '!' | '+' => {
+                    // chord symbols, annotations and decorations
+                    i += 1;
+                    while i < chars.len() && chars[i] != c {
+                        i += 1;
+                    }
+                }
+                '{' => {
+                    // grace notes
+                    while i < chars.len() && chars[i] != '}' {
+                        i += 1;
+                    }
+                }
+                '[' if chars.get(i + 2) == Some(&':')
+                    && chars.get(i + 1).is_some_and(|c| c.is_ascii_alphabetic()) =>
+                {
+                    let end = chars[i..]
+                        .iter()
+                        .position(|&c| c == ']')
+                        .map_or(chars.len(), |e| i + e);
+                    let value: String = chars[i + 3..end].iter().collect();
+                    self.field(chars[i + 1], &value)?;
+                    i = end;
+                }
+                '[' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                    i += 1;
+                    let ending = Parser::length(&chars, &mut i)? as u32;
+                    self.tokens.push(Token::Ending(ending));
+                    continue;
+                }
+                '[' if chars.get(i + 1) == Some(&'|') => {
+                    self.bar();
+                    i += 2;
+                    continue;
+                }
+                '[' => {
+                    // chord, the top note is used
+                    i += 1;
+                    let mut top: Option<(Pitch, f64)> = None;
+                    while i < chars.len() && chars[i] != ']' {
+                        if matches!(chars[i], '^' | '_' | '=' | 'A'..='G' | 'a'..='g') {
+                            let note = self.note(&chars, &mut i)?;
+                            if top.is_none_or(|top| note.0 > top.0) {
+                                top = Some(note);
+                            }
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    i += 1;
+                    let length = Parser::length(&chars, &mut i)?;
+                    if let Some((pitch, note_length)) = top {
+                        let length = self.scale(note_length * length)?;
+                        self.tokens.push(Token::Note {
+                            pitch,
+                            length,
+                            tie: false,
+                        });
+                    }
+                    continue;
+                }
+                '|' | ':' => {
+                    let start = i;
+                    while i < chars.len() && matches!(chars[i], '|' | ':' | ']') {
+                        i += 1;
+                    }
+                    // "|:" starts a repeat, ":|" ends one and "::" does both
+                    let bar = &chars[start..i];
+                    if bar.first() == Some(&':') {
+                        self.tokens.push(Token::RepeatEnd);
+                    }
+                    if bar.len() > 1 && bar.last() == Some(&':') {
+                        self.tokens.push(Token::RepeatStart);
+                    }
+                    self.bar();
+                    if i < chars.len() && chars[i].is_ascii_digit() {
+                        let ending = Parser::length(&chars, &mut i)? as u32;
+                        self.tokens.push(Token::Ending(ending));
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+/// plays repeats and endings, and joins tied notes of the same pitch
+fn expand(tokens: &[Token]) -> Vec<TimedNote> {
+    let mut notes: Vec<TimedNote> = Vec::new();
+    let mut time = 0.0;
+    let mut tied = false;
+    let mut section = 0;
+    let mut second_pass = false;
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i] {
+            Token::Note { pitch, length, tie } => {
+                match notes.last_mut() {
+                    Some(last) if tied && last.pitch == pitch => last.duration += length,
+                    _ => notes.push(TimedNote {
+                        pitch,
+                        start: time,
+                        duration: length,
+                    }),
+                }
+                time += length;
+                tied = tie;
+            }
+            Token::Rest(length) => {
+                time += length;
+                tied = false;
+            }
+            Token::RepeatStart => {
+                section = i + 1;
+                second_pass = false;
+            }
+            Token::RepeatEnd if !second_pass => {
+                second_pass = true;
+                i = section;
+                continue;
+            }
+            Token::RepeatEnd => {
+                second_pass = false;
+                section = i + 1;
+            }
+            Token::Ending(n) if second_pass && n == 1 => {
+                // skip the first ending, to after the end of the repeat
+                let end = tokens[i..]
+                    .iter()
+                    .position(|t| *t == Token::RepeatEnd)
+                    .map_or(tokens.len(), |e| i + e + 1);
+                second_pass = false;
+                section = end;
+                i = end;
+                continue;
+            }
+            Token::Ending(_) => {}
+        }
+        i += 1;
+    }
+    notes
+}
+
+/// reads a single tune, from its X: or first field to its end
+fn parse_tune(text: &str) -> Result<Tune, Error> {
+    let mut number = None;
+    let mut title = None;
+    let mut parser = Parser {
+        unit: 0.5,
+        time: (4, 4),
+        key: None,
+        signature: [0; 7],
+        accidentals: HashMap::new(),
+        tokens: Vec::new(),
+        tuplet: None,
+        broken: None,
+    };
+    let mut unit = None;
+    let mut in_body = false;
+
+    for line in text.lines() {
+        // an empty line ends the tune
+        if in_body && line.trim().is_empty() {
+            break;
+        }
+        let line = line.split('%').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut chars = line.chars();
+        let field = match (chars.next(), chars.next()) {
+            (Some(name), Some(':')) if name.is_ascii_alphabetic() => Some((name, line[2..].trim())),
+            _ => None,
+        };
+
+        match field {
+            Some(('X', value)) => number = value.parse().ok(),
+            Some(('T', value)) if title.is_none() => title = Some(value.to_string()),
+            Some(('L', value)) => {
+                unit = Some(parse_length(value)?);
+                parser.unit = unit.unwrap();
+            }
+            Some(('K', value)) if !in_body => {
+                parser.field('K', value)?;
+                in_body = true;
+                // the default unit note length depends on the meter
+                if unit.is_none() {
+                    let (beats, beat) = parser.time;
+                    parser.unit = if (beats as f64) / (beat as f64) < 0.75 {
+                        0.25
+                    } else {
+                        0.5
+                    };
+                }
+            }
+            Some((name, value)) => parser.field(name, value)?,
+            None if in_body => parser.line(line)?,
+            None => {}
+        }
+    }
+    if !in_body {
+        return Err(Error::Abc(String::from("missing K: field")));
+    }
+
+    Ok(Tune {
+        number,
+        key: parser.key,
+        score: Score {
+            title,
+            time: parser.time,
+            notes: expand(&parser.tokens),
+        },
+    })
+}
+
+/// reads every tune of an ABC file
+pub fn parse(text: &str) -> Result<Vec<Tune>, Error> {
+    let mut tunes = Vec::new();
+    let mut tune = String::new();
+    for line in text.lines() {
+        if line.starts_with("X:") {
+            if tune.contains("K:") {
+                tunes.push(parse_tune(&tune)?);
+            }
+            tune.clear();
+        }
+        tune.push_str(line);
+        tune.push('\n');
+    }
+    if tune.contains("K:") {
+        tunes.push(parse_tune(&tune)?);
+    }
+    Ok(tunes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(tune: &Tune) -> Vec<(i32, f64, f64)> {
+        tune.score
+            .notes
+            .iter()
+            .map(|note| (note.pitch.0, note.start, note.duration))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let (key, signature) = parse_key("G").unwrap();
+        assert_eq!(
+            key,
+            Some(Key {
+                tonic: 7,
+                mode: Mode::Ionian
+            })
+        );
+        assert_eq!(signature, [0, 0, 0, 1, 0, 0, 0]);
+
+        // A dorian has the signature of G major
+        let (key, signature) = parse_key("ADor").unwrap();
+        assert_eq!(key.unwrap().mode, Mode::Dorian);
+        assert_eq!(key.unwrap().mode.position(), 3);
+        assert_eq!(signature, [0, 0, 0, 1, 0, 0, 0]);
+
+        let (key, signature) = parse_key("Bb minor ^c").unwrap();
+        assert_eq!(
+            key,
+            Some(Key {
+                tonic: 10,
+                mode: Mode::Aeolian
+            })
+        );
+        assert_eq!(signature, [1, -1, -1, 0, -1, -1, -1]);
+
+        assert_eq!(parse_key("none").unwrap(), (None, [0; 7]));
+        assert!(matches!(parse_key("H"), Err(Error::Abc(_))));
+    }
+
+    #[test]
+    fn test_parse() {
+        let abc = "\
+X:1
+T:Test
+M:6/8
+K:D
+% a comment
+A>B c2 | [DFA]3 =F^F _B-|_B z d' D, :|
+";
+        let tunes = parse(abc).unwrap();
+        assert_eq!(tunes.len(), 1);
+        let tune = &tunes[0];
+        assert_eq!(tune.number, Some(1));
+        assert_eq!(tune.score.title.as_deref(), Some("Test"));
+        assert_eq!(tune.score.time, (6, 8));
+        let expected = vec![
+            (69, 0.0, 0.75),
+            (71, 0.75, 0.25),
+            (73, 1.0, 1.0),
+            (69, 2.0, 1.5),
+            (65, 3.5, 0.5),
+            (66, 4.0, 0.5),
+            (70, 4.5, 1.0),
+            (86, 6.0, 0.5),
+            (50, 6.5, 0.5),
+        ];
+        let mut twice = expected.clone();
+        twice.extend(expected.iter().map(|&(p, start, d)| (p, start + 7.0, d)));
+        assert_eq!(notes(tune), twice);
+    }
+
+    #[test]
+    fn test_endings_and_tuplets() {
+        let abc = "\
+X:1
+L:1/4
+K:C
+|: C |1 D :|2 E |] (3GAB [L:1/8] c/ x/ |
+X:2
+T:Second
+K:Amix
+A
+";
+        let tunes = parse(abc).unwrap();
+        assert_eq!(tunes.len(), 2);
+        let pitches: Vec<i32> = tunes[0].score.notes.iter().map(|n| n.pitch.0).collect();
+        assert_eq!(pitches, vec![60, 62, 60, 64, 67, 69, 71, 72]);
+        let last = tunes[0].score.notes.last().unwrap();
+        let third = 2.0 / 3.0;
+        assert!((last.start - (4.0 + 3.0 * third)).abs() < 1e-9);
+        assert_eq!(last.duration, 0.25);
+
+        assert_eq!(
+            tunes[1].key,
+            Some(Key {
+                tonic: 9,
+                mode: Mode::Mixolydian
+            })
+        );
+        assert_eq!(tunes[1].score.notes[0].pitch, Pitch(69));
+        assert!(matches!(parse("X:1\nC D E\n"), Ok(tunes) if tunes.is_empty()));
+
+        // 3 notes in the time of 2, for 2 notes
+        let tunes = parse("X:1\nL:1/4\nK:C\n(3:2:2 C D E\n").unwrap();
+        let durations: Vec<f64> = tunes[0].score.notes.iter().map(|n| n.duration).collect();
+        assert_eq!(durations, vec![2.0 / 3.0, 2.0 / 3.0, 1.0]);
+        for tuplet in ["(0", "(3:0", "(3:2:0", "(99999999999999999999"] {
+            let abc = format!("X:1\nK:C\n{} C D E\n", tuplet);
+            assert!(matches!(parse(&abc), Err(Error::Abc(_))), "{}", tuplet);
+        }
+    }
+
+    #[test]
+    fn test_lengths() {
+        let tunes = parse("X:1\nL:1/4\nK:C\nC/ D3/2 E// F2 z Z2\n").unwrap();
+        let durations: Vec<f64> = tunes[0].score.notes.iter().map(|n| n.duration).collect();
+        assert_eq!(durations, vec![0.5, 1.5, 0.25, 2.0]);
+
+        for body in [
+            "C/0",
+            "C3/0",
+            "C99999999999999",
+            "z99999999999999",
+            "Z9999",
+            "[CE]9999",
+        ] {
+            let abc = format!("X:1\nK:C\n{}\n", body);
+            assert!(matches!(parse(&abc), Err(Error::Abc(_))), "{}", body);
+        }
+        assert!(matches!(
+            parse("X:1\nL:99999999/1\nK:C\nC\n"),
+            Err(Error::Abc(_))
+        ));
+    }
+}
@@ -0,0 +1,174 @@
+//! Size and scaling of exported layout charts, shared by the PNG, SVG and PDF exports.
+
+use crate::layout::Layout;
+use crate::theme::Theme;
+use crate::{Error, MAX_EXPORT_SIZE};
+
+pub const HOLE_SIZE: f32 = 50.0;
+pub const HOLE_GAP: f32 = 4.0;
+/// height of the caption at the top of the chart
+pub const CAPTION_HEIGHT: f32 = 50.0;
+/// charts are never smaller than the original 1024x600 export
+const MIN_WIDTH: f32 = 1024.0;
+const MIN_HEIGHT: f32 = 600.0;
+const MARGIN: f32 = 10.0;
+/// rough width of a character, relative to the font size
+const CHAR_WIDTH: f32 = 0.6;
+
+/// returns the x coordinate of the first hole, right of the row labels. The longest row
+/// label needs about ten times its font size, 190 pixels at the default 18.
+pub fn left(should_draw_row_labels: bool, theme: &Theme) -> f32 {
+    if should_draw_row_labels {
+        theme.label_font_size * 10.0 + 10.0
+    } else {
+        5.0
+    }
+}
+
+/// returns the baseline of text centered in a cell or in the caption, 30 pixels below
+/// the top for the default font size
+pub fn baseline(top: f32, font_size: f32) -> f32 {
+    top + HOLE_SIZE / 2.0 + font_size / 6.0
+}
+
+/// returns the diagonal lines of the pattern fill of a cell, from its bottom left to
+/// its top right
+pub fn hatch(x: f32, y: f32) -> Vec<((f32, f32), (f32, f32))> {
+    let spacing = 10.0;
+    (1..(2.0 * HOLE_SIZE / spacing) as usize)
+        .map(|i| {
+            // the line where the distances from the cell's left and top add up to k
+            let k = i as f32 * spacing;
+            let start = ((k - HOLE_SIZE).max(0.0), k.min(HOLE_SIZE));
+            let end = (k.min(HOLE_SIZE), (k - HOLE_SIZE).max(0.0));
+            ((x + start.0, y + start.1), (x + end.0, y + end.1))
+        })
+        .collect()
+}
+
+/// returns the line under the note of a cell
+pub fn underline(x: f32, y: f32, font_size: f32) -> ((f32, f32), (f32, f32)) {
+    let y = baseline(y, font_size) + font_size / 8.0;
+    ((x + 2.0, y), (x + HOLE_SIZE - 2.0, y))
+}
+
+/// returns the width and height in pixels needed to draw a layout and its caption
+/// without clipping, e.g. for 13 or 16 hole tunings
+pub fn natural_size(
+    layout: &Layout,
+    caption: &str,
+    should_draw_row_labels: bool,
+    theme: &Theme,
+) -> (f32, f32) {
+    let step = HOLE_SIZE + HOLE_GAP;
+    let holes_width = left(should_draw_row_labels, theme) + layout.holes as f32 * step + MARGIN;
+    let caption_width = if theme.caption.visible {
+        caption.chars().count() as f32 * theme.caption.font_size * CHAR_WIDTH + MARGIN
+    } else {
+        0.0
+    };
+    // every row and the row of hole numbers
+    let rows_height = CAPTION_HEIGHT + (layout.rows.len() + 1) as f32 * step + MARGIN;
+    (
+        MIN_WIDTH.max(holes_width).max(caption_width).ceil(),
+        MIN_HEIGHT.max(rows_height).ceil(),
+    )
+}
+
+/// returns the scale factor and the canvas size for drawing a chart of a natural size.
+/// Given both a width and a height, the chart is scaled to fit inside them; given one,
+/// the other follows the chart's aspect ratio. Otherwise the chart is scaled by `scale`.
+/// Fails if the canvas would be larger than `MAX_EXPORT_SIZE` on a side.
+pub fn fit(
+    natural: (f32, f32),
+    width: Option<u32>,
+    height: Option<u32>,
+    scale: f32,
+) -> Result<(f32, (u32, u32)), Error> {
+    let (natural_width, natural_height) = natural;
+    let sized = |scale: f32| {
+        (
+            (natural_width * scale).round().max(1.0) as u32,
+            (natural_height * scale).round().max(1.0) as u32,
+        )
+    };
+    let (scale, (width, height)) = match (width, height) {
+        (Some(width), Some(height)) => {
+            let scale = (width as f32 / natural_width).min(height as f32 / natural_height);
+            (scale, (width, height))
+        }
+        (Some(width), None) => {
+            let scale = width as f32 / natural_width;
+            (scale, (width, sized(scale).1))
+        }
+        (None, Some(height)) => {
+            let scale = height as f32 / natural_height;
+            (scale, (sized(scale).0, height))
+        }
+        (None, None) => (scale, sized(scale)),
+    };
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(Error::Export(format!("invalid scale {}", scale)));
+    }
+    if width > MAX_EXPORT_SIZE || height > MAX_EXPORT_SIZE {
+        return Err(Error::Export(format!(
+            "image of {}x{} pixels is larger than {} pixels on a side",
+            width, height, MAX_EXPORT_SIZE
+        )));
+    }
+    Ok((scale, (width, height)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChromaticScale, Setup, Tuning};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_natural_size() {
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        let root = ChromaticScale::new("C", None).unwrap();
+        let layout = Tuning::default().layout(&root, &setup);
+        assert_eq!(
+            natural_size(&layout, "C richter harmonica", true, &Theme::default()),
+            (1024.0, 600.0)
+        );
+
+        let notes = ["C", "E", "G"].repeat(6)[..16].join(" ");
+        let draw = ["D", "G", "B"].repeat(6)[..16].join(" ");
+        let tuning = Tuning::try_from(format!("{}\n{}", notes, draw).as_str()).unwrap();
+        let layout = tuning.layout(&root, &setup);
+        let (width, _) = natural_size(&layout, "C sixteen harmonica", true, &Theme::default());
+        assert!(width >= 190.0 + 16.0 * 54.0);
+    }
+
+    #[test]
+    fn test_hatch() {
+        let lines = hatch(100.0, 200.0);
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], ((100.0, 210.0), (110.0, 200.0)));
+        assert_eq!(lines[4], ((100.0, 250.0), (150.0, 200.0)));
+        assert_eq!(lines[8], ((140.0, 250.0), (150.0, 240.0)));
+    }
+
+    #[test]
+    fn test_fit() {
+        let natural = (1000.0, 500.0);
+        let fit = |width, height, scale| fit(natural, width, height, scale).ok();
+        assert_eq!(fit(None, None, 2.0), Some((2.0, (2000, 1000))));
+        assert_eq!(fit(Some(500), None, 1.0), Some((0.5, (500, 250))));
+        assert_eq!(fit(None, Some(1000), 1.0), Some((2.0, (2000, 1000))));
+        assert_eq!(fit(Some(500), Some(500), 1.0), Some((0.5, (500, 500))));
+
+        assert_eq!(fit(None, None, 1e30), None);
+        assert_eq!(fit(None, None, 0.0), None);
+        assert_eq!(fit(Some(4_000_000_000), None, 1.0), None);
+        // the height follows the width past the limit
+        assert_eq!(fit(Some(10_000), None, 1.0), Some((10.0, (10_000, 5000))));
+        assert_eq!(fit(None, Some(6000), 1.0), None);
+    }
+}
@@ -0,0 +1,227 @@
+//! User defaults and presets, read from `config.toml` in the harptool config directory:
+//!
+//! ```toml
+//! [defaults]
+//! tuning = "paddy richter"
+//! key = "A"
+//!
+//! [export]
+//! format = "svg"
+//! theme = "dark"
+//! width = 800
+//!
+//! # harptool --preset blues-g
+//! [presets.blues-g]
+//! key = "G"
+//! position = 2
+//! scale = "blues"
+//! ```
+//!
+//! Options given on the command line come first, then the preset, then the defaults.
+//! Reading the file needs the `config` feature.
+
+use crate::*;
+use std::collections::BTreeMap;
+
+/// tuning, key and setup of a layout. Fields that are not set fall back to the defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "config", derive(Deserialize), serde(deny_unknown_fields))]
+pub struct Settings {
+    pub tuning: Option<String>,
+    pub key: Option<String>,
+    /// sharps if true, flats if false
+    pub sharps: Option<bool>,
+    pub position: Option<usize>,
+    pub scale: Option<String>,
+}
+
+impl Settings {
+    /// returns these settings with the unset fields taken from `defaults`
+    pub fn or(&self, defaults: &Settings) -> Settings {
+        Settings {
+            tuning: self.tuning.clone().or_else(|| defaults.tuning.clone()),
+            key: self.key.clone().or_else(|| defaults.key.clone()),
+            sharps: self.sharps.or(defaults.sharps),
+            position: self.position.or(defaults.position),
+            scale: self.scale.clone().or_else(|| defaults.scale.clone()),
+        }
+    }
+}
+
+/// defaults for exported images
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "config",
+    derive(Deserialize),
+    serde(deny_unknown_fields, rename_all = "kebab-case")
+)]
+pub struct ExportSettings {
+    pub format: Option<ExportFormat>,
+    pub output: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub scale: Option<f32>,
+    pub dpi: Option<f32>,
+    pub theme: Option<String>,
+    pub label_rows: Option<bool>,
+}
+
+impl ExportSettings {
+    /// checks the sizes and scale factors like the options of `harptool export`
+    #[cfg(feature = "config")]
+    fn check(&self) -> Result<(), Error> {
+        for (field, size) in [("width", self.width), ("height", self.height)] {
+            if size.is_some_and(|size| size == 0 || size > MAX_EXPORT_SIZE) {
+                return Err(Error::Config(format!(
+                    "export {} must be between 1 and {}",
+                    field, MAX_EXPORT_SIZE
+                )));
+            }
+        }
+        for (field, factor, max) in [
+            ("scale", self.scale, MAX_EXPORT_SCALE),
+            ("dpi", self.dpi, MAX_EXPORT_SCALE * 96.0),
+        ] {
+            if factor.is_some_and(|factor| !(factor > 0.0 && factor <= max)) {
+                return Err(Error::Config(format!(
+                    "export {} must be positive and at most {}",
+                    field, max
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the contents of `config.toml`
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "config",
+    derive(Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct Config {
+    pub defaults: Settings,
+    pub export: ExportSettings,
+    pub presets: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    /// returns the path of `config.toml`
+    pub fn path() -> Option<PathBuf> {
+        let mut path = dirs::config_dir()?;
+        path.push("harptool");
+        path.push("config.toml");
+        Some(path)
+    }
+
+    /// reads `config.toml`, or returns empty settings if there is none
+    #[cfg(feature = "config")]
+    pub fn load() -> Result<Config, Error> {
+        match Config::path().map(fs::read_to_string) {
+            Some(Ok(contents)) => Config::parse(&contents),
+            Some(Err(err)) if err.kind() != io::ErrorKind::NotFound => {
+                Err(Error::Config(err.to_string()))
+            }
+            _ => Ok(Config::default()),
+        }
+    }
+
+    /// returns empty settings, as `config.toml` can only be read with the `config` feature
+    #[cfg(not(feature = "config"))]
+    pub fn load() -> Result<Config, Error> {
+        Ok(Config::default())
+    }
+
+    #[cfg(feature = "config")]
+    pub fn parse(contents: &str) -> Result<Config, Error> {
+        let config: Config =
+            toml::from_str(contents).map_err(|err| Error::Config(err.to_string()))?;
+        let settings = std::iter::once(("defaults", &config.defaults))
+            .chain(config.presets.iter().map(|(name, p)| (name.as_str(), p)));
+        for (name, settings) in settings {
+            if settings.position.is_some_and(|p| !(1..=12).contains(&p)) {
+                return Err(Error::Config(format!(
+                    "position of {} must be between 1 and 12",
+                    name
+                )));
+            }
+        }
+        config.export.check()?;
+        Ok(config)
+    }
+
+    /// returns the settings of a preset over the defaults, or just the defaults
+    pub fn settings(&self, preset: Option<&str>) -> Result<Settings, Error> {
+        let preset = match preset {
+            Some(name) => self.presets.get(name).ok_or_else(|| Error::UnknownPreset {
+                name: name.to_string(),
+                available: self.presets.keys().cloned().collect(),
+            })?,
+            None => return Ok(self.defaults.clone()),
+        };
+        Ok(preset.or(&self.defaults))
+    }
+}
+
+#[cfg(all(test, feature = "config"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            r#"
+            [defaults]
+            tuning = "paddy richter"
+            key = "A"
+            sharps = false
+
+            [export]
+            format = "svg"
+            label-rows = false
+
+            [presets.blues-g]
+            key = "G"
+            position = 2
+            scale = "blues"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.export.format, Some(ExportFormat::Svg));
+        assert_eq!(config.export.label_rows, Some(false));
+
+        let settings = config.settings(Some("blues-g")).unwrap();
+        assert_eq!(settings.tuning.as_deref(), Some("paddy richter"));
+        assert_eq!(settings.key.as_deref(), Some("G"));
+        assert_eq!(settings.sharps, Some(false));
+        assert_eq!(settings.position, Some(2));
+        assert_eq!(settings.scale.as_deref(), Some("blues"));
+        assert_eq!(config.settings(None).unwrap().key.as_deref(), Some("A"));
+        assert!(config.settings(Some("blues-a")).is_err());
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("[defaults]\nkye = \"G\"").is_err());
+        assert!(Config::parse("[presets.x]\nposition = 13").is_err());
+        for export in [
+            "width = 0",
+            "height = 20000",
+            "scale = -2.0",
+            "dpi = 0.0",
+            "scale = nan",
+        ] {
+            assert!(
+                matches!(
+                    Config::parse(&format!("[export]\n{}", export)),
+                    Err(Error::Config(_))
+                ),
+                "{}",
+                export
+            );
+        }
+        assert!(matches!(
+            config.settings(Some("blues-a")),
+            Err(Error::UnknownPreset { .. })
+        ));
+    }
+}
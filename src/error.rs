@@ -0,0 +1,147 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// errors returned by the harptool library
+#[derive(Debug)]
+pub enum Error {
+    /// root note is not a valid harmonica key
+    UnknownRoot(String),
+    /// sharps were requested for a flat root, or flats for a sharp root
+    ConflictingAccidentals(String),
+    /// tuning is neither built in nor found in a tuning directory or file
+    UnknownTuning {
+        name: String,
+        path: Option<PathBuf>,
+        /// the closest tuning names, see `names::suggest`
+        suggestions: Vec<String>,
+    },
+    /// tuning is built in, or its file exists in the tuning directory
+    TuningExists {
+        name: String,
+        path: Option<PathBuf>,
+    },
+    /// scale is not in `scales::get_scales`
+    UnknownScale {
+        name: String,
+        /// the closest scale names, see `names::suggest`
+        suggestions: Vec<String>,
+    },
+    /// tuning file could not be parsed
+    MalformedTuning(String),
+    /// note is not a scientific pitch name like "G4"
+    InvalidNote(String),
+    /// melody, score or file voice has no notes
+    EmptyMelody,
+    /// tab could not be parsed or is not playable on the tuning
+    InvalidTab {
+        tab: String,
+        reason: String,
+    },
+    /// MIDI file could not be read
+    Midi(String),
+    /// MusicXML score could not be read
+    MusicXml(String),
+    /// score is longer than `melody::MAX_MEASURES` measures and can't be written
+    ScoreTooLong,
+    /// ABC tune could not be read
+    Abc(String),
+    /// image could not be rendered or written
+    Export(String),
+    /// export theme is not built in, or its file could not be read
+    Theme(String),
+    /// config.toml could not be read
+    Config(String),
+    /// preset is not in config.toml
+    UnknownPreset {
+        name: String,
+        available: Vec<String>,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownRoot(root) => write!(f, "invalid root note \"{}\"", root),
+            Error::ConflictingAccidentals(root) => write!(
+                f,
+                "cannot choose sharp/flat notes if root is sharp/flat (root \"{}\")",
+                root
+            ),
+            Error::UnknownTuning {
+                name, suggestions, ..
+            } => write!(
+                f,
+                "tuning \"{}\" not found{}",
+                name,
+                did_you_mean(suggestions)
+            ),
+            Error::TuningExists { name, .. } => write!(f, "tuning \"{}\" already exists", name),
+            Error::UnknownScale { name, suggestions } => write!(
+                f,
+                "scale \"{}\" not found{}",
+                name,
+                did_you_mean(suggestions)
+            ),
+            Error::MalformedTuning(reason) => write!(f, "malformed tuning: {}", reason),
+            Error::InvalidNote(note) => write!(f, "invalid note \"{}\"", note),
+            Error::EmptyMelody => write!(f, "the melody has no notes"),
+            Error::InvalidTab { tab, reason } => write!(f, "invalid tab \"{}\": {}", tab, reason),
+            Error::Midi(reason) => write!(f, "could not read MIDI file: {}", reason),
+            Error::MusicXml(reason) => write!(f, "could not read MusicXML: {}", reason),
+            Error::ScoreTooLong => write!(
+                f,
+                "the score is too long to write, at most {} measures can be written",
+                crate::melody::MAX_MEASURES
+            ),
+            Error::Abc(reason) => write!(f, "could not read ABC: {}", reason),
+            Error::Export(reason) => write!(f, "could not export layout: {}", reason),
+            Error::Theme(reason) => write!(f, "could not load theme: {}", reason),
+            Error::Config(reason) => write!(f, "could not read config.toml: {}", reason),
+            Error::UnknownPreset { name, available } if available.is_empty() => write!(
+                f,
+                "preset \"{}\" not found, config.toml has no presets",
+                name
+            ),
+            Error::UnknownPreset { name, available } => write!(
+                f,
+                "preset \"{}\" not found (available: {})",
+                name,
+                available.join(", ")
+            ),
+            Error::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// returns ", did you mean "a" or "b"?" for suggested names, or nothing without any
+fn did_you_mean(suggestions: &[String]) -> String {
+    match suggestions {
+        [] => String::new(),
+        [rest @ .., last] if !rest.is_empty() => format!(
+            ", did you mean {} or \"{}\"?",
+            rest.iter()
+                .map(|s| format!("\"{}\"", s))
+                .collect::<Vec<_>>()
+                .join(", "),
+            last
+        ),
+        [name, ..] => format!(", did you mean \"{}\"?", name),
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
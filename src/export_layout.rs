@@ -1,3 +1,5 @@
+use crate::layout::{Cell, Label, Row};
+use crate::theme::{Color, ScaleMark, Theme};
 use crate::*;
 use font_kit::loaders::freetype::Font;
 use raqote::*;
@@ -6,202 +8,299 @@ use std::sync::Arc;
 fn draw_number_row(
     dt: &mut DrawTarget,
     font: &Font,
+    theme: &Theme,
     hole_size: f32,
     hole_gap: f32,
     pos: Point,
     len: usize,
 ) {
     for i in 1..=len {
-        let fill_color = Source::Solid(SolidSource::from_unpremultiplied_argb(200, 39, 215, 245));
         let rectpoint = Point::new(pos.x + (i - 1) as f32 * (hole_size + hole_gap), pos.y);
-        draw_square(dt, hole_size, rectpoint, fill_color);
+        draw_square(dt, theme, hole_size, rectpoint, theme.hole_number.fill);
 
         // draw hole number
-        dt.draw_text(
+        draw_text(
+            dt,
             font,
-            30.,
+            theme.note_font_size,
             i.to_string().as_ref(),
-            Point::new(rectpoint.x, rectpoint.y + 30.0),
-            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0)),
-            &DrawOptions::new(),
+            Point::new(
+                rectpoint.x,
+                chart::baseline(rectpoint.y, theme.note_font_size),
+            ),
+            theme.hole_number.text,
         );
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_row(
     dt: &mut DrawTarget,
     font: &Font,
+    theme: &Theme,
     hole_size: f32,
     hole_gap: f32,
     pos: Point,
-    notes: &Vec<Option<(&'static str, bool)>>,
+    cells: &[Option<Cell>],
+    label: Label,
 ) {
-    for i in 0..notes.len() {
-        if let Some((note, is_scale_note)) = notes.get(i).unwrap() {
-            let fill_color = if *is_scale_note {
-                Source::Solid(SolidSource::from_unpremultiplied_argb(255, 129, 255, 124))
+    for (i, cell) in cells.iter().enumerate() {
+        if let Some(cell) = cell {
+            let style = if cell.is_scale_note {
+                theme.scale_note
             } else {
-                Source::Solid(SolidSource::from_unpremultiplied_argb(100, 129, 255, 124))
+                theme.other_note
             };
 
             let rectpoint = Point::new(pos.x + i as f32 * (hole_size + hole_gap), pos.y);
-            draw_square(dt, hole_size, rectpoint, fill_color);
-
-            // draw hole number
-            dt.draw_text(
-                font,
-                30.,
-                note,
-                Point::new(rectpoint.x + 2.0, rectpoint.y + 30.0),
-                &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0)),
-                &DrawOptions::new(),
-            );
+            draw_square(dt, theme, hole_size, rectpoint, style.fill);
+            let mark = if cell.is_scale_note {
+                theme.scale_mark
+            } else {
+                ScaleMark::None
+            };
+            match mark {
+                ScaleMark::Pattern => {
+                    let color = Color {
+                        a: style.text.a / 3,
+                        ..style.text
+                    };
+                    for (from, to) in chart::hatch(rectpoint.x, rectpoint.y) {
+                        draw_line(dt, from, to, color, 1.5);
+                    }
+                }
+                ScaleMark::Underline => {
+                    let (from, to) =
+                        chart::underline(rectpoint.x, rectpoint.y, theme.note_font_size);
+                    draw_line(dt, from, to, style.text, 2.0);
+                }
+                ScaleMark::Bold | ScaleMark::None => {}
+            }
+
+            // draw note, twice and shifted for bold
+            let offsets: &[f32] = if mark == ScaleMark::Bold {
+                &[0.0, 1.0]
+            } else {
+                &[0.0]
+            };
+            for offset in offsets {
+                draw_text(
+                    dt,
+                    font,
+                    theme.note_font_size,
+                    &cell.label(label),
+                    Point::new(
+                        rectpoint.x + 2.0 + offset,
+                        chart::baseline(rectpoint.y, theme.note_font_size),
+                    ),
+                    style.text,
+                );
+            }
         }
     }
 }
 
-fn draw_square(dt: &mut DrawTarget, size: f32, point: Point, fill_color: Source) {
+fn source(color: Color) -> Source<'static> {
+    Source::Solid(SolidSource::from_unpremultiplied_argb(
+        color.a, color.r, color.g, color.b,
+    ))
+}
+
+/// draws text. raqote clips glyphs drawn with a scaling transform, so the text is
+/// placed and sized in device pixels instead.
+fn draw_text(dt: &mut DrawTarget, font: &Font, size: f32, text: &str, point: Point, color: Color) {
+    let transform = *dt.get_transform();
+    dt.set_transform(&Transform::identity());
+    dt.draw_text(
+        font,
+        size * transform.m11,
+        text,
+        transform.transform_point(point),
+        &source(color),
+        &DrawOptions::new(),
+    );
+    dt.set_transform(&transform);
+}
+
+fn draw_line(dt: &mut DrawTarget, from: (f32, f32), to: (f32, f32), color: Color, width: f32) {
+    let mut pb = PathBuilder::new();
+    pb.move_to(from.0, from.1);
+    pb.line_to(to.0, to.1);
+    let path = pb.finish();
+    dt.stroke(
+        &path,
+        &source(color),
+        &StrokeStyle {
+            width,
+            ..StrokeStyle::default()
+        },
+        &DrawOptions::new(),
+    );
+}
+
+fn draw_square(dt: &mut DrawTarget, theme: &Theme, size: f32, point: Point, fill: Color) {
     let mut pb = PathBuilder::new();
     pb.rect(point.x, point.y, size, size);
     let path = pb.finish();
-    dt.fill(&path, &fill_color, &DrawOptions::new());
-    let stroke_color = Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0));
+    dt.fill(&path, &source(fill), &DrawOptions::new());
     dt.stroke(
         &path,
-        &stroke_color,
-        &StrokeStyle::default(),
+        &source(theme.stroke),
+        &StrokeStyle {
+            width: theme.stroke_width,
+            ..StrokeStyle::default()
+        },
         &DrawOptions::new(),
     );
 }
 
-/// exports a PNG image of the note layout as "layout.png"
-pub fn export_png(
+/// returns a PNG image of the note layout, sized to fit every hole and scaled to the
+/// width, height or scale of the export options
+pub fn render(
     tuning_name: &str,
     tuning: &Tuning,
     root: &Option<ChromaticScale>,
     setup: &Setup,
     should_draw_row_labels: bool,
-) {
-    let mut dt = DrawTarget::new(1024, 600);
+    options: &ExportOptions,
+) -> Result<Vec<u8>, Error> {
+    let (layout, label) = match root {
+        Some(root) => (tuning.layout(root, setup), Label::Note),
+        None => (
+            tuning.layout(&ChromaticScale::new("C", None)?, setup),
+            Label::Degree,
+        ),
+    };
+    let caption = get_caption(tuning_name, root, setup);
+    let theme = &options.theme;
+    let (width, height) = chart::natural_size(&layout, &caption, should_draw_row_labels, theme);
+    let (scale, (canvas_width, canvas_height)) = chart::fit(
+        (width, height),
+        options.width,
+        options.height,
+        options.scale,
+    )?;
+
+    let mut dt = DrawTarget::new(canvas_width as i32, canvas_height as i32);
+    draw_background(
+        Point::new(0., 0.),
+        canvas_width as f32,
+        canvas_height as f32,
+        theme.background,
+        &mut dt,
+    );
+    dt.set_transform(&Transform::scale(scale, scale));
 
     let font = include_bytes!("dejavu-sans-font/DejaVuSans.ttf");
-    let font = font_kit::font::Font::from_bytes(Arc::new(font.to_vec()), 0).unwrap();
+    let font = font_kit::font::Font::from_bytes(Arc::new(font.to_vec()), 0)
+        .map_err(|e| Error::Export(format!("{:?}", e)))?;
 
-    let hole_size = 50.0;
-    let hole_gap = 4.0;
+    let hole_size = chart::HOLE_SIZE;
+    let hole_gap = chart::HOLE_GAP;
     let mut y = 0.0;
-    let x = if should_draw_row_labels { 190.0 } else { 5.0 };
+    let x = chart::left(should_draw_row_labels, theme);
 
     if should_draw_row_labels {
-        draw_background(Point::new(0., 50.), 185., 550., &mut dt);
+        draw_background(
+            Point::new(0., chart::CAPTION_HEIGHT),
+            x - 5.,
+            height - chart::CAPTION_HEIGHT,
+            theme.label_background,
+            &mut dt,
+        );
     }
 
-    for (i, (row, label)) in [
-        (&tuning.overblows, "overblows"),
-        (&tuning.blow_bends_full, "blow bends full step"),
-        (&tuning.blow_bends_half, "blow bends half step"),
-        (&tuning.blow, "blow"),
-        (&tuning.draw, "draw"),
-        (&tuning.bends_half, "bends half step"),
-        (&tuning.bends_full, "bends full step"),
-        (&tuning.bends_one_and_half, "bends 1 1/2 step"),
-        (&tuning.overdraws, "overdraws"),
-    ]
-    .iter()
-    .enumerate()
-    {
+    for (row, cells) in &layout.rows {
         y += hole_size + hole_gap;
 
-        let notes = if let Some(root) = root {
-            Tuning::get_row_notes(row, root, setup)
-        } else {
-            Tuning::get_row_degrees(row, setup)
-        };
-
         if should_draw_row_labels {
-            draw_row_label(&mut dt, &font, label, y + 35.0);
+            draw_row_label(&mut dt, &font, theme, row.label(), y + 35.0);
         }
         draw_row(
             &mut dt,
             &font,
+            theme,
             hole_size,
             hole_gap,
             Point::new(x, y),
-            &notes,
+            cells,
+            label,
         );
 
-        if i == 3 {
+        if *row == Row::Blow {
             y += hole_size + hole_gap;
             draw_number_row(
                 &mut dt,
                 &font,
+                theme,
                 hole_size,
                 hole_gap,
                 Point::new(x, y),
-                tuning.blow.len(),
+                layout.holes,
             );
         }
     }
 
-    draw_background(Point::new(0., 0.), 1024., 50., &mut dt);
-    let caption = get_caption(tuning_name, root, setup);
-    dt.draw_text(
-        &font,
-        30.,
-        &caption,
-        Point::new(0., 30.),
-        &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0)),
-        &DrawOptions::new(),
-    );
+    if theme.caption.visible {
+        draw_background(
+            Point::new(0., 0.),
+            width,
+            chart::CAPTION_HEIGHT,
+            theme.caption.background,
+            &mut dt,
+        );
+        draw_text(
+            &mut dt,
+            &font,
+            theme.caption.font_size,
+            &caption,
+            Point::new(0., chart::baseline(0., theme.caption.font_size)),
+            theme.caption.color,
+        );
+    }
+
+    encode_png(&dt)
+}
 
-    dt.write_png("layout.png").unwrap();
+/// encodes the pixels of a draw target as PNG, which raqote can only write to a file
+fn encode_png(dt: &DrawTarget) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::with_capacity(dt.get_data().len() * 4);
+    for pixel in dt.get_data() {
+        let a = (pixel >> 24) & 0xff;
+        // raqote stores premultiplied alpha
+        let unpremultiply = |c: u32| (c * 255 + a / 2).checked_div(a).unwrap_or(0) as u8;
+        data.push(unpremultiply((pixel >> 16) & 0xff));
+        data.push(unpremultiply((pixel >> 8) & 0xff));
+        data.push(unpremultiply(pixel & 0xff));
+        data.push(a as u8);
+    }
+
+    let err = |e: png::EncodingError| Error::Export(e.to_string());
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, dt.width() as u32, dt.height() as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(err)?;
+        writer.write_image_data(&data).map_err(err)?;
+    }
+    Ok(out)
 }
 
-fn draw_background(pos: Point, width: f32, height: f32, dt: &mut DrawTarget) {
+fn draw_background(pos: Point, width: f32, height: f32, color: Color, dt: &mut DrawTarget) {
     let mut pb = PathBuilder::new();
     pb.rect(pos.x, pos.y, width, height);
     let path = pb.finish();
-    dt.fill(
-        &path,
-        &Source::Solid(SolidSource::from_unpremultiplied_argb(150, 255, 255, 255)),
-        &DrawOptions::new(),
-    );
+    dt.fill(&path, &source(color), &DrawOptions::new());
 }
 
-fn draw_row_label(dt: &mut DrawTarget, font: &Font, label: &str, y: f32) {
-    dt.draw_text(
+fn draw_row_label(dt: &mut DrawTarget, font: &Font, theme: &Theme, label: &str, y: f32) {
+    draw_text(
+        dt,
         font,
-        18.,
+        theme.label_font_size,
         label,
         Point::new(0., y),
-        &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0)),
-        &DrawOptions::new(),
+        theme.text,
     );
 }
-
-fn to_ordinal(n: usize) -> String {
-    let suffix = match n {
-        1 => "st",
-        2 => "nd",
-        3 => "rd",
-        _ => "th",
-    };
-    format!("{}{}", n, suffix)
-}
-
-fn get_caption(tuning_name: &str, root: &Option<ChromaticScale>, setup: &Setup) -> String {
-    let scale_text = if let Some(scale) = &setup.scale {
-        format!(", {} scale", scale)
-    } else {
-        String::from("")
-    };
-
-    let harmonica_type = if let Some(root) = root {
-        format!("{} {} harmonica", root.0[0], tuning_name)
-    } else {
-        format!("{} harmonica", tuning_name)
-    };
-
-    let position = to_ordinal(setup.position);
-    format!("{}{}, {} position", harmonica_type, scale_text, position)
-}
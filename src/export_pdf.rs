@@ -0,0 +1,288 @@
+use crate::chart::{self, HOLE_GAP, HOLE_SIZE};
+use crate::layout::{Cell, Label, Row};
+use crate::theme::{Color as ThemeColor, ScaleMark, Theme};
+use crate::*;
+use printpdf::path::PaintMode;
+use printpdf::{
+    Color, IndirectFontRef, Line, Mm, PdfDocument, PdfLayerReference, Point, Pt, Rect, Rgb,
+};
+
+/// size of a landscape A4 page
+const PAGE_WIDTH: f32 = 297.0;
+const PAGE_HEIGHT: f32 = 210.0;
+const MARGIN: f32 = 10.0;
+
+/// a layout chart on one page of a PDF
+pub struct Page<'a> {
+    pub tuning_name: &'a str,
+    pub tuning: Tuning,
+    pub root: Option<ChromaticScale>,
+    pub setup: Setup<'a>,
+}
+
+/// draws on a page using the pixel coordinates of the PNG export, from the top left
+struct Canvas<'a> {
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    theme: &'a Theme,
+    /// millimeters per pixel, fitting the chart on the page
+    scale: f32,
+}
+
+impl Canvas<'_> {
+    fn x(&self, x: f32) -> Mm {
+        Mm(MARGIN + x * self.scale)
+    }
+
+    fn y(&self, y: f32) -> Mm {
+        Mm(PAGE_HEIGHT - MARGIN - y * self.scale)
+    }
+
+    /// the background of the theme, on white paper
+    fn paper(&self) -> ThemeColor {
+        self.theme.background.over(ThemeColor::rgb(255, 255, 255))
+    }
+
+    /// returns a color of the theme blended with the paper, as PDFs have no transparency
+    fn color(&self, color: ThemeColor) -> Color {
+        let color = color.over(self.paper());
+        let channel = |c: u8| c as f32 / 255.0;
+        Color::Rgb(Rgb::new(
+            channel(color.r),
+            channel(color.g),
+            channel(color.b),
+            None,
+        ))
+    }
+
+    fn draw_background(&self) {
+        if self.theme.background.a == 0 {
+            return;
+        }
+        self.layer.set_fill_color(self.color(self.theme.background));
+        let rect =
+            Rect::new(Mm(0.0), Mm(0.0), Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT)).with_mode(PaintMode::Fill);
+        self.layer.add_rect(rect);
+    }
+
+    fn draw_text(&self, x: f32, y: f32, size: f32, text: &str, color: ThemeColor) {
+        self.layer.set_fill_color(self.color(color));
+        let size = Pt::from(Mm(size * self.scale)).0;
+        self.layer
+            .use_text(text, size, self.x(x), self.y(y), &self.font);
+    }
+
+    /// draws a line `width` pixels wide
+    fn draw_line(&self, from: (f32, f32), to: (f32, f32), color: ThemeColor, width: f32) {
+        self.layer.set_outline_color(self.color(color));
+        self.layer
+            .set_outline_thickness(Pt::from(Mm(width * self.scale)).0);
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(self.x(from.0), self.y(from.1)), false),
+                (Point::new(self.x(to.0), self.y(to.1)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    /// draws a square filled with a color, outlined like every cell of the theme
+    fn draw_square(&self, x: f32, y: f32, fill: ThemeColor) {
+        self.layer.set_fill_color(self.color(fill));
+        self.layer.set_outline_color(self.color(self.theme.stroke));
+        self.layer
+            .set_outline_thickness(0.5 * self.theme.stroke_width);
+        let rect = Rect::new(
+            self.x(x),
+            self.y(y + HOLE_SIZE),
+            self.x(x + HOLE_SIZE),
+            self.y(y),
+        )
+        .with_mode(PaintMode::FillStroke);
+        self.layer.add_rect(rect);
+    }
+
+    fn draw_number_row(&self, x: f32, y: f32, len: usize) {
+        let theme = self.theme;
+        for i in 1..=len {
+            let rect_x = x + (i - 1) as f32 * (HOLE_SIZE + HOLE_GAP);
+            self.draw_square(rect_x, y, theme.hole_number.fill);
+            self.draw_text(
+                rect_x,
+                chart::baseline(y, theme.note_font_size),
+                theme.note_font_size,
+                &i.to_string(),
+                theme.hole_number.text,
+            );
+        }
+    }
+
+    fn draw_row(&self, x: f32, y: f32, cells: &[Option<Cell>], label: Label) {
+        let theme = self.theme;
+        for (i, cell) in cells.iter().enumerate() {
+            if let Some(cell) = cell {
+                let style = if cell.is_scale_note {
+                    theme.scale_note
+                } else {
+                    theme.other_note
+                };
+                let rect_x = x + i as f32 * (HOLE_SIZE + HOLE_GAP);
+                self.draw_square(rect_x, y, style.fill);
+                let mark = if cell.is_scale_note {
+                    theme.scale_mark
+                } else {
+                    ScaleMark::None
+                };
+                match mark {
+                    ScaleMark::Pattern => {
+                        let color = ThemeColor {
+                            a: style.text.a / 3,
+                            ..style.text
+                        };
+                        for (from, to) in chart::hatch(rect_x, y) {
+                            self.draw_line(from, to, color, 1.5);
+                        }
+                    }
+                    ScaleMark::Underline => {
+                        let (from, to) = chart::underline(rect_x, y, theme.note_font_size);
+                        self.draw_line(from, to, style.text, 2.0);
+                    }
+                    ScaleMark::Bold | ScaleMark::None => {}
+                }
+
+                // draw the note twice and shifted for bold
+                let offsets: &[f32] = if mark == ScaleMark::Bold {
+                    &[0.0, 1.0]
+                } else {
+                    &[0.0]
+                };
+                for offset in offsets {
+                    self.draw_text(
+                        rect_x + 2.0 + offset,
+                        chart::baseline(y, theme.note_font_size),
+                        theme.note_font_size,
+                        &cell.label(label),
+                        style.text,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// draws a layout chart like the PNG export, scaled to fit the page. Returns its caption.
+fn draw_page(
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    theme: &Theme,
+    page: &Page,
+    should_draw_row_labels: bool,
+) -> Result<String, Error> {
+    let (layout, label) = match &page.root {
+        Some(root) => (page.tuning.layout(root, &page.setup), Label::Note),
+        None => (
+            page.tuning
+                .layout(&ChromaticScale::new("C", None)?, &page.setup),
+            Label::Degree,
+        ),
+    };
+    let caption = get_caption(page.tuning_name, &page.root, &page.setup);
+    let (width, height) = chart::natural_size(&layout, &caption, should_draw_row_labels, theme);
+    let canvas = Canvas {
+        layer,
+        font,
+        theme,
+        scale: ((PAGE_WIDTH - 2.0 * MARGIN) / width).min((PAGE_HEIGHT - 2.0 * MARGIN) / height),
+    };
+    canvas.draw_background();
+
+    let mut y = 0.0;
+    let x = chart::left(should_draw_row_labels, theme);
+
+    for (row, cells) in &layout.rows {
+        y += HOLE_SIZE + HOLE_GAP;
+
+        if should_draw_row_labels {
+            canvas.draw_text(
+                0.0,
+                y + 35.0,
+                theme.label_font_size,
+                row.label(),
+                theme.text,
+            );
+        }
+        canvas.draw_row(x, y, cells, label);
+
+        if *row == Row::Blow {
+            y += HOLE_SIZE + HOLE_GAP;
+            canvas.draw_number_row(x, y, layout.holes);
+        }
+    }
+
+    if theme.caption.visible {
+        canvas.draw_text(
+            0.0,
+            chart::baseline(0.0, theme.caption.font_size),
+            theme.caption.font_size,
+            &caption,
+            theme.caption.color,
+        );
+    }
+    Ok(caption)
+}
+
+/// returns a PDF with one layout chart per page, each bookmarked with its caption.
+/// The DejaVu Sans font is embedded.
+pub fn render(
+    title: &str,
+    pages: &[Page],
+    should_draw_row_labels: bool,
+    theme: &Theme,
+) -> Result<Vec<u8>, Error> {
+    let err = |e: printpdf::Error| Error::Export(e.to_string());
+    let (doc, first_page, first_layer) =
+        PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "layout");
+    let font = include_bytes!("dejavu-sans-font/DejaVuSans.ttf");
+    let font = doc.add_external_font(&font[..]).map_err(err)?;
+
+    for (i, page) in pages.iter().enumerate() {
+        let (page_index, layer_index) = if i == 0 {
+            (first_page, first_layer)
+        } else {
+            doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "layout")
+        };
+        let layer = doc.get_page(page_index).get_layer(layer_index);
+        let caption = draw_page(layer, font.clone(), theme, page, should_draw_row_labels)?;
+        doc.add_bookmark(caption, page_index);
+    }
+    doc.save_to_bytes().map_err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let pages: Vec<Page> = ["C", "G", "Bb"]
+            .iter()
+            .map(|key| Page {
+                tuning_name: "richter",
+                tuning: Tuning::default(),
+                root: Some(ChromaticScale::new(key, None).unwrap()),
+                setup: Setup {
+                    scale: Some("blues"),
+                    position: 2,
+                },
+            })
+            .collect();
+        let pdf = render("richter", &pages, true, &Theme::default()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with(b"%PDF"));
+        assert!(text.contains("/Type/Pages/Count 3"));
+        assert!(text.contains("/Type/Outlines/Count 3"));
+        // the font file is embedded, not just referenced
+        assert!(text.contains("/FontFile2"));
+        assert!(text.contains("DejaVuSans"));
+    }
+}
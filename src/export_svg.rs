@@ -0,0 +1,345 @@
+use crate::chart::{self, HOLE_GAP, HOLE_SIZE};
+use crate::layout::{Cell, Label, Row};
+use crate::theme::{Color, ScaleMark, Theme};
+use crate::*;
+use std::fmt::Write;
+
+const FONT: &str = "DejaVu Sans, Verdana, sans-serif";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rgb(color: Color) -> String {
+    format!("rgb({},{},{})", color.r, color.g, color.b)
+}
+
+fn draw_text(svg: &mut String, x: f32, y: f32, size: f32, text: &str, color: Color) {
+    let _ = writeln!(
+        svg,
+        "  <text x=\"{}\" y=\"{}\" font-size=\"{}\" fill=\"{}\" fill-opacity=\"{:.2}\">{}</text>",
+        x,
+        y,
+        size,
+        rgb(color),
+        color.opacity(),
+        escape(text)
+    );
+}
+
+/// draws a square filled with a color, outlined like every cell of the theme
+fn draw_square(svg: &mut String, theme: &Theme, x: f32, y: f32, fill: Color) {
+    let _ = writeln!(
+        svg,
+        "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{:.2}\" stroke=\"{}\" stroke-opacity=\"{:.2}\" stroke-width=\"{}\"/>",
+        x,
+        y,
+        HOLE_SIZE,
+        HOLE_SIZE,
+        rgb(fill),
+        fill.opacity(),
+        rgb(theme.stroke),
+        theme.stroke.opacity(),
+        theme.stroke_width
+    );
+}
+
+fn draw_line(svg: &mut String, from: (f32, f32), to: (f32, f32), color: Color, width: f32) {
+    let _ = writeln!(
+        svg,
+        "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-opacity=\"{:.2}\" stroke-width=\"{}\"/>",
+        from.0,
+        from.1,
+        to.0,
+        to.1,
+        rgb(color),
+        color.opacity(),
+        width
+    );
+}
+
+fn draw_background(svg: &mut String, x: f32, y: f32, width: f32, height: f32, color: Color) {
+    if color.a == 0 {
+        return;
+    }
+    let _ = writeln!(
+        svg,
+        "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{:.2}\"/>",
+        x,
+        y,
+        width,
+        height,
+        rgb(color),
+        color.opacity()
+    );
+}
+
+fn draw_number_row(svg: &mut String, theme: &Theme, x: f32, y: f32, len: usize) {
+    for i in 1..=len {
+        let rect_x = x + (i - 1) as f32 * (HOLE_SIZE + HOLE_GAP);
+        draw_square(svg, theme, rect_x, y, theme.hole_number.fill);
+        draw_text(
+            svg,
+            rect_x,
+            chart::baseline(y, theme.note_font_size),
+            theme.note_font_size,
+            &i.to_string(),
+            theme.hole_number.text,
+        );
+    }
+}
+
+fn draw_row(svg: &mut String, theme: &Theme, x: f32, y: f32, cells: &[Option<Cell>], label: Label) {
+    for (i, cell) in cells.iter().enumerate() {
+        if let Some(cell) = cell {
+            let style = if cell.is_scale_note {
+                theme.scale_note
+            } else {
+                theme.other_note
+            };
+            let rect_x = x + i as f32 * (HOLE_SIZE + HOLE_GAP);
+            draw_square(svg, theme, rect_x, y, style.fill);
+            let mark = if cell.is_scale_note {
+                theme.scale_mark
+            } else {
+                ScaleMark::None
+            };
+            match mark {
+                ScaleMark::Pattern => {
+                    let color = Color {
+                        a: style.text.a / 3,
+                        ..style.text
+                    };
+                    for (from, to) in chart::hatch(rect_x, y) {
+                        draw_line(svg, from, to, color, 1.5);
+                    }
+                }
+                ScaleMark::Underline => {
+                    let (from, to) = chart::underline(rect_x, y, theme.note_font_size);
+                    draw_line(svg, from, to, style.text, 2.0);
+                }
+                ScaleMark::Bold | ScaleMark::None => {}
+            }
+            let text = escape(&cell.label(label));
+            let _ = writeln!(
+                svg,
+                "  <text x=\"{}\" y=\"{}\" font-size=\"{}\" fill=\"{}\" fill-opacity=\"{:.2}\"{}>{}</text>",
+                rect_x + 2.0,
+                chart::baseline(y, theme.note_font_size),
+                theme.note_font_size,
+                rgb(style.text),
+                style.text.opacity(),
+                if mark == ScaleMark::Bold {
+                    " font-weight=\"bold\""
+                } else {
+                    ""
+                },
+                text
+            );
+        }
+    }
+}
+
+/// returns an SVG image of the note layout, drawn like the PNG export. The export options
+/// set the size the image is shown at.
+pub fn render(
+    tuning_name: &str,
+    tuning: &Tuning,
+    root: &Option<ChromaticScale>,
+    setup: &Setup,
+    should_draw_row_labels: bool,
+    options: &ExportOptions,
+) -> Result<String, Error> {
+    let (layout, label) = match root {
+        Some(root) => (tuning.layout(root, setup), Label::Note),
+        None => (
+            tuning.layout(&ChromaticScale::new("C", None)?, setup),
+            Label::Degree,
+        ),
+    };
+    let caption = get_caption(tuning_name, root, setup);
+    let theme = &options.theme;
+    let (width, height) = chart::natural_size(&layout, &caption, should_draw_row_labels, theme);
+    let (_, (shown_width, shown_height)) = chart::fit(
+        (width, height),
+        options.width,
+        options.height,
+        options.scale,
+    )?;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" preserveAspectRatio=\"xMinYMin meet\" font-family=\"{}\">",
+        shown_width, shown_height, width, height, FONT
+    );
+
+    let mut y = 0.0;
+    let x = chart::left(should_draw_row_labels, theme);
+
+    draw_background(&mut svg, 0.0, 0.0, width, height, theme.background);
+    if should_draw_row_labels {
+        draw_background(
+            &mut svg,
+            0.0,
+            chart::CAPTION_HEIGHT,
+            x - 5.0,
+            height - chart::CAPTION_HEIGHT,
+            theme.label_background,
+        );
+    }
+
+    for (row, cells) in &layout.rows {
+        y += HOLE_SIZE + HOLE_GAP;
+
+        if should_draw_row_labels {
+            draw_text(
+                &mut svg,
+                0.0,
+                y + 35.0,
+                theme.label_font_size,
+                row.label(),
+                theme.text,
+            );
+        }
+        draw_row(&mut svg, theme, x, y, cells, label);
+
+        if *row == Row::Blow {
+            y += HOLE_SIZE + HOLE_GAP;
+            draw_number_row(&mut svg, theme, x, y, layout.holes);
+        }
+    }
+
+    if theme.caption.visible {
+        draw_background(
+            &mut svg,
+            0.0,
+            0.0,
+            width,
+            chart::CAPTION_HEIGHT,
+            theme.caption.background,
+        );
+        draw_text(
+            &mut svg,
+            0.0,
+            chart::baseline(0.0, theme.caption.font_size),
+            theme.caption.font_size,
+            &caption,
+            theme.caption.color,
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let setup = Setup {
+            scale: Some("blues"),
+            position: 2,
+        };
+        let root = Some(ChromaticScale::new("C", None).unwrap());
+        let options = ExportOptions::default();
+        let svg = render("richter", &Tuning::default(), &root, &setup, true, &options).unwrap();
+        assert!(svg.starts_with(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1024\" height=\"600\" viewBox=\"0 0 1024 600\""
+        ));
+        assert!(svg.contains(">C richter harmonica, blues scale, 2nd position</text>\n</svg>\n"));
+        assert!(svg.contains(">bends 1 1/2 step</text>"));
+        assert!(svg.contains(">10</text>"));
+        // every cell of the layout and every hole number is drawn
+        let cells = Tuning::default()
+            .layout(root.as_ref().unwrap(), &setup)
+            .cells()
+            .count();
+        assert_eq!(svg.matches("stroke=\"rgb(0,0,0)\"").count(), cells + 10);
+
+        let svg = render(
+            "richter",
+            &Tuning::default(),
+            &None,
+            &setup,
+            false,
+            &options,
+        )
+        .unwrap();
+        assert!(svg.contains(">richter harmonica, blues scale, 2nd position</text>"));
+        assert!(svg.contains(">b7</text>"));
+        assert!(!svg.contains(">overblows</text>"));
+    }
+
+    #[test]
+    fn test_render_size() {
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        let tuning = read_tuning_from_hashmap_or_file("lucky 13 diminished").unwrap();
+        let root = Some(ChromaticScale::new("C", None).unwrap());
+        let options = ExportOptions {
+            width: Some(512),
+            ..ExportOptions::default()
+        };
+        let svg = render(
+            "lucky 13 diminished",
+            &tuning,
+            &root,
+            &setup,
+            true,
+            &options,
+        )
+        .unwrap();
+        // the 13th hole is drawn inside the image
+        let x = 190.0 + 12.0 * 54.0;
+        assert!(svg.contains(&format!(
+            "<text x=\"{}\" y=\"300\" font-size=\"30\" fill=\"rgb(0,0,0)\" fill-opacity=\"1.00\">13</text>",
+            x
+        )));
+        let view_box = svg.split("viewBox=\"0 0 ").nth(1).unwrap();
+        let view_width: f32 = view_box.split(' ').next().unwrap().parse().unwrap();
+        assert!(view_width >= x + 54.0);
+        assert!(svg.contains(" width=\"512\" "));
+    }
+
+    #[test]
+    fn test_render_theme() {
+        let setup = Setup {
+            scale: Some("blues"),
+            position: 2,
+        };
+        let root = Some(ChromaticScale::new("C", None).unwrap());
+        let mut theme = Theme::dark();
+        theme.caption.visible = false;
+        let options = ExportOptions {
+            theme,
+            ..ExportOptions::default()
+        };
+        let svg = render("richter", &Tuning::default(), &root, &setup, true, &options).unwrap();
+        assert!(svg.contains(
+            "<rect x=\"0\" y=\"0\" width=\"1024\" height=\"600\" fill=\"rgb(32,32,36)\""
+        ));
+        assert!(svg
+            .contains("fill=\"rgb(46,160,67)\" fill-opacity=\"1.00\" stroke=\"rgb(120,120,120)\""));
+        assert!(!svg.contains("harmonica"));
+
+        let options = ExportOptions {
+            theme: Theme::colorblind(),
+            ..ExportOptions::default()
+        };
+        let svg = render("richter", &Tuning::default(), &root, &setup, true, &options).unwrap();
+        let scale_notes = Tuning::default()
+            .layout(root.as_ref().unwrap(), &setup)
+            .cells()
+            .filter(|cell| cell.is_scale_note)
+            .count();
+        assert_eq!(svg.matches("<line ").count(), scale_notes * 9);
+    }
+}
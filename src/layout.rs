@@ -0,0 +1,306 @@
+use crate::pitch::Pitch;
+use crate::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// direction of breath needed to play a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Breath {
+    Blow,
+    Draw,
+}
+
+/// how a note is played on its hole
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case", tag = "type", content = "semitones")
+)]
+pub enum Technique {
+    /// unbent blow or draw note
+    Plain,
+    /// draw bend, lowered by the given number of semitones
+    Bend(u8),
+    /// blow bend, lowered by the given number of semitones
+    BlowBend(u8),
+    Overblow,
+    Overdraw,
+}
+
+/// which text to show for a cell
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Label {
+    Note,
+    Degree,
+    /// scientific pitch name, e.g. "C4"
+    Pitch,
+    /// MIDI note number
+    Midi,
+    /// frequency in Hz, for the given A4 reference
+    Frequency(f64),
+}
+
+/// rows of the note layout, from top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Row {
+    Overblows,
+    BlowBendsFull,
+    BlowBendsHalf,
+    Blow,
+    Draw,
+    BendsHalf,
+    BendsFull,
+    BendsOneAndHalf,
+    Overdraws,
+}
+
+impl Row {
+    pub const ALL: [Row; 9] = [
+        Row::Overblows,
+        Row::BlowBendsFull,
+        Row::BlowBendsHalf,
+        Row::Blow,
+        Row::Draw,
+        Row::BendsHalf,
+        Row::BendsFull,
+        Row::BendsOneAndHalf,
+        Row::Overdraws,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Row::Overblows => "overblows",
+            Row::BlowBendsFull => "blow bends full step",
+            Row::BlowBendsHalf => "blow bends half step",
+            Row::Blow => "blow",
+            Row::Draw => "draw",
+            Row::BendsHalf => "bends half step",
+            Row::BendsFull => "bends full step",
+            Row::BendsOneAndHalf => "bends 1 1/2 step",
+            Row::Overdraws => "overdraws",
+        }
+    }
+
+    /// name used for the row in tuning files and JSON output
+    pub fn id(self) -> &'static str {
+        match self {
+            Row::Overblows => "overblows",
+            Row::BlowBendsFull => "blow_bends_full",
+            Row::BlowBendsHalf => "blow_bends_half",
+            Row::Blow => "blow",
+            Row::Draw => "draw",
+            Row::BendsHalf => "bends_half",
+            Row::BendsFull => "bends_full",
+            Row::BendsOneAndHalf => "bends_one_and_half",
+            Row::Overdraws => "overdraws",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Row> {
+        Row::ALL.iter().copied().find(|row| row.id() == id)
+    }
+
+    /// returns the row holding notes played with a breath direction and technique
+    pub fn find(breath: Breath, technique: Technique) -> Option<Row> {
+        Row::ALL
+            .iter()
+            .copied()
+            .find(|row| row.breath() == breath && row.technique() == technique)
+    }
+
+    /// returns the row of unbent notes for a breath direction
+    pub fn from_breath(breath: Breath) -> Row {
+        match breath {
+            Breath::Blow => Row::Blow,
+            Breath::Draw => Row::Draw,
+        }
+    }
+
+    pub fn breath(self) -> Breath {
+        match self {
+            Row::Overblows | Row::BlowBendsFull | Row::BlowBendsHalf | Row::Blow => Breath::Blow,
+            _ => Breath::Draw,
+        }
+    }
+
+    pub fn technique(self) -> Technique {
+        match self {
+            Row::Overblows => Technique::Overblow,
+            Row::BlowBendsFull => Technique::BlowBend(2),
+            Row::BlowBendsHalf => Technique::BlowBend(1),
+            Row::Blow | Row::Draw => Technique::Plain,
+            Row::BendsHalf => Technique::Bend(1),
+            Row::BendsFull => Technique::Bend(2),
+            Row::BendsOneAndHalf => Technique::Bend(3),
+            Row::Overdraws => Technique::Overdraw,
+        }
+    }
+}
+
+/// a single playable note of a tuning
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Cell {
+    /// hole number, starting at 1
+    pub hole: usize,
+    pub breath: Breath,
+    pub technique: Technique,
+    /// pitch class, 0 is C
+    pub pitch_class: usize,
+    /// absolute pitch for the register of the harp's key
+    pub pitch: Pitch,
+    pub note: &'static str,
+    pub degree: &'static str,
+    pub is_scale_note: bool,
+}
+
+impl Cell {
+    /// returns the scientific pitch name, e.g. "C4"
+    pub fn pitch_name(&self) -> String {
+        format!("{}{}", self.note, self.pitch.octave())
+    }
+
+    pub fn label(&self, label: Label) -> String {
+        match label {
+            Label::Note => self.note.to_string(),
+            Label::Degree => self.degree.to_string(),
+            Label::Pitch => self.pitch_name(),
+            Label::Midi => self.pitch.midi().to_string(),
+            Label::Frequency(a4) => format!("{:.1}", self.pitch.frequency(a4)),
+        }
+    }
+}
+
+/// the note layout of a tuning in a key, one cell per hole and technique
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Layout {
+    pub holes: usize,
+    pub rows: Vec<(Row, Vec<Option<Cell>>)>,
+}
+
+impl Layout {
+    pub fn new(tuning: &Tuning, root: &ChromaticScale, setup: &Setup) -> Layout {
+        let root_pitch_class = root.root_pitch_class();
+        let base = Pitch::key_base(root_pitch_class);
+        let rows = Row::ALL
+            .iter()
+            .map(|&row| {
+                let cells = tuning
+                    .row(row)
+                    .iter()
+                    .enumerate()
+                    .map(|(i, index)| {
+                        index.map(|index| {
+                            let degree = to_scale_degree(index, setup.position);
+                            Cell {
+                                hole: i + 1,
+                                breath: row.breath(),
+                                technique: row.technique(),
+                                pitch_class: (root_pitch_class + index) % 12,
+                                pitch: Pitch(base.midi() + index as i32),
+                                note: root.0[index % 12],
+                                degree,
+                                is_scale_note: setup
+                                    .scale
+                                    .is_some_and(|scale| scales::is_scale_note(degree, scale)),
+                            }
+                        })
+                    })
+                    .collect();
+                (row, cells)
+            })
+            .collect();
+
+        Layout {
+            holes: tuning.blow.len(),
+            rows,
+        }
+    }
+
+    /// returns the lowest and highest pitch of the layout
+    pub fn range(&self) -> Option<(Pitch, Pitch)> {
+        let min = self.cells().map(|c| c.pitch).min()?;
+        let max = self.cells().map(|c| c.pitch).max()?;
+        Some((min, max))
+    }
+
+    pub fn row(&self, row: Row) -> &[Option<Cell>] {
+        let (_, cells) = self.rows.iter().find(|(r, _)| *r == row).unwrap();
+        cells
+    }
+
+    /// iterates over all playable notes, row by row
+    pub fn cells(&self) -> impl Iterator<Item = &Cell> {
+        self.rows
+            .iter()
+            .flat_map(|(_, cells)| cells.iter().flatten())
+    }
+}
+
+/// a layout together with the choices it was generated from, as written by
+/// `harptool --format json`. Fields are only ever added to this document.
+#[cfg(feature = "serde")]
+#[derive(Debug, Serialize)]
+pub struct LayoutDocument<'a> {
+    pub version: u32,
+    pub tuning: &'a str,
+    pub key: &'static str,
+    pub position: usize,
+    pub scale: Option<&'a str>,
+    pub holes: usize,
+    pub cells: Vec<CellDocument<'a>>,
+}
+
+/// a cell of a `LayoutDocument`, with the row it is shown in
+#[cfg(feature = "serde")]
+#[derive(Debug, Serialize)]
+pub struct CellDocument<'a> {
+    pub row: Row,
+    #[serde(flatten)]
+    pub cell: &'a Cell,
+    pub pitch_name: String,
+}
+
+#[cfg(feature = "serde")]
+impl<'a> LayoutDocument<'a> {
+    pub fn new(
+        tuning: &'a str,
+        layout: &'a Layout,
+        root: &ChromaticScale,
+        setup: &Setup<'a>,
+    ) -> Self {
+        let cells = layout
+            .rows
+            .iter()
+            .flat_map(|(row, cells)| {
+                cells.iter().flatten().map(move |cell| CellDocument {
+                    row: *row,
+                    cell,
+                    pitch_name: cell.pitch_name(),
+                })
+            })
+            .collect();
+
+        LayoutDocument {
+            version: 1,
+            tuning,
+            key: root.0[0],
+            position: setup.position,
+            scale: setup.scale,
+            holes: layout.holes,
+            cells,
+        }
+    }
+}
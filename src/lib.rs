@@ -1,26 +1,94 @@
+use crate::layout::{Label, Layout, Row, Technique};
+use crate::melody::Costs;
 use crate::scales::get_scales;
+use crate::tab::Dialect;
+use crate::theme::Theme;
+use crate::tuning_file::TuningFile;
 use crate::tunings::get_tunings;
-use colored::*;
 use itertools::Itertools;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::ffi::OsStr;
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
+pub use crate::error::Error;
+
+mod chart;
+mod error;
 #[cfg(feature = "export")]
 mod export_layout;
-
+#[cfg(feature = "pdf")]
+mod export_pdf;
+mod export_svg;
+#[cfg(feature = "serde")]
+mod serde_support;
+
+pub mod abc;
+pub mod config;
+pub mod layout;
+pub mod lilypond;
+pub mod melody;
+#[cfg(feature = "midi")]
+pub mod midi;
+#[cfg(feature = "musicxml")]
+pub mod musicxml;
+pub mod names;
+pub mod pitch;
 pub mod scales;
+pub mod tab;
+pub mod theme;
+pub mod transpose;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod tuning_file;
 pub mod tunings;
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Setup<'a> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub scale: Option<&'a str>,
     pub position: usize,
 }
 
-#[derive(Debug)]
+/// owned version of `Setup`, e.g. for storing or deserializing without a borrowed input
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedSetup {
+    pub scale: Option<String>,
+    pub position: usize,
+}
+
+impl OwnedSetup {
+    pub fn as_setup(&self) -> Setup<'_> {
+        Setup {
+            scale: self.scale.as_deref(),
+            position: self.position,
+        }
+    }
+}
+
+impl From<&Setup<'_>> for OwnedSetup {
+    fn from(setup: &Setup) -> OwnedSetup {
+        OwnedSetup {
+            scale: setup.scale.map(String::from),
+            position: setup.position,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "Vec<String>")
+)]
 pub struct ChromaticScale(pub [&'static str; 12]);
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Tuning {
     pub blow: Vec<Option<usize>>,
     pub draw: Vec<Option<usize>>,
@@ -37,30 +105,42 @@ impl Default for Tuning {
     #[rustfmt::skip]
     fn default() -> Tuning {
         Tuning {
-            blow: vec![Some(0), Some(4), Some(7), Some(0), Some(4), Some(7), Some(0), Some(4), Some(7), Some(0)],
-            draw: vec![Some(2), Some(7), Some(11), Some(2), Some(5), Some(9), Some(11), Some(2), Some(5), Some(9)],
-            bends_half: vec![Some(1), Some(6), Some(10), Some(1), None, Some(8), None, None, None, None],
+            blow: vec![Some(0), Some(4), Some(7), Some(12), Some(16), Some(19), Some(24), Some(28), Some(31), Some(36)],
+            draw: vec![Some(2), Some(7), Some(11), Some(14), Some(17), Some(21), Some(23), Some(26), Some(29), Some(33)],
+            bends_half: vec![Some(1), Some(6), Some(10), Some(13), None, Some(20), None, None, None, None],
             bends_full: vec![None, Some(5), Some(9), None, None, None, None, None, None, None],
             bends_one_and_half: vec![None, None, Some(8), None, None, None, None, None, None, None],
-            blow_bends_half: vec![None, None, None, None, None, None, None, Some(3), Some(6), Some(11)],
-            blow_bends_full: vec![None, None, None, None, None, None, None, None, None, Some(10)],
-            overblows: vec![Some(3), None, None, Some(3), Some(6), Some(10), None, None, None, None],
-            overdraws: vec![None, None, None, None, None, None, Some(1), None, Some(8), Some(1)],
+            blow_bends_half: vec![None, None, None, None, None, None, None, Some(27), Some(30), Some(35)],
+            blow_bends_full: vec![None, None, None, None, None, None, None, None, None, Some(34)],
+            overblows: vec![Some(3), None, None, Some(15), Some(18), Some(22), None, None, None, None],
+            overdraws: vec![None, None, None, None, None, None, Some(25), None, Some(32), Some(37)],
         }
     }
 }
 
-impl From<&str> for Tuning {
-    fn from(s: &str) -> Tuning {
-        let (top, bottom) = str_to_rows(s);
-        Tuning::new(top, bottom)
+impl TryFrom<&str> for Tuning {
+    type Error = Error;
+
+    /// parses a tuning file, see `tuning_file`
+    fn try_from(s: &str) -> Result<Tuning, Error> {
+        TuningFile::parse(s)?.to_tuning()
     }
 }
 
 impl Tuning {
-    pub fn new(top_notes: Vec<usize>, bottom_notes: Vec<usize>) -> Tuning {
-        let blow: Vec<Option<usize>> = top_notes.iter().map(|x| Some(*x)).collect();
-        let draw: Vec<Option<usize>> = bottom_notes.iter().map(|x| Some(*x)).collect();
+    /// builds a tuning from the pitch classes of the blow and draw reeds, relative to
+    /// the key. Notes are stored as semitones above the root of the key, keeping
+    /// their octave. Hole 1 blow is placed less than an octave above the root.
+    /// Returns an error if the holes don't rise so their notes can be put in order.
+    pub fn new(top_notes: Vec<usize>, bottom_notes: Vec<usize>) -> Result<Tuning, Error> {
+        // work relative to hole 1 blow, which notes_in_order expects to be the lowest note
+        let offset = top_notes.first().map_or(0, |x| x % 12);
+        let top_notes: Vec<usize> = top_notes.iter().map(|x| (x + 12 - offset) % 12).collect();
+        let bottom_notes: Vec<usize> = bottom_notes
+            .iter()
+            .map(|x| (x + 12 - offset) % 12)
+            .collect();
+
         let len = top_notes.len();
         let mut bends_half: Vec<Option<usize>> = vec![None; len];
         let mut bends_full: Vec<Option<usize>> = vec![None; len];
@@ -72,34 +152,33 @@ impl Tuning {
 
         let top_notes = adjust_octaves(&top_notes);
         let bottom_notes = adjust_octaves(&bottom_notes);
-        let (_, duplicates) = notes_in_order(&top_notes, &bottom_notes);
+        let (_, duplicates) = notes_in_order(&top_notes, &bottom_notes)?;
+        let blow: Vec<Option<usize>> = top_notes.iter().map(|x| Some(*x)).collect();
+        let draw: Vec<Option<usize>> = bottom_notes.iter().map(|x| Some(*x)).collect();
 
         for (i, (top, bottom)) in top_notes.iter().zip(bottom_notes).enumerate() {
             let top = *top;
 
             if bottom > top {
-                let _ = overblows.get_mut(i).unwrap().insert((bottom + 1) % 12);
+                let _ = overblows.get_mut(i).unwrap().insert(bottom + 1);
 
                 if bottom - top >= 4 {
-                    let _ = bends_one_and_half
-                        .get_mut(i)
-                        .unwrap()
-                        .insert((bottom - 3) % 12);
+                    let _ = bends_one_and_half.get_mut(i).unwrap().insert(bottom - 3);
                 }
                 if bottom - top >= 3 {
-                    let _ = bends_full.get_mut(i).unwrap().insert((bottom - 2) % 12);
+                    let _ = bends_full.get_mut(i).unwrap().insert(bottom - 2);
                 }
                 if bottom - top >= 2 {
-                    let _ = bends_half.get_mut(i).unwrap().insert((bottom - 1) % 12);
+                    let _ = bends_half.get_mut(i).unwrap().insert(bottom - 1);
                 }
             } else {
-                let _ = overdraws.get_mut(i).unwrap().insert((top + 1) % 12);
+                let _ = overdraws.get_mut(i).unwrap().insert(top + 1);
 
                 if top - bottom >= 3 {
-                    let _ = blow_bends_full.get_mut(i).unwrap().insert((top - 2) % 12);
+                    let _ = blow_bends_full.get_mut(i).unwrap().insert(top - 2);
                 }
                 if top - bottom >= 2 {
-                    let _ = blow_bends_half.get_mut(i).unwrap().insert((top - 1) % 12);
+                    let _ = blow_bends_half.get_mut(i).unwrap().insert(top - 1);
                 }
             }
         }
@@ -122,27 +201,56 @@ impl Tuning {
             }
         }
 
-        Tuning {
-            blow,
-            draw,
-            bends_half,
-            bends_full,
-            bends_one_and_half,
-            blow_bends_half,
-            blow_bends_full,
-            overblows,
-            overdraws,
+        let shift = |row: Vec<Option<usize>>| -> Vec<Option<usize>> {
+            row.into_iter().map(|x| x.map(|x| x + offset)).collect()
+        };
+        Ok(Tuning {
+            blow: shift(blow),
+            draw: shift(draw),
+            bends_half: shift(bends_half),
+            bends_full: shift(bends_full),
+            bends_one_and_half: shift(bends_one_and_half),
+            blow_bends_half: shift(blow_bends_half),
+            blow_bends_full: shift(blow_bends_full),
+            overblows: shift(overblows),
+            overdraws: shift(overdraws),
+        })
+    }
+
+    /// returns the notes of a row of the layout
+    pub fn row(&self, row: Row) -> &[Option<usize>] {
+        match row {
+            Row::Overblows => &self.overblows,
+            Row::BlowBendsFull => &self.blow_bends_full,
+            Row::BlowBendsHalf => &self.blow_bends_half,
+            Row::Blow => &self.blow,
+            Row::Draw => &self.draw,
+            Row::BendsHalf => &self.bends_half,
+            Row::BendsFull => &self.bends_full,
+            Row::BendsOneAndHalf => &self.bends_one_and_half,
+            Row::Overdraws => &self.overdraws,
         }
     }
 
-    fn print_row(row: &[Option<usize>], root: Option<&ChromaticScale>, setup: &Setup) {
-        if let Some(root) = root {
-            Tuning::print_row_notes(row, root, setup);
-        } else {
-            Tuning::print_row_degrees(row, setup);
+    pub(crate) fn row_mut(&mut self, row: Row) -> &mut Vec<Option<usize>> {
+        match row {
+            Row::Overblows => &mut self.overblows,
+            Row::BlowBendsFull => &mut self.blow_bends_full,
+            Row::BlowBendsHalf => &mut self.blow_bends_half,
+            Row::Blow => &mut self.blow,
+            Row::Draw => &mut self.draw,
+            Row::BendsHalf => &mut self.bends_half,
+            Row::BendsFull => &mut self.bends_full,
+            Row::BendsOneAndHalf => &mut self.bends_one_and_half,
+            Row::Overdraws => &mut self.overdraws,
         }
     }
 
+    /// returns the layout with note names and scale degrees for every cell
+    pub fn layout(&self, root: &ChromaticScale, setup: &Setup) -> Layout {
+        Layout::new(self, root, setup)
+    }
+
     /// returns Vec< Option<(scale_degree, is_scale_note)> >
     pub fn get_row_degrees(
         row: &[Option<usize>],
@@ -165,69 +273,151 @@ impl Tuning {
         res
     }
 
-    fn print_row_degrees(row: &[Option<usize>], setup: &Setup) {
-        for x in row {
-            let n = match x {
-                None => " ",
-                Some(x) => to_scale_degree(*x, setup.position),
-            };
-            Tuning::print_colorized(setup.scale, n, n);
+    /// writes a cell padded to `width`, marking scale notes. Brackets widen every cell
+    /// by two characters.
+    fn write_cell<W: Write>(
+        out: &mut W,
+        note: &str,
+        is_scale_note: bool,
+        highlight: Highlight,
+        width: usize,
+    ) -> io::Result<()> {
+        let text = match (highlight.mark, is_scale_note) {
+            (Mark::Brackets, true) => format!("[{}]", note),
+            (Mark::Brackets, false) => format!(" {} ", note),
+            _ => note.to_string(),
+        };
+        // pad outside of the escape codes, so that underlines end with the note
+        let padding = " ".repeat(highlight.cell_width(width) - text.chars().count() + 1);
+        if !(highlight.ansi && is_scale_note) {
+            return write!(out, "{}{}", text, padding);
         }
-        println!();
+        let style = match (highlight.mark, highlight.palette) {
+            (Mark::Color, Palette::Default) => GREEN,
+            (Mark::Color, Palette::Colorblind) => ORANGE,
+            (Mark::Bold, _) => BOLD,
+            (Mark::Underline, _) => UNDERLINE,
+            (Mark::Brackets, _) => return write!(out, "{}{}", text, padding),
+        };
+        write!(out, "{}{}", styled(&text, style), padding)
     }
 
-    fn print_colorized(scale: Option<&str>, degree: &str, note: &str) {
-        // TODO remove duplication
-        if let Some(scale) = scale {
-            if scales::is_scale_note(degree, scale) {
-                print!("{:width$} ", note.green(), width = 3);
-            } else {
-                print!("{:width$} ", note, width = 3);
-            }
+    fn write_number_row<W: Write>(
+        &self,
+        out: &mut W,
+        highlight: Highlight,
+        width: usize,
+    ) -> io::Result<()> {
+        let indent = if highlight.mark == Mark::Brackets {
+            " "
         } else {
-            print!("{:width$} ", note, width = 3);
+            ""
+        };
+        let numbers = (1..=self.blow.len())
+            .map(|i| {
+                let number = format!("{}{}", indent, i);
+                format!("{:<width$} ", number, width = highlight.cell_width(width))
+            })
+            .collect::<String>();
+        let numbers = numbers.trim_end();
+        match (highlight.ansi, highlight.mark, highlight.palette) {
+            (true, Mark::Color, Palette::Default) => {
+                writeln!(out, "{:width$} {}", "", styled(numbers, BLUE), width = 20)
+            }
+            (true, Mark::Color, Palette::Colorblind) => {
+                writeln!(
+                    out,
+                    "{:width$} {}",
+                    "",
+                    styled(numbers, SKY_BLUE),
+                    width = 20
+                )
+            }
+            _ => writeln!(out, "{:width$} {}", "", numbers, width = 20),
         }
     }
 
-    fn print_number_row(&self) {
-        let mut numbers = String::from("");
-        numbers.push('1');
-        for i in 1..self.blow.len() {
-            if i < 10 {
-                numbers.push_str("   ");
-            } else {
-                numbers.push_str("  ");
-            }
-            let i = i + 1;
-            numbers.push_str(&i.to_string());
+    /// writes the note layout as text, with note names if `root` is given and
+    /// scale degrees otherwise. Scale notes and hole numbers are marked as given by
+    /// `highlight`.
+    pub fn write_layout<W: Write>(
+        &self,
+        out: &mut W,
+        root: Option<&ChromaticScale>,
+        setup: &Setup,
+        highlight: Highlight,
+    ) -> io::Result<()> {
+        match root {
+            Some(root) => self.write_layout_labeled(out, root, setup, Label::Note, highlight),
+            None => self.write_layout_labeled(
+                out,
+                &ChromaticScale::new("C", None).unwrap(),
+                setup,
+                Label::Degree,
+                highlight,
+            ),
         }
-        println!("{:width$} {}", "", numbers.blue(), width = 20);
     }
 
-    fn print_layout(&self, root: Option<&ChromaticScale>, setup: Setup) {
-        print!("{:width$} ", "overblows", width = 20);
-        Tuning::print_row(&self.overblows, root, &setup);
-
-        print!("{:width$} ", "blow bends full step", width = 20);
-        Tuning::print_row(&self.blow_bends_full, root, &setup);
-        print!("{:width$} ", "blow bends half step", width = 20);
-        Tuning::print_row(&self.blow_bends_half, root, &setup);
-        print!("{:width$} ", "blow", width = 20);
-        Tuning::print_row(&self.blow, root, &setup);
-
-        self.print_number_row();
+    /// writes the note layout as text, showing `label` for every cell
+    pub fn write_layout_labeled<W: Write>(
+        &self,
+        out: &mut W,
+        root: &ChromaticScale,
+        setup: &Setup,
+        label: Label,
+        highlight: Highlight,
+    ) -> io::Result<()> {
+        let layout = self.layout(root, setup);
+        // without a scale there is nothing to put in brackets
+        let highlight = match (highlight.mark, setup.scale) {
+            (Mark::Brackets, None) => Highlight {
+                mark: Mark::Bold,
+                ..highlight
+            },
+            _ => highlight,
+        };
+        let width = layout
+            .cells()
+            .map(|cell| cell.label(label).len())
+            .max()
+            .unwrap_or(0)
+            .max(3);
+
+        for (row, cells) in &layout.rows {
+            write!(out, "{:width$} ", row.label(), width = 20)?;
+            for cell in cells {
+                match cell {
+                    Some(cell) => Tuning::write_cell(
+                        out,
+                        &cell.label(label),
+                        cell.is_scale_note,
+                        highlight,
+                        width,
+                    )?,
+                    None => Tuning::write_cell(out, " ", false, highlight, width)?,
+                }
+            }
+            writeln!(out)?;
 
-        print!("{:width$} ", "draw", width = 20);
-        Tuning::print_row(&self.draw, root, &setup);
-        print!("{:width$} ", "bends half step", width = 20);
-        Tuning::print_row(&self.bends_half, root, &setup);
-        print!("{:width$} ", "bends full step", width = 20);
-        Tuning::print_row(&self.bends_full, root, &setup);
-        print!("{:width$} ", "bends 1 1/2 step", width = 20);
-        Tuning::print_row(&self.bends_one_and_half, root, &setup);
+            if *row == Row::Blow {
+                self.write_number_row(out, highlight, width)?;
+            }
+        }
+        Ok(())
+    }
 
-        print!("{:width$} ", "overdraws", width = 20);
-        Tuning::print_row(&self.overdraws, root, &setup);
+    /// returns the note layout as text, see `write_layout`
+    pub fn layout_to_string(
+        &self,
+        root: Option<&ChromaticScale>,
+        setup: &Setup,
+        highlight: Highlight,
+    ) -> String {
+        let mut out = Vec::new();
+        self.write_layout(&mut out, root, setup, highlight)
+            .expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("layout is valid UTF-8")
     }
 
     /// returns Vec< Option<(note_name, is_scale_note)> >
@@ -246,7 +436,7 @@ impl Tuning {
             let is_scale_note;
 
             if let Some(note_index) = *i {
-                note = notes.get(note_index).unwrap();
+                note = notes.get(note_index % 12).unwrap();
                 let degree = to_scale_degree(note_index, setup.position);
                 is_scale_note = if let Some(scale) = setup.scale {
                     scales::is_scale_note(degree, scale)
@@ -260,38 +450,18 @@ impl Tuning {
         }
         res
     }
-
-    fn print_row_notes(indices: &[Option<usize>], root: &ChromaticScale, setup: &Setup) {
-        //                   0     1    2    3     4    5     6     7    8    9    10   11
-        // let notes = vec!["C", "Dd", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
-        let notes = root.0;
-
-        for i in indices {
-            let n = match *i {
-                Some(n) => notes.get(n).unwrap().to_string(),
-                None => String::from(" "),
-            };
-
-            let degree = if let Some(index) = *i {
-                to_scale_degree(index, setup.position)
-            } else {
-                ""
-            };
-            Tuning::print_colorized(setup.scale, degree, &n);
-        }
-        println!();
-    }
 }
 
 impl ChromaticScale {
-    pub fn new(root: &str, use_sharps: Option<bool>) -> ChromaticScale {
+    #[allow(clippy::explicit_counter_loop)]
+    pub fn new(root: &str, use_sharps: Option<bool>) -> Result<ChromaticScale, Error> {
         let sharp;
         if let Some(value) = use_sharps {
             sharp = value;
             if (sharp && ["Bb", "Eb", "Ab", "Db", "Gb", "Cb", "Fb"].contains(&root))
                 || (!sharp && ["F#", "C#", "G#", "D#", "A#", "E#", "B#"].contains(&root))
             {
-                panic!("cannot choose sharp/flat notes if root is sharp/flat");
+                return Err(Error::ConflictingAccidentals(root.to_string()));
             }
         } else {
             sharp = !["Bb", "Eb", "Ab", "Db", "Gb", "F"].contains(&root);
@@ -303,7 +473,7 @@ impl ChromaticScale {
         ]
         .contains(&root)
         {
-            panic!("invalid root note");
+            return Err(Error::UnknownRoot(root.to_string()));
         }
 
         let notes = if sharp {
@@ -323,18 +493,252 @@ impl ChromaticScale {
             pos += 1;
         }
 
-        ChromaticScale(scale)
+        Ok(ChromaticScale(scale))
+    }
+
+    /// returns the pitch class of the root note, 0 is C
+    pub fn root_pitch_class(&self) -> usize {
+        pitch_class(self.0[0]).unwrap()
     }
+
+    /// returns the name of a pitch class (0 is C) spelled with this scale's accidentals
+    pub fn note_name(&self, pitch_class: usize) -> &'static str {
+        self.0[(pitch_class + 12 - self.root_pitch_class()) % 12]
+    }
+}
+
+/// returns the pitch class of a note name, 0 is C
+pub(crate) fn pitch_class(note: &str) -> Option<usize> {
+    let index = match note {
+        "C" | "B#" => 0,
+        "C#" | "Db" => 1,
+        "D" => 2,
+        "D#" | "Eb" => 3,
+        "E" | "Fb" => 4,
+        "F" | "E#" => 5,
+        "F#" | "Gb" => 6,
+        "G" => 7,
+        "G#" | "Ab" => 8,
+        "A" => 9,
+        "A#" | "Bb" => 10,
+        "B" | "Cb" => 11,
+        _ => return None,
+    };
+    Some(index)
 }
 
-pub fn run(tuning: &str, key: &str, sharp: Option<bool>, setup: Setup) {
-    let tuning = read_tuning_from_hashmap_or_file(tuning);
-    let v = ChromaticScale::new(key, sharp);
-    tuning.print_layout(Some(&v), setup);
+/// how scale notes stand out in the text layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    /// colored scale notes and hole numbers
+    Color,
+    /// scale notes in square brackets, e.g. "[C]", which needs no escape codes
+    Brackets,
+    Bold,
+    Underline,
 }
 
-#[allow(unused_variables)]
-/// exports a PNG image of the note layout as "layout.png"
+/// colors of scale notes and hole numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    /// green scale notes and blue hole numbers
+    Default,
+    /// orange scale notes and sky blue hole numbers, which can be told apart with
+    /// red-green colorblindness
+    Colorblind,
+}
+
+// SGR parameters of the ANSI escape codes used by the text layout
+const GREEN: &str = "32";
+const BLUE: &str = "34";
+const ORANGE: &str = "38;2;230;159;0";
+const SKY_BLUE: &str = "38;2;86;180;233";
+const BOLD: &str = "1";
+const UNDERLINE: &str = "4";
+
+/// returns `text` wrapped in the ANSI escape codes of `style`
+fn styled(text: &str, style: &str) -> String {
+    format!("\u{1b}[{}m{}\u{1b}[0m", style, text)
+}
+
+/// how the text layout marks scale notes and hole numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Highlight {
+    pub mark: Mark,
+    pub palette: Palette,
+    /// whether ANSI escape codes may be written. Without them only brackets are shown.
+    pub ansi: bool,
+}
+
+impl Default for Highlight {
+    /// green scale notes and blue hole numbers
+    fn default() -> Highlight {
+        Highlight {
+            mark: Mark::Color,
+            palette: Palette::Default,
+            ansi: true,
+        }
+    }
+}
+
+impl Highlight {
+    /// plain text without marks
+    pub const NONE: Highlight = Highlight {
+        mark: Mark::Color,
+        palette: Palette::Default,
+        ansi: false,
+    };
+
+    /// returns how to mark notes written to stdout. Escape codes are only written to a
+    /// terminal, elsewhere scale notes are put in brackets. If `NO_COLOR` is set, scale
+    /// notes are bold instead of colored.
+    pub fn for_stdout(mark: Mark, palette: Palette) -> Highlight {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let ansi = io::stdout().is_terminal();
+        let mark = match mark {
+            _ if !ansi => Mark::Brackets,
+            Mark::Color if no_color => Mark::Bold,
+            mark => mark,
+        };
+        Highlight {
+            mark,
+            palette,
+            ansi,
+        }
+    }
+
+    /// width of a cell with a note of up to `width` characters
+    pub(crate) fn cell_width(&self, width: usize) -> usize {
+        if self.mark == Mark::Brackets {
+            width + 2
+        } else {
+            width
+        }
+    }
+}
+
+/// prints the note layout of a tuning in a key to stdout
+pub fn run(
+    tuning: &str,
+    key: &str,
+    sharp: Option<bool>,
+    setup: Setup,
+    highlight: Highlight,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning)?;
+    let v = ChromaticScale::new(key, sharp)?;
+    tuning.write_layout(&mut io::stdout().lock(), Some(&v), &setup, highlight)?;
+    Ok(())
+}
+
+/// image format of an exported layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ExportFormat {
+    /// needs the `export` feature
+    Png,
+    Svg,
+    /// needs the `pdf` feature
+    Pdf,
+}
+
+impl Default for ExportFormat {
+    fn default() -> ExportFormat {
+        if cfg!(feature = "export") {
+            ExportFormat::Png
+        } else {
+            ExportFormat::Svg
+        }
+    }
+}
+
+impl ExportFormat {
+    /// returns the format matching the extension of a file name, e.g. "chart.svg"
+    pub fn from_path(path: &str) -> Option<ExportFormat> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?;
+        match extension.to_lowercase().as_str() {
+            "png" => Some(ExportFormat::Png),
+            "svg" => Some(ExportFormat::Svg),
+            "pdf" => Some(ExportFormat::Pdf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// the largest width or height in pixels of exported PNG and SVG images
+pub const MAX_EXPORT_SIZE: u32 = 10_000;
+
+/// the largest scale factor of exported PNG and SVG images, `MAX_EXPORT_SCALE * 96` DPI
+pub const MAX_EXPORT_SCALE: f32 = 100.0;
+
+/// where and how big to export a layout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportOptions<'a> {
+    /// file to write, "-" for stdout. Defaults to "layout.png", "layout.svg" or "layout.pdf".
+    pub path: Option<&'a str>,
+    pub format: ExportFormat,
+    /// width in pixels of PNG and SVG images. The height follows unless it is given too,
+    /// then the chart is scaled to fit inside both.
+    pub width: Option<u32>,
+    /// height in pixels of PNG and SVG images
+    pub height: Option<u32>,
+    /// scale factor of PNG and SVG images if no width or height is given, e.g. 2.0 for
+    /// a 2048 pixel wide chart
+    pub scale: f32,
+    /// colors and font sizes
+    pub theme: Theme,
+}
+
+impl Default for ExportOptions<'_> {
+    fn default() -> Self {
+        ExportOptions {
+            path: None,
+            format: ExportFormat::default(),
+            width: None,
+            height: None,
+            scale: 1.0,
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl ExportOptions<'_> {
+    /// writes an exported file to the path of the options, or to stdout for "-"
+    fn write(&self, contents: &[u8]) -> Result<(), Error> {
+        match self.path {
+            Some("-") => io::stdout().lock().write_all(contents)?,
+            Some(path) => fs::write(path, contents)?,
+            None => fs::write(format!("layout.{}", self.format.extension()), contents)?,
+        }
+        Ok(())
+    }
+}
+
+/// what changes from page to page of a PDF booklet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Booklet {
+    /// every harp key
+    Keys,
+    /// every built-in tuning
+    Tunings,
+    /// every position, e.g. to show where a scale lies in each
+    Positions,
+}
+
+/// exports an image of the note layout as PNG, SVG or PDF. The image grows to fit every
+/// hole of the tuning.
 pub fn export(
     tuning_name: &str,
     key: &str,
@@ -342,84 +746,846 @@ pub fn export(
     setup: &Setup,
     use_degrees: bool,
     label_rows: bool,
-) {
-    let tuning = read_tuning_from_hashmap_or_file(tuning_name);
+    options: &ExportOptions,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning_name)?;
     let root = if use_degrees {
         None
     } else {
-        Some(ChromaticScale::new(key, sharp))
+        Some(ChromaticScale::new(key, sharp)?)
+    };
+
+    match options.format {
+        #[cfg(feature = "export")]
+        ExportFormat::Png => options.write(&export_layout::render(
+            tuning_name,
+            &tuning,
+            &root,
+            setup,
+            label_rows,
+            options,
+        )?),
+        #[cfg(not(feature = "export"))]
+        ExportFormat::Png => Err(Error::Export(String::from(
+            "PNG export needs the export feature",
+        ))),
+        ExportFormat::Svg => options.write(
+            export_svg::render(tuning_name, &tuning, &root, setup, label_rows, options)?.as_bytes(),
+        ),
+        #[cfg(feature = "pdf")]
+        ExportFormat::Pdf => {
+            let page = export_pdf::Page {
+                tuning_name,
+                tuning,
+                root,
+                setup: Setup {
+                    scale: setup.scale,
+                    position: setup.position,
+                },
+            };
+            options.write(&export_pdf::render(
+                tuning_name,
+                &[page],
+                label_rows,
+                &options.theme,
+            )?)
+        }
+        #[cfg(not(feature = "pdf"))]
+        ExportFormat::Pdf => Err(Error::Export(String::from(
+            "PDF export needs the pdf feature",
+        ))),
+    }
+}
+
+/// exports a PDF booklet of layouts, with one page for every key, tuning or position.
+/// The other pages keep the given tuning, key and position.
+#[cfg(feature = "pdf")]
+#[allow(clippy::too_many_arguments)]
+pub fn export_booklet(
+    tuning_name: &str,
+    key: &str,
+    sharp: Option<bool>,
+    setup: &Setup,
+    use_degrees: bool,
+    label_rows: bool,
+    booklet: Booklet,
+    options: &ExportOptions,
+) -> Result<(), Error> {
+    if options.format != ExportFormat::Pdf {
+        return Err(Error::Export(String::from(
+            "booklets can only be exported as PDF",
+        )));
+    }
+    let root = |key: &str| -> Result<Option<ChromaticScale>, Error> {
+        if use_degrees {
+            Ok(None)
+        } else {
+            // keep the chosen accidentals where the key allows it
+            ChromaticScale::new(key, sharp)
+                .or_else(|_| ChromaticScale::new(key, None))
+                .map(Some)
+        }
+    };
+    let page = |tuning_name, key, position| -> Result<export_pdf::Page, Error> {
+        Ok(export_pdf::Page {
+            tuning_name,
+            tuning: read_tuning_from_hashmap_or_file(tuning_name)?,
+            root: root(key)?,
+            setup: Setup {
+                scale: setup.scale,
+                position,
+            },
+        })
+    };
+    // check the key once, rather than silently replacing it on every page
+    ChromaticScale::new(key, sharp)?;
+
+    let tunings = get_tunings();
+    let pages = match booklet {
+        Booklet::Keys => melody::HARP_KEYS
+            .iter()
+            .map(|key| page(tuning_name, key, setup.position))
+            .collect::<Result<Vec<_>, Error>>()?,
+        Booklet::Tunings => tunings
+            .keys()
+            .sorted()
+            .map(|tuning_name| page(tuning_name, key, setup.position))
+            .collect::<Result<Vec<_>, Error>>()?,
+        Booklet::Positions => (1..=12)
+            .map(|position| page(tuning_name, key, position))
+            .collect::<Result<Vec<_>, Error>>()?,
+    };
+    options.write(&export_pdf::render(
+        tuning_name,
+        &pages,
+        label_rows,
+        &options.theme,
+    )?)
+}
+
+/// prints the layout of a tuning in a key to stdout, showing `label` for every note
+pub fn run_labeled(
+    tuning: &str,
+    key: &str,
+    sharp: Option<bool>,
+    setup: Setup,
+    label: Label,
+    highlight: Highlight,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning)?;
+    let v = ChromaticScale::new(key, sharp)?;
+    tuning.write_layout_labeled(&mut io::stdout().lock(), &v, &setup, label, highlight)?;
+    Ok(())
+}
+
+/// prints the layout of a tuning in a key to stdout as a JSON document
+#[cfg(feature = "json")]
+pub fn run_json(
+    tuning_name: &str,
+    key: &str,
+    sharp: Option<bool>,
+    setup: Setup,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning_name)?;
+    let root = ChromaticScale::new(key, sharp)?;
+    let layout = tuning.layout(&root, &setup);
+    let document = layout::LayoutDocument::new(tuning_name, &layout, &root, &setup);
+
+    let mut out = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, &document).map_err(io::Error::from)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// prints the layout of a tuning in a key to stdout as a LilyPond chart, showing `label`
+/// for every note
+pub fn run_lilypond(
+    tuning_name: &str,
+    key: &str,
+    sharp: Option<bool>,
+    setup: Setup,
+    label: Label,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning_name)?;
+    let root = ChromaticScale::new(key, sharp)?;
+    let layout = tuning.layout(&root, &setup);
+    let caption = if label == Label::Degree {
+        get_caption(tuning_name, &None, &setup)
+    } else {
+        get_caption(tuning_name, &Some(root), &setup)
+    };
+    print!("{}", lilypond::layout_chart(&layout, &caption, label));
+    Ok(())
+}
+
+/// prints the scale degree layout of a tuning to stdout
+pub fn run_degrees(tuning: &str, setup: Setup, highlight: Highlight) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning)?;
+    tuning.write_layout(&mut io::stdout().lock(), None, &setup, highlight)?;
+    Ok(())
+}
+
+/// prints the tab with the lowest cost for a melody of scientific pitch names to stdout,
+/// and every note that can't be played to stderr
+pub fn run_melody(
+    tuning: &str,
+    key: &str,
+    sharp: Option<bool>,
+    melody: &str,
+    costs: &Costs,
+    dialect: Dialect,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning)?;
+    let root = ChromaticScale::new(key, sharp)?;
+    let melody = melody::parse_melody(melody)?;
+    let setup = Setup {
+        scale: None,
+        position: 1,
+    };
+    let tab = melody::melody_to_tab(&melody, &tuning.layout(&root, &setup), costs);
+    println!("{}", tab.tab(dialect));
+    for (i, pitch, reason) in tab.unplayable() {
+        eprintln!("note {} ({}) is {}", i + 1, pitch.name(&root), reason);
+    }
+    Ok(())
+}
+
+/// prints tab with timing for a voice of a MIDI file, see `run_score`. Without a voice, the
+/// first monophonic voice or the highest notes of the busiest voice are used. Tracks and
+/// channels count from 0.
+#[cfg(feature = "midi")]
+#[allow(clippy::too_many_arguments)]
+pub fn run_midi(
+    tuning: &str,
+    key: Option<&str>,
+    sharp: Option<bool>,
+    path: &str,
+    voice: Option<(usize, u8)>,
+    costs: &Costs,
+    dialect: Dialect,
+    files: &ScoreFiles,
+) -> Result<(), Error> {
+    let (score, voice) = read_midi(path, voice)?;
+    println!("{}", voice);
+    run_score(tuning, key, sharp, &score, costs, dialect, files)
+}
+
+/// reads a voice of a MIDI file as a score, see `run_midi`. Returns the score and the voice
+/// it was read from, e.g. "track 2, channel 1".
+#[cfg(feature = "midi")]
+pub fn read_midi(path: &str, voice: Option<(usize, u8)>) -> Result<(melody::Score, String), Error> {
+    let file = midi::MidiFile::parse(&fs::read(path)?)?;
+    let voice = match voice {
+        Some((track, channel)) => file.voice(track, channel).ok_or_else(|| {
+            Error::Midi(format!(
+                "no notes on track {}, channel {}",
+                track + 1,
+                channel + 1
+            ))
+        })?,
+        None => file
+            .default_voice()
+            .ok_or_else(|| Error::Midi(String::from("no notes found")))?,
+    };
+    let description = format!(
+        "track {}, channel {}{}",
+        voice.track + 1,
+        voice.channel + 1,
+        if voice.monophonic {
+            ""
+        } else {
+            " (highest notes)"
+        },
+    );
+
+    let mut score = melody::Score::new(voice.melody());
+    if let Some(time) = file.time {
+        score.time = time;
+    }
+    Ok((score, description))
+}
+
+/// prints tab with timing for a part of a MusicXML file, see `run_score`
+#[cfg(feature = "musicxml")]
+#[allow(clippy::too_many_arguments)]
+pub fn run_musicxml(
+    tuning: &str,
+    key: Option<&str>,
+    sharp: Option<bool>,
+    path: &str,
+    part: Option<&str>,
+    costs: &Costs,
+    dialect: Dialect,
+    files: &ScoreFiles,
+) -> Result<(), Error> {
+    let score = musicxml::import(&fs::read_to_string(path)?, part)?;
+    if let Some(title) = &score.title {
+        println!("{}", title);
+    }
+    run_score(tuning, key, sharp, &score, costs, dialect, files)
+}
+
+/// prints tab with timing for a tune of an ABC file, see `print_score_tab`. Tunes are chosen
+/// by their X: number, the first tune is used by default. Without a key, the harp is chosen so
+/// that the tune is played in the given position, or the usual position for its mode
+/// (e.g. 2nd position for mixolydian, 3rd for dorian).
+#[allow(clippy::too_many_arguments)]
+pub fn run_abc(
+    tuning: &str,
+    key: Option<&str>,
+    position: Option<usize>,
+    sharp: Option<bool>,
+    path: &str,
+    tune: Option<u32>,
+    costs: &Costs,
+    dialect: Dialect,
+    files: &ScoreFiles,
+) -> Result<(), Error> {
+    let tune = read_abc_tune(path, tune)?;
+    if let Some(title) = &tune.score.title {
+        println!("{}", title);
+    }
+
+    let tuning = read_tuning_from_hashmap_or_file(tuning)?;
+    let melody = tune.score.pitches();
+    if melody.is_empty() {
+        return Err(Error::EmptyMelody);
+    }
+    let tonic = match tune.key {
+        Some(abc_key) => abc_key.tonic,
+        None => melody.last().map_or(0, |pitch| pitch.pitch_class()),
+    };
+    let key = match key {
+        Some(key) => ChromaticScale::new(key, sharp)?,
+        None => {
+            let position = position
+                .or_else(|| tune.key.map(|key| key.mode.position()))
+                .unwrap_or(1);
+            let key = (tonic + 12 - (position - 1) * 7 % 12) % 12;
+            let name = melody::HARP_KEYS[key];
+            ChromaticScale::new(name, sharp).or_else(|_| ChromaticScale::new(name, None))?
+        }
     };
+    let suggestion = melody::fit_key(&melody, &tuning, &key, tonic, costs);
+    print_score_tab(&tune.score, &suggestion, dialect, files)
+}
+
+/// reads a tune of an ABC file by its X: number, or the first tune
+pub fn read_abc_tune(path: &str, tune: Option<u32>) -> Result<abc::Tune, Error> {
+    let mut tunes = abc::parse(&fs::read_to_string(path)?)?.into_iter();
+    match tune {
+        Some(number) => tunes
+            .find(|tune| tune.number == Some(number))
+            .ok_or_else(|| Error::Abc(format!("no tune X:{}", number))),
+        None => tunes
+            .next()
+            .ok_or_else(|| Error::Abc(String::from("no tunes found"))),
+    }
+}
 
-    #[cfg(feature = "export")]
-    export_layout::export_png(tuning_name, &tuning, &root, setup, label_rows);
+/// prints how well a melody fits a harp in every key to stdout, best first. The positions
+/// follow the tonic of the melody, which is its last note unless given.
+pub fn run_analyze(
+    tuning: &str,
+    melody: &[pitch::Pitch],
+    tonic: Option<usize>,
+    costs: &Costs,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning)?;
+    let tonic = match melody.last() {
+        Some(last) => tonic.unwrap_or(last.pitch_class()),
+        None => return Err(Error::EmptyMelody),
+    };
+
+    println!("{} notes", melody.len());
+    println!(
+        "{:<4} {:<8} {:>7} {:>10} {:>5} {:>9} {:>5}",
+        "key", "position", "octaves", "unplayable", "bends", "overblows", "cost"
+    );
+    for suggestion in melody::rank_keys(melody, &tuning, tonic, costs) {
+        let count = |is_technique: fn(Technique) -> bool| {
+            suggestion
+                .tab
+                .notes
+                .iter()
+                .filter(|note| note.tab.is_ok_and(|tab| is_technique(tab.technique)))
+                .count()
+        };
+        println!(
+            "{:<4} {:<8} {:>7} {:>10} {:>5} {:>9} {:>5}",
+            suggestion.key.0[0],
+            to_ordinal(suggestion.position),
+            match suggestion.octaves {
+                0 => String::from("0"),
+                octaves => format!("{:+}", octaves),
+            },
+            suggestion.tab.unplayable().count(),
+            count(|t| matches!(t, Technique::Bend(_) | Technique::BlowBend(_))),
+            count(|t| matches!(t, Technique::Overblow | Technique::Overdraw)),
+            suggestion.tab.cost
+        );
+    }
+    Ok(())
+}
+
+/// files a score is written to together with its tab
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScoreFiles<'a> {
+    /// MusicXML with the tab as lyrics, needs the `musicxml` feature
+    pub musicxml: Option<&'a str>,
+    /// LilyPond with the tab as markup under the notes
+    pub lilypond: Option<&'a str>,
+}
+
+/// prints tab with timing for a score to stdout, and every note that can't be played to
+/// stderr. Without a key, the harp key, position and octave that fit the melody best are
+/// chosen. The score can also be written to files, see `ScoreFiles`.
+pub fn run_score(
+    tuning: &str,
+    key: Option<&str>,
+    sharp: Option<bool>,
+    score: &melody::Score,
+    costs: &Costs,
+    dialect: Dialect,
+    files: &ScoreFiles,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning)?;
+    let melody = score.pitches();
+    if melody.is_empty() {
+        return Err(Error::EmptyMelody);
+    }
+    let suggestion = match key {
+        Some(key) => {
+            let key = ChromaticScale::new(key, sharp)?;
+            let tonic = melody.last().map_or(0, |pitch| pitch.pitch_class());
+            melody::fit_key(&melody, &tuning, &key, tonic, costs)
+        }
+        None => melody::suggest_key(&melody, &tuning, costs).ok_or(Error::EmptyMelody)?,
+    };
+    print_score_tab(score, &suggestion, dialect, files)
+}
+
+/// prints the tab of a score to stdout and the notes that can't be played to stderr,
+/// and writes it to the requested files
+fn print_score_tab(
+    score: &melody::Score,
+    suggestion: &melody::Suggestion,
+    dialect: Dialect,
+    files: &ScoreFiles,
+) -> Result<(), Error> {
+    println!(
+        "{} harp, {} position, {} notes",
+        suggestion.key.0[0],
+        to_ordinal(suggestion.position),
+        score.notes.len()
+    );
+    if suggestion.octaves != 0 {
+        println!("melody moved {} octaves", suggestion.octaves);
+    }
+    println!("{:>8} {:>8}  {:<5} tab", "beat", "length", "note");
+    for (note, tabbed) in score.notes.iter().zip(&suggestion.tab.notes) {
+        let tab = match tabbed.tab {
+            Ok(tab) => tab.format(dialect),
+            Err(_) => String::from("X"),
+        };
+        println!(
+            "{:>8.2} {:>8.2}  {:<5} {}",
+            note.start,
+            note.duration,
+            tabbed.pitch.name(&suggestion.key),
+            tab
+        );
+    }
+    for (i, pitch, reason) in suggestion.tab.unplayable() {
+        eprintln!(
+            "note {} ({}) is {}",
+            i + 1,
+            pitch.name(&suggestion.key),
+            reason
+        );
+    }
+
+    // the files show the melody at the pitches it is played at
+    let mut score = score.clone();
+    for (note, tabbed) in score.notes.iter_mut().zip(&suggestion.tab.notes) {
+        note.pitch = tabbed.pitch;
+    }
+    if let Some(path) = files.lilypond {
+        let ly = lilypond::export(&score, &suggestion.tab, &suggestion.key, dialect)?;
+        fs::write(path, ly)?;
+    }
+    #[cfg(feature = "musicxml")]
+    if let Some(path) = files.musicxml {
+        let xml = musicxml::export(&score, &suggestion.tab, &suggestion.key, dialect)?;
+        fs::write(path, xml)?;
+    }
+    Ok(())
+}
+
+/// prints tab rewritten from one tuning for another in the same key to stdout,
+/// and every note that changed technique or can't be played to stderr
+pub fn run_transpose_tuning(
+    from: &str,
+    to: &str,
+    key: &str,
+    sharp: Option<bool>,
+    tab: &str,
+    costs: &Costs,
+    dialect: Dialect,
+) -> Result<(), Error> {
+    let from = read_tuning_from_hashmap_or_file(from)?;
+    let to = read_tuning_from_hashmap_or_file(to)?;
+    let root = ChromaticScale::new(key, sharp)?;
+    let tab = tab::parse_tab(tab, &from)?;
+    let setup = Setup {
+        scale: None,
+        position: 1,
+    };
+    let notes = transpose::transpose_tuning(
+        &tab,
+        &from.layout(&root, &setup),
+        &to.layout(&root, &setup),
+        costs,
+    );
+
+    print_transposed(&notes, &root, dialect, true);
+    Ok(())
+}
+
+/// prints tab moved from one position to another on the same harp to stdout, and how far it
+/// was moved, its bend and overblow load and every note that can't be played to stderr
+#[allow(clippy::too_many_arguments)]
+pub fn run_transpose_position(
+    tuning: &str,
+    key: &str,
+    sharp: Option<bool>,
+    tab: &str,
+    from: usize,
+    to: usize,
+    costs: &Costs,
+    dialect: Dialect,
+) -> Result<(), Error> {
+    let tuning = read_tuning_from_hashmap_or_file(tuning)?;
+    let root = ChromaticScale::new(key, sharp)?;
+    let tab = tab::parse_tab(tab, &tuning)?;
+    let setup = Setup {
+        scale: None,
+        position: 1,
+    };
+    let res = transpose::transpose_position(&tab, &tuning.layout(&root, &setup), from, to, costs);
+
+    print_transposed(&res.notes, &root, dialect, false);
+    eprintln!("moved {} semitones", res.semitones);
+    let load = transpose::Load::new(&tab);
+    eprintln!(
+        "bends: {} -> {}, overblows and overdraws: {} -> {}",
+        load.bends, res.load.bends, load.overblows, res.load.overblows
+    );
+    Ok(())
 }
 
-pub fn run_degrees(tuning: &str, setup: Setup) {
-    let tuning = read_tuning_from_hashmap_or_file(tuning);
-    tuning.print_layout(None, setup);
+/// prints transposed tab to stdout, and the notes that can't be played or
+/// optionally changed technique to stderr
+fn print_transposed(
+    notes: &[transpose::TransposedNote],
+    root: &ChromaticScale,
+    dialect: Dialect,
+    flag_changes: bool,
+) {
+    let tab = notes
+        .iter()
+        .map(|note| match note.to {
+            Ok(to) => to.format(dialect),
+            Err(_) => String::from("X"),
+        })
+        .join(" ");
+    println!("{}", tab);
+    for (i, note) in notes.iter().enumerate() {
+        let from = note.from.format(dialect);
+        match note.to {
+            Ok(to) if flag_changes && note.technique_changed() => eprintln!(
+                "note {} ({}): {} is now {}",
+                i + 1,
+                note.pitch.name(root),
+                from,
+                to.format(dialect)
+            ),
+            Err(reason) => eprintln!(
+                "note {} ({}): {} is {}",
+                i + 1,
+                note.pitch.name(root),
+                from,
+                reason
+            ),
+            _ => {}
+        }
+    }
 }
 
 // C E G C E G -> 0 4  7 0 4 7
 // D G B D F A -> 2 7 11 2 5 9
-fn convert_to_numbers(top: Vec<&str>, bottom: Vec<&str>) -> (Vec<usize>, Vec<usize>) {
-    let mut top_numbers: Vec<usize> = Vec::new();
-    let mut bottom_numbers: Vec<usize> = Vec::new();
-
+fn convert_to_numbers(
+    top: Vec<&str>,
+    bottom: Vec<&str>,
+) -> Result<(Vec<usize>, Vec<usize>), Error> {
     let flat_notes = ["Bb", "Eb", "Ab", "Db", "Gb"];
     let sharp = !(top.iter().any(|s| flat_notes.contains(s))
         || bottom.iter().any(|s| flat_notes.contains(s)));
 
-    let scale = ChromaticScale::new(top.first().unwrap(), Some(sharp));
+    let first = top
+        .first()
+        .ok_or_else(|| Error::MalformedTuning(String::from("blow row is empty")))?;
+    let scale = ChromaticScale::new(first, Some(sharp))
+        .map_err(|e| Error::MalformedTuning(e.to_string()))?;
+
+    let to_number = |note: &&str| {
+        scale
+            .0
+            .iter()
+            .position(|x| note == x)
+            .ok_or_else(|| Error::MalformedTuning(format!("unknown note \"{}\"", note)))
+    };
+    let top_numbers = top
+        .iter()
+        .map(to_number)
+        .collect::<Result<Vec<usize>, Error>>()?;
+    let bottom_numbers = bottom
+        .iter()
+        .map(to_number)
+        .collect::<Result<Vec<usize>, Error>>()?;
+    Ok((top_numbers, bottom_numbers))
+}
 
-    for note in top.iter() {
-        top_numbers.push(scale.0.iter().position(|x| note == x).unwrap());
-    }
-    for note in bottom.iter() {
-        bottom_numbers.push(scale.0.iter().position(|x| note == x).unwrap());
-    }
-    (top_numbers, bottom_numbers)
+/// returns the caption of a layout chart, e.g. "C richter harmonica, blues scale, 2nd position"
+pub(crate) fn get_caption(
+    tuning_name: &str,
+    root: &Option<ChromaticScale>,
+    setup: &Setup,
+) -> String {
+    let scale_text = if let Some(scale) = &setup.scale {
+        format!(", {} scale", scale)
+    } else {
+        String::from("")
+    };
+
+    let harmonica_type = if let Some(root) = root {
+        format!("{} {} harmonica", root.0[0], tuning_name)
+    } else {
+        format!("{} harmonica", tuning_name)
+    };
+
+    let position = to_ordinal(setup.position);
+    format!("{}{}, {} position", harmonica_type, scale_text, position)
+}
+
+pub(crate) fn to_ordinal(n: usize) -> String {
+    let suffix = match n {
+        1 => "st",
+        2 => "nd",
+        3 => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
 }
 
 fn to_scale_degree(index: usize, position: usize) -> &'static str {
     let index = (index + (position - 1) * 5) % 12;
-    let degrees = [
-        "1", "b2", "2", "b3", "3", "4", "#4", "5", "b6", "6", "b7", "7",
-    ];
-    degrees[index]
+    scales::DEGREES[index]
 }
 
-fn read_tuning_from_hashmap_or_file(tuning_name: &str) -> Tuning {
-    let tunings = get_tunings();
-    let contents = if let Some(notes) = tunings.get(tuning_name) {
-        notes.to_string()
-    } else {
-        let mut filepath = dirs::config_dir().unwrap();
-        filepath.push("harptool");
-        if !filepath.is_dir() {
-            write_example_tuning_layout(filepath.clone());
-        }
-
-        filepath.push(tuning_name);
-
-        match fs::read_to_string(&filepath) {
-            Ok(contents) => contents,
-            Err(_) => {
-                eprintln!(
-                    "tuning \"{}\" not found\nadd it by creating file \"{}\"\n",
-                    tuning_name,
-                    filepath.to_string_lossy()
-                );
-                list_tunings();
-                std::process::exit(-1);
+/// looks up a tuning by name, see `read_tuning_file`
+pub fn read_tuning_from_hashmap_or_file(tuning_name: &str) -> Result<Tuning, Error> {
+    Tuning::try_from(read_tuning_file(tuning_name)?.as_str())
+}
+
+/// returns the directories searched for tuning files, in order: the directories listed
+/// in `HARPTOOL_PATH`, then the harptool config directory. None of them are created.
+pub fn tuning_dirs() -> Vec<PathBuf> {
+    search_path(
+        std::env::var_os("HARPTOOL_PATH").as_deref(),
+        config_tuning_dir(),
+    )
+}
+
+/// returns the directories of a `HARPTOOL_PATH` value followed by the config directory
+fn search_path(harptool_path: Option<&OsStr>, config_dir: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = harptool_path
+        .map(|paths| std::env::split_paths(paths).collect())
+        .unwrap_or_default();
+    dirs.retain(|dir| !dir.as_os_str().is_empty());
+    dirs.extend(config_dir);
+    dirs
+}
+
+/// the harptool config directory, where `harptool init` and `add_tuning` put tunings
+fn config_tuning_dir() -> Option<PathBuf> {
+    let mut path = dirs::config_dir()?;
+    path.push("harptool");
+    Some(path)
+}
+
+/// returns the tuning file for a name, see `find_tuning`
+pub fn read_tuning_file(tuning_name: &str) -> Result<String, Error> {
+    Ok(find_tuning(tuning_name)?.1)
+}
+
+/// returns the name and the tuning file of a tuning. A name with a directory in it, like
+/// "./my-tuning", is read as a path. Other names are looked up in the `tuning_dirs` and
+/// then in the built-in tunings, so a tuning file can replace a built-in tuning of the
+/// same name. Names match in any case and with spaces, `_` or `-` between words, and
+/// tunings are also found by their aliases.
+pub fn find_tuning(tuning_name: &str) -> Result<(String, String), Error> {
+    find_tuning_in(tuning_name, &tuning_dirs())
+}
+
+/// returns the name and the tuning file of a tuning like `find_tuning`, searching `dirs`
+/// instead of the `tuning_dirs`
+pub fn find_tuning_in(tuning_name: &str, dirs: &[PathBuf]) -> Result<(String, String), Error> {
+    if Path::new(tuning_name).components().nth(1).is_some() {
+        return match fs::read_to_string(tuning_name) {
+            Ok(contents) => Ok((tuning_name.to_string(), contents)),
+            Err(_) => Err(Error::UnknownTuning {
+                name: tuning_name.to_string(),
+                path: Some(PathBuf::from(tuning_name)),
+                suggestions: Vec::new(),
+            }),
+        };
+    }
+
+    let mut candidates = Vec::new();
+    for dir in dirs {
+        let path = dir.join(tuning_name);
+        if path.is_file() {
+            return Ok((tuning_name.to_string(), fs::read_to_string(&path)?));
+        }
+        for (name, path) in tuning_files(dir) {
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(_) => continue,
+            };
+            let aliases = TuningFile::parse(&contents)
+                .map(|file| file.aliases)
+                .unwrap_or_default();
+            if names::matches(&name, tuning_name)
+                || aliases
+                    .iter()
+                    .any(|alias| names::matches(alias, tuning_name))
+            {
+                return Ok((name, contents));
             }
+            candidates.push(name);
+            candidates.extend(aliases);
         }
+    }
+
+    let tunings = get_tunings();
+    let aliases = tunings::get_tuning_aliases();
+    let builtin = tunings.iter().find(|(name, _)| {
+        names::matches(name, tuning_name)
+            || aliases
+                .get(*name)
+                .is_some_and(|aliases| aliases.iter().any(|a| names::matches(a, tuning_name)))
+    });
+    if let Some((name, notes)) = builtin {
+        return Ok((name.to_string(), notes.to_string()));
+    }
+
+    candidates.extend(tunings.keys().map(|name| name.to_string()));
+    candidates.extend(
+        aliases
+            .values()
+            .flat_map(|a| a.iter().map(|a| a.to_string())),
+    );
+    Err(Error::UnknownTuning {
+        name: tuning_name.to_string(),
+        path: dirs.last().map(|dir| dir.join(tuning_name)),
+        suggestions: names::suggest(tuning_name, candidates.iter().map(String::as_str)),
+    })
+}
+
+/// returns the names and paths of the tuning files in a directory, sorted by name
+fn tuning_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file() && entry.file_name() != "config.toml")
+            .map(|entry| {
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.path(),
+                )
+            })
+            .sorted()
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// creates the harptool config directory with an example tuning file, unless they exist.
+/// Returns the directory.
+pub fn init() -> Result<PathBuf, Error> {
+    let path = config_tuning_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory found"))?;
+    write_example_tuning_layout(path.clone())?;
+    Ok(path)
+}
+
+/// copies a tuning file into the tuning directory, named by `name`, the `name:` field of
+/// the file or its file name. Returns where it was written. Tuning files, and built-in
+/// tunings that the new file would replace, are only replaced with `force`.
+pub fn add_tuning(path: &str, name: Option<&str>, force: bool) -> Result<PathBuf, Error> {
+    let contents = fs::read_to_string(path)?;
+    let file = TuningFile::parse(&contents)?;
+    file.to_tuning()?;
+
+    let name = match name.map(String::from).or(file.name) {
+        Some(name) => name,
+        None => PathBuf::from(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
     };
-    let (top, bottom) = str_to_rows(&contents);
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err(Error::MalformedTuning(format!(
+            "\"{}\" can't be used as a tuning name",
+            name
+        )));
+    }
+    if !force
+        && get_tunings()
+            .keys()
+            .any(|tuning| names::matches(tuning, &name))
+    {
+        return Err(Error::TuningExists { name, path: None });
+    }
 
-    Tuning::new(top, bottom)
+    let mut target = config_tuning_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory found"))?;
+    fs::create_dir_all(&target)?;
+    target.push(&name);
+    if target.exists() && !force {
+        return Err(Error::TuningExists {
+            name,
+            path: Some(target),
+        });
+    }
+    fs::write(&target, contents)?;
+    Ok(target)
 }
 
+/// prints the tuning files of every tuning directory, then the built-in tunings
 pub fn list_tunings() {
+    for dir in tuning_dirs() {
+        let files = tuning_files(&dir);
+        if !files.is_empty() {
+            println!("tunings in {}:", dir.to_string_lossy());
+            for (name, _) in files {
+                println!("{}", name);
+            }
+            println!();
+        }
+    }
+
     let tunings = get_tunings();
     println!("available tunings:");
     for tuning in tunings.keys().sorted() {
@@ -435,38 +1601,74 @@ pub fn list_scales() {
     }
 }
 
-/// checks if scale exists and exits otherwise
-pub fn validate_scale(scale: &str) {
-    let scales = get_scales();
-    if !scales.contains_key(scale) {
-        eprintln!("scale \"{}\" not found\n", scale);
-        list_scales();
-        std::process::exit(-1);
-    }
+/// returns the notes of a scale played in a position on a harp in `key`
+pub fn scale_notes(degrees: &[&str], key: &ChromaticScale, position: usize) -> Vec<&'static str> {
+    degrees
+        .iter()
+        .filter_map(|degree| scales::semitones(degree))
+        .map(|semitones| key.0[(semitones + (position - 1) * 7) % 12])
+        .collect()
 }
 
-pub fn str_to_notes_in_order(input: &str) -> (Vec<String>, Vec<String>) {
-    let (top, bottom) = str_to_rows(input);
+/// prints the degrees of a scale, and its notes in a position on a harp in `key`
+pub fn run_scale(
+    scale: &str,
+    key: &str,
+    sharp: Option<bool>,
+    position: usize,
+) -> Result<(), Error> {
+    let scale = scales::find_scale(scale)?;
+    let degrees = &get_scales()[scale];
+    let key = ChromaticScale::new(key, sharp)?;
+    println!("{}: {}", scale, degrees.join(" "));
+    println!(
+        "{} harp, {} position: {}",
+        key.0[0],
+        to_ordinal(position),
+        scale_notes(degrees, &key, position).join(" ")
+    );
+    Ok(())
+}
+
+/// checks if scale exists
+pub fn validate_scale(scale: &str) -> Result<(), Error> {
+    scales::find_scale(scale).map(|_| ())
+}
+
+pub fn str_to_notes_in_order(input: &str) -> Result<(Vec<String>, Vec<String>), Error> {
+    let (top, bottom) = str_to_rows(input)?;
     let top = adjust_octaves(&top);
     let bottom = adjust_octaves(&bottom);
     notes_in_order(&top, &bottom)
 }
 
 // "C E G\nD G B" -> [0 4 7], [2 7 11]
-fn str_to_rows(input: &str) -> (Vec<usize>, Vec<usize>) {
-    let contents: Vec<&str> = input.lines().collect();
-    let top: Vec<&str> = contents.first().unwrap().split(' ').collect();
-    let bottom: Vec<&str> = contents.get(1).unwrap().split(' ').collect();
+fn str_to_rows(input: &str) -> Result<(Vec<usize>, Vec<usize>), Error> {
+    let contents: Vec<&str> = input
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    if contents.len() < 2 {
+        return Err(Error::MalformedTuning(String::from(
+            "expected a blow row and a draw row",
+        )));
+    }
+    let top: Vec<&str> = contents[0].split_whitespace().collect();
+    let bottom: Vec<&str> = contents[1].split_whitespace().collect();
 
     convert_to_numbers(top, bottom)
 }
 
-fn write_example_tuning_layout(mut path: PathBuf) {
-    fs::create_dir_all(&path).expect("could not create note layout dir");
+fn write_example_tuning_layout(mut path: PathBuf) -> Result<(), Error> {
+    fs::create_dir_all(&path)?;
     path.push("richter example");
-    let mut f = fs::File::create(&path).unwrap();
-    f.write_all("C E G C E G C E G C\nD G B D F A B D F A\n".as_bytes())
-        .unwrap();
+    if path.exists() {
+        return Ok(());
+    }
+    let mut f = fs::File::create(&path)?;
+    f.write_all("C E G C E G C E G C\nD G B D F A B D F A\n".as_bytes())?;
+    Ok(())
 }
 
 // 0 4 7 0 4 7 -> 0 4 7 12 16 19
@@ -484,7 +1686,14 @@ fn adjust_octaves(row: &[usize]) -> Vec<usize> {
     row
 }
 
-fn notes_in_order(top: &[usize], bottom: &[usize]) -> (Vec<String>, Vec<String>) {
+#[allow(clippy::explicit_counter_loop)]
+fn notes_in_order(top: &[usize], bottom: &[usize]) -> Result<(Vec<String>, Vec<String>), Error> {
+    fn unordered(hole: i32) -> Error {
+        Error::MalformedTuning(format!(
+            "the notes of hole {} can't be put in order with the holes before it",
+            hole
+        ))
+    }
     fn getnote(hole: i32, bends: i32, overblow: bool) -> String {
         let mut hole = hole.to_string();
         if overblow {
@@ -543,7 +1752,7 @@ fn notes_in_order(top: &[usize], bottom: &[usize]) -> (Vec<String>, Vec<String>)
             res.push(note);
             accounted += 1;
         } else {
-            alternative = res.get(lower as usize).unwrap();
+            alternative = res.get(lower as usize).ok_or_else(|| unordered(hole))?;
             duplicated.push(note.clone());
             duplicated.push(alternative.clone());
         }
@@ -556,7 +1765,9 @@ fn notes_in_order(top: &[usize], bottom: &[usize]) -> (Vec<String>, Vec<String>)
                 res.push(note);
                 accounted += 1;
             } else {
-                alternative = res.get((higher - step) as usize).unwrap();
+                alternative = res
+                    .get((higher - step) as usize)
+                    .ok_or_else(|| unordered(hole))?;
                 duplicated.push(note.clone());
                 duplicated.push(alternative.clone());
             }
@@ -568,7 +1779,7 @@ fn notes_in_order(top: &[usize], bottom: &[usize]) -> (Vec<String>, Vec<String>)
             res.push(note);
             accounted += 1;
         } else {
-            alternative = res.get(lower as usize).unwrap();
+            alternative = res.get(lower as usize).ok_or_else(|| unordered(hole))?;
             duplicated.push(note.clone());
             duplicated.push(alternative.clone());
         }
@@ -582,7 +1793,9 @@ fn notes_in_order(top: &[usize], bottom: &[usize]) -> (Vec<String>, Vec<String>)
         // find replacement for overblow/overdraw
         if ob_duplicated {
             note = getnote(lastdirection * (hole - 1), 0, true);
-            alternative = res.get((lasthigher + 1) as usize).unwrap();
+            alternative = res
+                .get((lasthigher + 1) as usize)
+                .ok_or_else(|| unordered(hole))?;
             duplicated.push(note.clone());
             duplicated.push(alternative.clone());
         }
@@ -591,7 +1804,7 @@ fn notes_in_order(top: &[usize], bottom: &[usize]) -> (Vec<String>, Vec<String>)
         lastdirection = direction;
         lasthigher = higher;
     }
-    (res, duplicated)
+    Ok((res, duplicated))
 }
 
 #[cfg(test)]
@@ -602,36 +1815,61 @@ mod tests {
         let tuning = Tuning::new(
             vec![0, 4, 7, 0, 4, 7, 0, 4, 7, 0],
             vec![2, 7, 11, 2, 5, 9, 11, 2, 5, 9],
-        );
+        )
+        .unwrap();
         assert_eq!(tuning, Tuning::default());
     }
 
     #[test]
     fn test_tuning_from_str() {
         let richter = "C E G C E G C E G C\nD G B D F A B D F A\n";
-        let tuning = Tuning::from(richter);
+        let tuning = Tuning::try_from(richter).unwrap();
         assert_eq!(tuning, Tuning::default());
     }
 
+    #[test]
+    fn test_chromatic_scale_errors() {
+        assert!(matches!(
+            ChromaticScale::new("H", None),
+            Err(Error::UnknownRoot(_))
+        ));
+        assert!(matches!(
+            ChromaticScale::new("Bb", Some(true)),
+            Err(Error::ConflictingAccidentals(_))
+        ));
+        assert!(matches!(
+            Tuning::try_from("C E G\nD G X"),
+            Err(Error::MalformedTuning(_))
+        ));
+        assert!(matches!(
+            Tuning::try_from("C E G"),
+            Err(Error::MalformedTuning(_))
+        ));
+        assert!(matches!(
+            Tuning::try_from("C C C\nC C C"),
+            Err(Error::MalformedTuning(_))
+        ));
+    }
+
     #[test]
     fn test_convert_to_numbers() {
         let top = vec!["C", "E", "G", "C", "E", "G", "C", "E", "G", "C"];
         let bottom = vec!["D", "G", "B", "D", "F", "A", "B", "D", "F", "A"];
         let top_numbers = vec![0, 4, 7, 0, 4, 7, 0, 4, 7, 0];
         let bottom_numbers = vec![2, 7, 11, 2, 5, 9, 11, 2, 5, 9];
-        let (res_top, res_bottom) = convert_to_numbers(top, bottom);
+        let (res_top, res_bottom) = convert_to_numbers(top, bottom).unwrap();
         assert_eq!(top_numbers, res_top);
         assert_eq!(bottom_numbers, res_bottom);
 
         let top = vec!["D", "F#", "A", "D", "F#", "A", "D", "F#", "A", "D"];
         let bottom = vec!["E", "A", "C#", "E", "G", "B", "C#", "E", "G", "B"];
-        let (res_top, res_bottom) = convert_to_numbers(top, bottom);
+        let (res_top, res_bottom) = convert_to_numbers(top, bottom).unwrap();
         assert_eq!(top_numbers, res_top);
         assert_eq!(bottom_numbers, res_bottom);
 
         let top = vec!["F", "A", "C", "F", "A", "C", "F", "A", "C", "F"];
         let bottom = vec!["G", "C", "E", "G", "Bb", "D", "E", "G", "Bb", "D"];
-        let (res_top, res_bottom) = convert_to_numbers(top, bottom);
+        let (res_top, res_bottom) = convert_to_numbers(top, bottom).unwrap();
         assert_eq!(top_numbers, res_top);
         assert_eq!(bottom_numbers, res_bottom);
     }
@@ -639,7 +1877,7 @@ mod tests {
     #[test]
     fn test_str_to_rows() {
         let richter = "C E G C E G C E G C\nD G B D F A B D F A\n";
-        let (top, bottom) = str_to_rows(richter);
+        let (top, bottom) = str_to_rows(richter).unwrap();
         let expected_top = vec![0, 4, 7, 0, 4, 7, 0, 4, 7, 0];
         let expected_bottom = vec![2, 7, 11, 2, 5, 9, 11, 2, 5, 9];
         assert_eq!(expected_bottom, bottom);
@@ -673,7 +1911,7 @@ mod tests {
     fn test_notes_in_order() {
         let top = vec![0, 4, 7, 12, 16, 19, 24, 28, 31, 36];
         let bottom = vec![2, 7, 11, 14, 17, 21, 23, 26, 29, 33];
-        let res = notes_in_order(&top, &bottom);
+        let res = notes_in_order(&top, &bottom).unwrap();
         let expected = vec![
             "1", "-1'", "-1", "1o", "2", "-2''", "-2'", "-2", "-3'''", "-3''", "-3'", "-3", "4",
             "-4'", "-4", "4o", "5", "-5", "5o", "6", "-6'", "-6", "6o", "-7", "7", "-7o", "-8",
@@ -685,7 +1923,7 @@ mod tests {
     #[test]
     fn test_str_to_notes_in_order() {
         let richter = "C E G C E G C E G C\nD G B D F A B D F A\n";
-        let (notes, duplicated) = str_to_notes_in_order(richter);
+        let (notes, duplicated) = str_to_notes_in_order(richter).unwrap();
         let expected = vec![
             "1", "-1'", "-1", "1o", "2", "-2''", "-2'", "-2", "-3'''", "-3''", "-3'", "-3", "4",
             "-4'", "-4", "4o", "5", "-5", "5o", "6", "-6'", "-6", "6o", "-7", "7", "-7o", "-8",
@@ -696,7 +1934,7 @@ mod tests {
         assert_eq!(duplicated, expected_duplicated);
 
         let wilde = "C E G C E E G C E A\nD G B D F G B D G C\n";
-        let res = str_to_notes_in_order(wilde);
+        let res = str_to_notes_in_order(wilde).unwrap();
         let expected = vec![
             "1", "-1'", "-1", "1o", "2", "-2''", "-2'", "-2", "-3'''", "-3''", "-3'", "-3", "4",
             "-4'", "-4", "4o", "5", "-5", "-6'", "-6", "-7'''", "-7''", "-7'", "-7", "8", "-8'",
@@ -719,14 +1957,14 @@ mod tests {
         tunings.push("C E G C E G A C E A\nD G B D F A B D G C");
         tunings.push("C E G C D F A C E A\nD G B D E G B D G C");
         for tuning in tunings {
-            let (_, _) = str_to_notes_in_order(tuning);
+            let (_, _) = str_to_notes_in_order(tuning).unwrap();
         }
     }
 
     #[test]
     fn test_get_row_notes() {
         let richter = Tuning::default();
-        let root = ChromaticScale::new("C", None);
+        let root = ChromaticScale::new("C", None).unwrap();
         let setup = Setup {
             scale: Some("major"),
             position: 3,
@@ -764,6 +2002,166 @@ mod tests {
         assert_eq!(res, expected);
     }
 
+    #[test]
+    fn test_layout_to_string() {
+        let richter = Tuning::default();
+        let root = ChromaticScale::new("C", None).unwrap();
+        let setup = Setup {
+            scale: Some("major"),
+            position: 1,
+        };
+        let layout = richter.layout_to_string(Some(&root), &setup, Highlight::NONE);
+        let lines: Vec<&str> = layout.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(
+            lines[3],
+            "blow                 C   E   G   C   E   G   C   E   G   C   "
+        );
+        assert_eq!(
+            lines[4],
+            "                     1   2   3   4   5   6   7   8   9   10"
+        );
+        assert!(!layout.contains('\u{1b}'));
+
+        let degrees = richter.layout_to_string(None, &setup, Highlight::NONE);
+        assert!(degrees
+            .lines()
+            .any(|l| l == "draw                 2   5   7   2   4   6   7   2   4   6   "));
+    }
+
+    #[test]
+    fn test_layout_marks() {
+        let richter = Tuning::default();
+        let root = ChromaticScale::new("C", None).unwrap();
+        let setup = Setup {
+            scale: Some("major pentatonic"),
+            position: 1,
+        };
+        let brackets = Highlight {
+            mark: Mark::Brackets,
+            ..Highlight::NONE
+        };
+        let layout = richter.layout_to_string(Some(&root), &setup, brackets);
+        let lines: Vec<&str> = layout.lines().collect();
+        assert_eq!(
+            lines[3],
+            "blow                 [C]   [E]   [G]   [C]   [E]   [G]   [C]   [E]   [G]   [C]   "
+        );
+        assert_eq!(
+            lines[4],
+            "                      1     2     3     4     5     6     7     8     9     10"
+        );
+        assert!(lines[5].starts_with("draw                 [D]   [G]    B    [D]    F  "));
+
+        let bold = Highlight {
+            mark: Mark::Bold,
+            ..Highlight::default()
+        };
+        let layout = richter.layout_to_string(Some(&root), &setup, bold);
+        assert!(layout.contains("\u{1b}[1mC\u{1b}[0m   "));
+        // hole numbers are only colored with the color mark
+        assert!(layout.contains("\n                     1   2   3"));
+    }
+
+    #[test]
+    fn test_layout_cells() {
+        use crate::layout::{Breath, Cell, Technique};
+        use crate::pitch::Pitch;
+
+        let richter = Tuning::default();
+        let root = ChromaticScale::new("G", None).unwrap();
+        let setup = Setup {
+            scale: Some("blues"),
+            position: 2,
+        };
+        let layout = richter.layout(&root, &setup);
+        assert_eq!(layout.holes, 10);
+        assert_eq!(layout.cells().count(), 39);
+
+        let cell = layout.row(Row::BendsHalf)[3].clone().unwrap();
+        assert_eq!(
+            cell,
+            Cell {
+                hole: 4,
+                breath: Breath::Draw,
+                technique: Technique::Bend(1),
+                pitch_class: 8,
+                pitch: Pitch(68),
+                note: "G#",
+                degree: "#4",
+                is_scale_note: true,
+            }
+        );
+
+        let overdraws: Vec<usize> = layout
+            .cells()
+            .filter(|c| c.technique == Technique::Overdraw)
+            .map(|c| c.hole)
+            .collect();
+        assert_eq!(overdraws, vec![7, 9, 10]);
+
+        assert_eq!(
+            layout.row(Row::Blow)[0].as_ref().unwrap().pitch_name(),
+            "G3"
+        );
+        assert_eq!(layout.range(), Some((Pitch(55), Pitch(55 + 37))));
+    }
+
+    #[test]
+    fn test_pitch() {
+        use crate::pitch::Pitch;
+
+        let c = ChromaticScale::new("C", None).unwrap();
+        assert_eq!(Pitch::key_base(0), Pitch(60));
+        assert_eq!(Pitch::key_base(7), Pitch(55));
+        assert_eq!(Pitch::key_base(6), Pitch(66));
+        assert_eq!(Pitch(60).name(&c), "C4");
+        assert_eq!(Pitch(59).name(&c), "B3");
+        assert_eq!(
+            Pitch(61).name(&ChromaticScale::new("F", None).unwrap()),
+            "Db4"
+        );
+        assert!((Pitch(69).frequency(440.0) - 440.0).abs() < 1e-9);
+        assert!((Pitch(60).frequency(440.0) - 261.6256).abs() < 1e-3);
+        assert!((Pitch(81).frequency(442.0) - 884.0).abs() < 1e-9);
+
+        let richter = Tuning::default();
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        let mut midi = Vec::new();
+        richter
+            .write_layout_labeled(&mut midi, &c, &setup, Label::Midi, Highlight::NONE)
+            .unwrap();
+        let midi = String::from_utf8(midi).unwrap();
+        assert!(midi
+            .lines()
+            .any(|l| l.starts_with("blow                 60  64  67  72  76  79  84  88  91  96")));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_serde_round_trip() {
+        let richter = Tuning::default();
+        let json = serde_json::to_string(&richter).unwrap();
+        assert_eq!(serde_json::from_str::<Tuning>(&json).unwrap(), richter);
+
+        let root = ChromaticScale::new("Bb", None).unwrap();
+        let json = serde_json::to_string(&root).unwrap();
+        assert_eq!(serde_json::from_str::<ChromaticScale>(&json).unwrap(), root);
+        assert!(serde_json::from_str::<ChromaticScale>(r#"["C","C#"]"#).is_err());
+
+        let setup: Setup = serde_json::from_str(r#"{"scale":"blues","position":2}"#).unwrap();
+        let owned = OwnedSetup::from(&setup);
+        let json = serde_json::to_string(&owned).unwrap();
+        assert_eq!(serde_json::from_str::<OwnedSetup>(&json).unwrap(), owned);
+
+        let layout = richter.layout(&root, &owned.as_setup());
+        let json = serde_json::to_string(&layout).unwrap();
+        assert_eq!(serde_json::from_str::<Layout>(&json).unwrap(), layout);
+    }
+
     #[test]
     fn test_get_row_degrees() {
         let richter = Tuning::default();
@@ -786,4 +2184,50 @@ mod tests {
         ];
         assert_eq!(res, expected);
     }
+
+    #[test]
+    fn test_scale_notes() {
+        let blues = &get_scales()["blues"];
+        let c = ChromaticScale::new("C", None).unwrap();
+        assert_eq!(
+            scale_notes(blues, &c, 2),
+            vec!["G", "A#", "C", "C#", "D", "F"]
+        );
+        let a = ChromaticScale::new("A", None).unwrap();
+        assert_eq!(
+            scale_notes(&get_scales()["major"], &a, 1),
+            vec!["A", "B", "C#", "D", "E", "F#", "G#"]
+        );
+    }
+
+    #[test]
+    fn test_find_tuning() {
+        let dir = std::env::temp_dir().join(format!("harptool-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let notes = "C Eb G C Eb G C Eb G C\nD G Bb D F A Bb D F A\n";
+        fs::write(dir.join("minor test"), notes).unwrap();
+        fs::write(dir.join("richter"), notes).unwrap();
+
+        let path = dir.join("minor test");
+        assert_eq!(read_tuning_file(&path.to_string_lossy()).unwrap(), notes);
+        let dirs = [dir.clone()];
+        assert!(find_tuning_in("minor test", &[]).is_err());
+        assert_eq!(find_tuning_in("minor test", &dirs).unwrap().1, notes);
+        assert_eq!(find_tuning_in("Minor_Test", &dirs).unwrap().0, "minor test");
+        // the file replaces the built-in tuning
+        assert_eq!(find_tuning_in("richter", &dirs).unwrap().1, notes);
+        assert_ne!(find_tuning_in("richter", &[]).unwrap().1, notes);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let paths = std::env::join_paths(["/a", "", "/b"]).unwrap();
+        assert_eq!(
+            search_path(Some(&paths), Some(PathBuf::from("/config"))),
+            vec![
+                PathBuf::from("/a"),
+                PathBuf::from("/b"),
+                PathBuf::from("/config")
+            ]
+        );
+        assert!(search_path(None, None).is_empty());
+    }
 }
@@ -0,0 +1,274 @@
+//! Writing scores and layout charts as LilyPond files.
+
+use crate::layout::{Label, Layout, Row};
+use crate::melody::{MelodyTab, Score};
+use crate::pitch::Pitch;
+use crate::tab::Dialect;
+use crate::{ChromaticScale, Error};
+use std::fmt::Write;
+
+const VERSION: &str = "\\version \"2.24.0\"\n";
+
+/// note lengths are rounded to 32nd notes
+const STEPS_PER_BEAT: u32 = 8;
+
+/// LilyPond durations and their length in 32nd notes, longest first
+const DURATIONS: [(u32, &str); 11] = [
+    (48, "1."),
+    (32, "1"),
+    (24, "2."),
+    (16, "2"),
+    (12, "4."),
+    (8, "4"),
+    (6, "8."),
+    (4, "8"),
+    (3, "16."),
+    (2, "16"),
+    (1, "32"),
+];
+
+/// returns a LilyPond string, quoted and escaped
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// returns the LilyPond name of a note, e.g. "fis" for "F#"
+fn note_name(name: &str) -> String {
+    let mut out = name[..1].to_lowercase();
+    match name.get(1..) {
+        Some("#") => out.push_str("is"),
+        Some("b") => out.push_str("es"),
+        _ => {}
+    }
+    out
+}
+
+/// returns the durations adding up to a length in 32nd notes
+fn durations(mut length: u32) -> Vec<&'static str> {
+    let mut out = Vec::new();
+    while length > 0 {
+        let &(steps, duration) = DURATIONS
+            .iter()
+            .find(|(steps, _)| *steps <= length)
+            .unwrap();
+        out.push(duration);
+        length -= steps;
+    }
+    out
+}
+
+/// writes a score as LilyPond with the tab of every note as markup below it. Notes are
+/// spelled like the notes of the harp key, whose major key signature is used, and notes
+/// crossing a barline are split into tied notes. Returns an error for scores longer
+/// than `MAX_MEASURES`.
+pub fn export(
+    score: &Score,
+    tab: &MelodyTab,
+    key: &ChromaticScale,
+    dialect: Dialect,
+) -> Result<String, Error> {
+    if score.measures().is_none() {
+        return Err(Error::ScoreTooLong);
+    }
+    let mut out = String::from(VERSION);
+    out.push_str("\n\\header {\n");
+    if let Some(title) = &score.title {
+        let _ = writeln!(out, "  title = {}", quote(title));
+    }
+    let _ = writeln!(
+        out,
+        "  instrument = {}",
+        quote(&format!("{} harmonica", key.0[0]))
+    );
+    out.push_str("}\n\n\\score {\n  {\n");
+    let _ = writeln!(
+        out,
+        "    \\key {} \\major\n    \\time {}/{}",
+        note_name(key.0[0]),
+        score.time.0,
+        score.time.1
+    );
+
+    // notes and rests as (start, end, note index) in steps
+    let to_steps = |beats: f64| (beats * STEPS_PER_BEAT as f64).round().max(0.0) as u32;
+    let mut events: Vec<(u32, u32, Option<usize>)> = Vec::new();
+    let mut end: u32 = 0;
+    for (i, note) in score.notes.iter().enumerate() {
+        let start = to_steps(note.start).max(end);
+        if start > end {
+            events.push((end, start, None));
+        }
+        end = start
+            .checked_add(to_steps(note.duration).max(1))
+            .ok_or(Error::ScoreTooLong)?;
+        events.push((start, end, Some(i)));
+    }
+    let measure = to_steps(score.measure()).max(1);
+    let measures = end.div_ceil(measure);
+    if measures.checked_mul(measure).is_none() {
+        return Err(Error::ScoreTooLong);
+    }
+
+    for number in 0..measures {
+        out.push_str("   ");
+        let (bar_start, bar_end) = (number * measure, (number + 1) * measure);
+        let mut filled = bar_start;
+        for &(start, end, note) in &events {
+            let (clipped_start, clipped_end) = (start.max(bar_start), end.min(bar_end));
+            if clipped_start >= clipped_end {
+                continue;
+            }
+            filled = clipped_end;
+            let durations = durations(clipped_end - clipped_start);
+            let note = match note {
+                Some(i) => i,
+                None => {
+                    for duration in durations {
+                        let _ = write!(out, " r{}", duration);
+                    }
+                    continue;
+                }
+            };
+
+            let pitch = score.notes[note].pitch;
+            let name = key.note_name(pitch.pitch_class());
+            let alter = match name.get(1..) {
+                Some("#") => 1,
+                Some("b") => -1,
+                _ => 0,
+            };
+            let octave = Pitch(pitch.0 - alter).octave() - 3;
+            let marks = if octave >= 0 {
+                "'".repeat(octave as usize)
+            } else {
+                ",".repeat(-octave as usize)
+            };
+            for (i, duration) in durations.iter().enumerate() {
+                let _ = write!(out, " {}{}{}", note_name(name), marks, duration);
+                if i == 0 && clipped_start == start {
+                    if let Some(Ok(tab)) = tab.notes.get(note).map(|n| n.tab) {
+                        let _ = write!(out, "_\\markup {{ {} }}", quote(&tab.format(dialect)));
+                    }
+                }
+                if i + 1 < durations.len() || clipped_end < end {
+                    out.push('~');
+                }
+            }
+        }
+        // fill the last measure
+        for duration in durations(bar_end - filled) {
+            let _ = write!(out, " r{}", duration);
+        }
+        out.push_str(" |\n");
+    }
+    out.push_str("  }\n  \\layout { }\n}\n");
+    Ok(out)
+}
+
+/// writes the layout as a LilyPond table markup, with a row of hole numbers below the
+/// blow notes like the text layout. Scale notes are bold.
+pub fn layout_chart(layout: &Layout, caption: &str, label: Label) -> String {
+    let mut out = String::from(VERSION);
+    out.push_str("\n\\markup {\n  \\column {\n");
+    let _ = writeln!(out, "    \\bold {}\n    \\vspace #1", quote(caption));
+    let align = std::iter::once("-1")
+        .chain(std::iter::repeat_n("0", layout.holes))
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let _ = writeln!(
+        out,
+        "    \\override #'(padding . 2)\n    \\table #'({}) {{",
+        align
+    );
+
+    for (row, cells) in &layout.rows {
+        let _ = write!(out, "      {}", quote(row.label()));
+        for cell in cells {
+            match cell {
+                Some(cell) if cell.is_scale_note => {
+                    let _ = write!(out, " \\bold {}", quote(&cell.label(label)));
+                }
+                Some(cell) => {
+                    let _ = write!(out, " {}", quote(&cell.label(label)));
+                }
+                None => out.push_str(" \"\""),
+            }
+        }
+        out.push('\n');
+
+        if *row == Row::Blow {
+            out.push_str("      \"\"");
+            for hole in 1..=layout.holes {
+                let _ = write!(out, " \\italic \"{}\"", hole);
+            }
+            out.push('\n');
+        }
+    }
+    out.push_str("    }\n  }\n}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::melody::{melody_to_tab, Costs, TimedNote};
+    use crate::{Setup, Tuning};
+
+    #[test]
+    fn test_export() {
+        let key = ChromaticScale::new("G", None).unwrap();
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        let layout = Tuning::default().layout(&key, &setup);
+        let note = |midi, start, duration| TimedNote {
+            pitch: Pitch(midi),
+            start,
+            duration,
+        };
+        let mut score = Score::new(vec![
+            note(55, 0.0, 1.0),
+            note(66, 2.0, 3.0),
+            note(43, 5.0, 0.5),
+        ]);
+        score.title = Some(String::from("\"Test\""));
+        let tab = melody_to_tab(&score.pitches(), &layout, &Costs::default());
+        let ly = export(&score, &tab, &key, Dialect::Apostrophe).unwrap();
+
+        assert!(ly.contains("title = \"\\\"Test\\\"\""));
+        assert!(ly.contains("\\key g \\major\n    \\time 4/4\n"));
+        assert!(ly.contains(
+            "    g4_\\markup { \"1\" } r4 fis'2_\\markup { \"-3\" }~ |\n    fis'4 g,8 r2 r8 |\n"
+        ));
+
+        for duration in [1e12, f64::INFINITY] {
+            score.notes[2].duration = duration;
+            assert!(matches!(
+                export(&score, &tab, &key, Dialect::Apostrophe),
+                Err(Error::ScoreTooLong)
+            ));
+        }
+        score.notes[2].duration = 0.5;
+        score.time = (u32::MAX, 1);
+        assert!(matches!(
+            export(&score, &tab, &key, Dialect::Apostrophe),
+            Err(Error::ScoreTooLong)
+        ));
+    }
+
+    #[test]
+    fn test_layout_chart() {
+        let key = ChromaticScale::new("C", None).unwrap();
+        let setup = Setup {
+            scale: Some("major pentatonic"),
+            position: 1,
+        };
+        let layout = Tuning::default().layout(&key, &setup);
+        let ly = layout_chart(&layout, "C richter harmonica", Label::Note);
+        assert!(ly.contains("\\table #'(-1 0 0 0 0 0 0 0 0 0 0) {"));
+        assert!(ly.contains("      \"blow\" \\bold \"C\" \\bold \"E\" \\bold \"G\""));
+        assert!(ly.contains("      \"\" \\italic \"1\" \\italic \"2\""));
+        assert!(ly.contains("      \"draw\" \\bold \"D\" \\bold \"G\" \"B\""));
+    }
+}
@@ -1,6 +1,18 @@
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use harptool::config::{Config, Settings};
+use harptool::layout::Label;
+use harptool::melody::{self, Costs};
+use harptool::pitch::Pitch;
+use harptool::pitch::DEFAULT_A4;
+use harptool::tab::Dialect;
+use harptool::theme::{ScaleMark, Theme};
+use harptool::tuning_file::{self, Severity};
 use harptool::*;
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, MAIN_SEPARATOR};
+use std::sync::OnceLock;
 
 fn is_valid_position(val: String) -> Result<(), String> {
     if let Ok(res) = val.parse::<usize>() {
@@ -14,118 +26,1252 @@ fn is_valid_position(val: String) -> Result<(), String> {
     }
 }
 
-fn main() {
-    let matches = App::new("harptool")
-        .about("print harmonica note layouts")
-        .arg(
-            Arg::with_name("tuning")
-                .short("t")
-                .long("tuning")
-                .value_name("TUNING")
-                .help("select tuning"),
-        )
-        .arg(
-            Arg::with_name("key")
-                .short("k")
-                .long("key")
-                .value_name("KEY")
-                .help("select key"),
-        )
-        .arg(Arg::with_name("sharps").long("sharps").help("use sharps"))
-        .arg(Arg::with_name("flats").long("flats").help("use flats"))
-        .arg(
-            Arg::with_name("degrees")
-                .long("degrees")
-                .short("d")
-                .help("print scale degrees"),
-        )
-        .arg(
-            Arg::with_name("position")
-                .long("position")
-                .short("p")
-                .value_name("POSITION")
-                .help("set position")
-                .validator(is_valid_position),
-        )
-        .arg(
-            Arg::with_name("scale")
-                .long("scale")
-                .short("s")
-                .value_name("SCALE")
-                .help("highlight notes of a scale"),
-        )
-        .arg(
-            Arg::with_name("list tunings")
-                .long("list-tunings")
-                .short("l")
-                .help("list available tunings"),
-        )
-        .arg(
-            Arg::with_name("list scales")
-                .long("list-scales")
-                .help("list available scales"),
-        )
-        .arg(
-            Arg::with_name("export")
-                .long("export")
-                .short("e")
-                .help("export PNG of note layout"),
-        )
-        .arg(
-            Arg::with_name("no-label-rows")
-                .long("no-label-rows")
-                .help("don't label rows in export"),
-        )
-        .get_matches();
+/// prints an error and exits, listing the available tunings or scales when there are
+/// no close names to suggest
+fn exit_with_error(err: Error) -> ! {
+    match &err {
+        Error::UnknownTuning {
+            name,
+            path: Some(path),
+            ..
+        } if path.as_path() == Path::new(name) => {
+            eprintln!("tuning file \"{}\" not found", name);
+        }
+        Error::UnknownTuning { suggestions, .. } if !suggestions.is_empty() => {
+            eprintln!("{}", err);
+        }
+        Error::UnknownTuning {
+            path: Some(path), ..
+        } => {
+            eprintln!(
+                "{}\nadd it with \"harptool tunings add FILE\" or by creating file \"{}\"\n",
+                err,
+                path.to_string_lossy()
+            );
+            list_tunings();
+        }
+        Error::UnknownTuning { .. } => {
+            eprintln!("{}\n", err);
+            list_tunings();
+        }
+        Error::TuningExists { path: Some(_), .. } => {
+            eprintln!("{}\nreplace it with --force", err);
+        }
+        Error::TuningExists { path: None, .. } => {
+            eprintln!(
+                "{}\nit is built in, replace it with --force or choose another name with --name",
+                err
+            );
+        }
+        Error::UnknownScale { suggestions, .. } if !suggestions.is_empty() => {
+            eprintln!("{}", err);
+        }
+        Error::UnknownScale { .. } => {
+            eprintln!("{}\n", err);
+            list_scales();
+        }
+        _ => eprintln!("{}", err),
+    }
+    std::process::exit(-1);
+}
 
-    if matches.is_present("list tunings") {
-        list_tunings();
-        return;
+fn is_valid_frequency(val: String) -> Result<(), String> {
+    match val.parse::<f64>() {
+        Ok(res) if res > 0.0 => Ok(()),
+        Ok(_) => Err(String::from("must be positive")),
+        Err(_) => Err(String::from("must be a number")),
     }
-    if matches.is_present("list scales") {
-        list_scales();
+}
+
+/// prints every problem of a tuning file, exits with an error if the tuning can't be used
+fn check_tuning(path: &str) {
+    let contents = fs::read_to_string(path).unwrap_or_else(|e| exit_with_error(e.into()));
+    let diagnostics = tuning_file::check(&contents);
+    if diagnostics.is_empty() {
+        println!("{}: no problems found", path);
         return;
     }
 
-    let tuning = matches.value_of("tuning").unwrap_or("richter");
-    let key = matches.value_of("key").unwrap_or("C");
+    for diagnostic in &diagnostics {
+        println!("{}:{}", path, diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+}
+
+fn is_valid_cost(val: String) -> Result<(), String> {
+    val.parse::<u32>()
+        .map(|_| ())
+        .map_err(|_| String::from("must be a non-negative integer"))
+}
+
+/// arguments choosing the layout, shared by the subcommands that show one
+fn setup_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("tuning")
+            .short("t")
+            .long("tuning")
+            .value_name("TUNING")
+            .help("select tuning"),
+        tuning_file_arg(),
+        Arg::with_name("key")
+            .short("k")
+            .long("key")
+            .value_name("KEY")
+            .help("select key"),
+        Arg::with_name("sharps").long("sharps").help("use sharps"),
+        Arg::with_name("flats").long("flats").help("use flats"),
+        Arg::with_name("degrees")
+            .long("degrees")
+            .short("d")
+            .help("show scale degrees"),
+        Arg::with_name("position")
+            .long("position")
+            .short("p")
+            .value_name("POSITION")
+            .help("set position")
+            .validator(is_valid_position),
+        Arg::with_name("scale")
+            .long("scale")
+            .short("s")
+            .value_name("SCALE")
+            .help("highlight notes of a scale"),
+        preset_arg(),
+    ]
+}
+
+/// the argument choosing a preset of config.toml
+fn preset_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("preset")
+        .long("preset")
+        .value_name("PRESET")
+        .help("start from a preset of config.toml, e.g. a key, position and scale")
+}
 
-    let sharp = if matches.is_present("sharps") {
+/// arguments choosing how scale notes stand out
+fn mark_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("mark")
+            .long("mark")
+            .value_name("MARK")
+            .possible_values(&["color", "brackets", "bold", "underline", "pattern"])
+            .help("select how scale notes are marked (default color). Exports show brackets as a pattern, the terminal shows patterns as brackets"),
+        Arg::with_name("colorblind")
+            .long("colorblind")
+            .help("use colors that can be told apart with red-green colorblindness"),
+    ]
+}
+
+/// arguments of the layout subcommand, which can also be given without a subcommand
+fn layout_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = setup_args();
+    args.extend(vec![
+        Arg::with_name("pitches")
+            .long("pitches")
+            .help("print scientific pitch names (C4 is middle C)")
+            .conflicts_with_all(&["degrees", "midi", "frequencies"]),
+        Arg::with_name("midi")
+            .long("midi")
+            .help("print MIDI note numbers")
+            .conflicts_with_all(&["degrees", "frequencies"]),
+        Arg::with_name("frequencies")
+            .long("frequencies")
+            .help("print frequencies in Hz")
+            .conflicts_with("degrees"),
+        Arg::with_name("a4")
+            .long("a4")
+            .value_name("HZ")
+            .help("set the frequency of A4 used by --frequencies (default 440)")
+            .validator(is_valid_frequency),
+        Arg::with_name("format")
+            .long("format")
+            .short("f")
+            .value_name("FORMAT")
+            .possible_values(&[
+                "text",
+                #[cfg(feature = "json")]
+                "json",
+                "lilypond",
+            ])
+            .help("select output format"),
+        Arg::with_name("color")
+            .long("color")
+            .value_name("WHEN")
+            .possible_values(&["auto", "always", "never"])
+            .help("select when to color the layout (default auto: only in a terminal, and not if NO_COLOR is set)"),
+    ]);
+    args.extend(mark_args());
+    args
+}
+
+/// the format argument of the export subcommand, named `long` on the command line
+fn export_format_arg<'a, 'b>(long: &'b str) -> Arg<'a, 'b> {
+    Arg::with_name("export-format")
+        .long(long)
+        .value_name("FORMAT")
+        .possible_values(&[
+            #[cfg(feature = "export")]
+            "png",
+            "svg",
+            #[cfg(feature = "pdf")]
+            "pdf",
+        ])
+        .help("select image format of export (default from the output file, or png)")
+}
+
+/// arguments of the export subcommand besides the layout and its format
+fn image_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = vec![
+        Arg::with_name("output")
+            .long("output")
+            .short("o")
+            .value_name("FILE")
+            .help("write the export to FILE, or to stdout for \"-\" (default layout.png)"),
+        Arg::with_name("width")
+            .long("width")
+            .value_name("PIXELS")
+            .validator(is_valid_size)
+            .help("set width of exported image"),
+        Arg::with_name("height")
+            .long("height")
+            .value_name("PIXELS")
+            .validator(is_valid_size)
+            .help("set height of exported image"),
+        Arg::with_name("export-scale")
+            .long("export-scale")
+            .value_name("FACTOR")
+            .conflicts_with_all(&["width", "height", "dpi"])
+            .validator(is_valid_scale)
+            .help("scale exported image, e.g. 2 for twice the size"),
+        Arg::with_name("dpi")
+            .long("dpi")
+            .value_name("DPI")
+            .conflicts_with_all(&["width", "height"])
+            .validator(is_valid_dpi)
+            .help("scale exported image to DPI, where 96 is its natural size"),
+        Arg::with_name("theme")
+            .long("theme")
+            .value_name("THEME")
+            .help("select colors of export: light, dark, high-contrast, grayscale, colorblind or a theme file"),
+        Arg::with_name("no-label-rows")
+            .long("no-label-rows")
+            .help("don't label rows in export"),
+    ];
+    args.extend(booklet_args());
+    args
+}
+
+/// arguments of harptool without a subcommand: those of the layout subcommand, and the
+/// older flags for exporting and listing
+fn flat_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = layout_args();
+    args.extend(vec![
+        Arg::with_name("list tunings")
+            .long("list-tunings")
+            .short("l")
+            .help("list available tunings, like the tunings list subcommand"),
+        Arg::with_name("list scales")
+            .long("list-scales")
+            .help("list available scales, like the scales list subcommand"),
+        Arg::with_name("export")
+            .long("export")
+            .short("e")
+            .help("export image of note layout, like the export subcommand"),
+        export_format_arg("export-format").requires("export"),
+    ]);
+    args.extend(image_args().into_iter().map(|arg| arg.requires("export")));
+    args
+}
+
+/// arguments shared by the subcommands that print tab
+fn tab_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = vec![
+        Arg::with_name("key")
+            .short("k")
+            .long("key")
+            .value_name("KEY")
+            .help("select key of the harp"),
+        Arg::with_name("sharps").long("sharps").help("use sharps"),
+        Arg::with_name("flats").long("flats").help("use flats"),
+        Arg::with_name("dialect")
+            .long("dialect")
+            .value_name("DIALECT")
+            .possible_values(&["apostrophe", "plus", "suffix", "letters", "parens"])
+            .help("select how tab is written"),
+    ];
+    args.extend(cost_args());
+    args
+}
+
+/// arguments for the costs used to pick the best tab for a melody
+fn cost_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("plain-cost")
+            .long("plain-cost")
+            .value_name("COST")
+            .validator(is_valid_cost)
+            .help("cost of a plain blow or draw note (default 0)"),
+        Arg::with_name("bend-cost")
+            .long("bend-cost")
+            .value_name("COST")
+            .validator(is_valid_cost)
+            .help("cost per semitone of a bend (default 3)"),
+        Arg::with_name("overblow-cost")
+            .long("overblow-cost")
+            .value_name("COST")
+            .validator(is_valid_cost)
+            .help("cost of an overblow or overdraw (default 6)"),
+        Arg::with_name("jump-cost")
+            .long("jump-cost")
+            .value_name("COST")
+            .validator(is_valid_cost)
+            .help("cost per hole moved between notes (default 1)"),
+    ]
+}
+
+/// arguments for reading a MIDI file in the tab subcommand
+#[cfg(feature = "midi")]
+fn midi_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("midi")
+            .long("midi")
+            .value_name("FILE")
+            .conflicts_with("notes")
+            .help("read the melody from a MIDI file, suggests a harp key unless --key is given"),
+        Arg::with_name("track")
+            .long("track")
+            .value_name("TRACK")
+            .requires("midi")
+            .validator(is_valid_number)
+            .help("select track of the MIDI file, counting from 1"),
+        Arg::with_name("channel")
+            .long("channel")
+            .value_name("CHANNEL")
+            .requires("track")
+            .validator(is_valid_channel)
+            .help("select channel of the track, counting from 1 (default 1)"),
+    ]
+}
+
+#[cfg(not(feature = "midi"))]
+fn midi_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    Vec::new()
+}
+
+/// arguments for reading and writing MusicXML in the tab subcommand
+#[cfg(feature = "musicxml")]
+fn musicxml_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("musicxml")
+            .long("musicxml")
+            .value_name("FILE")
+            .conflicts_with_all(&["notes", "midi"])
+            .help(
+                "read the melody from a MusicXML file, suggests a harp key unless --key is given",
+            ),
+        Arg::with_name("part")
+            .long("part")
+            .value_name("ID")
+            .requires("musicxml")
+            .help("select part of the MusicXML file by its id, e.g. P1"),
+    ]
+}
+
+#[cfg(not(feature = "musicxml"))]
+fn musicxml_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    Vec::new()
+}
+
+/// arguments for writing the melody of the tab subcommand to files together with its tab
+fn score_out_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("lilypond-out")
+            .long("lilypond-out")
+            .value_name("FILE")
+            .help("write the melody as LilyPond with the tab under the notes"),
+        #[cfg(feature = "musicxml")]
+        Arg::with_name("musicxml-out")
+            .long("musicxml-out")
+            .value_name("FILE")
+            .help("write the melody as MusicXML with the tab as lyrics"),
+    ]
+}
+
+/// arguments for exporting a PDF booklet of layouts
+#[cfg(feature = "pdf")]
+fn booklet_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("booklet")
+        .long("booklet")
+        .value_name("PAGES")
+        .possible_values(&["keys", "tunings", "positions"])
+        .help("export a PDF with one page for every key, tuning or position")]
+}
+
+#[cfg(not(feature = "pdf"))]
+fn booklet_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    Vec::new()
+}
+
+/// the tui subcommand, which starts from the same choices as the printed layout
+#[cfg(feature = "tui")]
+fn tui_subcommands<'a, 'b>() -> Vec<App<'a, 'b>> {
+    vec![SubCommand::with_name("tui")
+        .about("explore layouts interactively in the terminal")
+        .args(&setup_args())
+        .args(&mark_args())]
+}
+
+#[cfg(not(feature = "tui"))]
+fn tui_subcommands<'a, 'b>() -> Vec<App<'a, 'b>> {
+    Vec::new()
+}
+
+/// arguments for reading an ABC file in the tab subcommand
+fn abc_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("abc")
+            .long("abc")
+            .value_name("FILE")
+            .conflicts_with("notes")
+            .help("read the melody from an ABC file, the harp key follows the tune's key and mode"),
+        Arg::with_name("tune")
+            .long("tune")
+            .value_name("X")
+            .requires("abc")
+            .validator(is_valid_tune)
+            .help("select tune of the ABC file by its X: number"),
+    ]
+}
+
+fn is_valid_tune(val: String) -> Result<(), String> {
+    val.parse::<u32>()
+        .map(|_| ())
+        .map_err(|_| String::from("must be a non-negative integer"))
+}
+
+#[cfg(feature = "midi")]
+fn is_valid_number(val: String) -> Result<(), String> {
+    match val.parse::<usize>() {
+        Ok(res) if res > 0 => Ok(()),
+        _ => Err(String::from("must be a positive integer")),
+    }
+}
+
+#[cfg(feature = "midi")]
+fn is_valid_channel(val: String) -> Result<(), String> {
+    match val.parse::<usize>() {
+        Ok(res) if (1..=16).contains(&res) => Ok(()),
+        _ => Err(String::from("must be between 1 and 16")),
+    }
+}
+
+fn get_sharp(matches: &ArgMatches) -> Option<bool> {
+    if matches.is_present("sharps") {
         Some(true)
     } else if matches.is_present("flats") {
         Some(false)
     } else {
         None
+    }
+}
+
+/// returns where and how big to export the layout. The format is taken from
+/// --export-format, or else from the extension of the output file. Options that are not
+/// given are taken from config.toml, where the size is only used if no size is given.
+fn get_export_options<'a>(matches: &'a ArgMatches) -> Result<ExportOptions<'a>, Error> {
+    let defaults = &config().export;
+    let from_path = |path: &str| {
+        if path == "-" {
+            return Ok(None);
+        }
+        ExportFormat::from_path(path).map(Some).ok_or_else(|| {
+            Error::Export(format!(
+                "can't tell the export format of \"{}\", use --export-format",
+                path
+            ))
+        })
     };
+    // the format is given, or follows the output file, on the command line or in config.toml
+    let mut format = match matches.value_of("export-format") {
+        Some("png") => Some(ExportFormat::Png),
+        Some("svg") => Some(ExportFormat::Svg),
+        Some(_) => Some(ExportFormat::Pdf),
+        None => None,
+    };
+    if let (None, Some(path)) = (format, matches.value_of("output")) {
+        format = from_path(path)?;
+    }
+    format = format.or(defaults.format);
+    if let (None, None, Some(path)) = (format, matches.value_of("output"), &defaults.output) {
+        format = from_path(path)?;
+    }
+    let format = match format {
+        Some(format) => format,
+        None if matches.is_present("booklet") => ExportFormat::Pdf,
+        None => ExportFormat::default(),
+    };
+    let path = matches.value_of("output").or(defaults.output.as_deref());
+
+    let sized = ["width", "height", "export-scale", "dpi"]
+        .iter()
+        .any(|name| matches.is_present(name));
+    let size = |name, default: Option<u32>| match matches.value_of(name) {
+        Some(size) => Some(size.parse::<u32>().unwrap()),
+        None if sized => None,
+        None => default,
+    };
+    let scale = match (matches.value_of("export-scale"), matches.value_of("dpi")) {
+        (Some(scale), _) => scale.parse::<f32>().unwrap(),
+        // images are drawn at 96 DPI
+        (_, Some(dpi)) => dpi.parse::<f32>().unwrap() / 96.0,
+        _ if sized => 1.0,
+        _ => match (defaults.scale, defaults.dpi) {
+            (Some(scale), _) => scale,
+            (_, Some(dpi)) => dpi / 96.0,
+            _ => 1.0,
+        },
+    };
+    let width = size("width", defaults.width);
+    let height = size("height", defaults.height);
+    let mut theme = match matches.value_of("theme").or(defaults.theme.as_deref()) {
+        Some(theme) => Theme::load(theme)?,
+        None if matches.is_present("colorblind") => Theme::colorblind(),
+        None => Theme::default(),
+    };
+    theme.scale_mark = match matches.value_of("mark") {
+        // brackets don't fit in the cells of an image
+        Some("brackets") | Some("pattern") => ScaleMark::Pattern,
+        Some("bold") => ScaleMark::Bold,
+        Some("underline") => ScaleMark::Underline,
+        _ => theme.scale_mark,
+    };
+    Ok(ExportOptions {
+        path,
+        format,
+        width,
+        height,
+        scale,
+        theme,
+    })
+}
+
+/// returns how to mark scale notes in the layout printed to stdout, following --color
+fn get_highlight(matches: &ArgMatches) -> Highlight {
+    let mark = match matches.value_of("mark") {
+        // there are no patterns in a terminal
+        Some("brackets") | Some("pattern") => Mark::Brackets,
+        Some("bold") => Mark::Bold,
+        Some("underline") => Mark::Underline,
+        _ => Mark::Color,
+    };
+    let palette = if matches.is_present("colorblind") {
+        Palette::Colorblind
+    } else {
+        Palette::Default
+    };
+    let highlight = match matches.value_of("color") {
+        Some("always") => Highlight {
+            mark,
+            palette,
+            ansi: true,
+        },
+        Some("never") => Highlight {
+            mark,
+            palette,
+            ansi: false,
+        },
+        _ => Highlight::for_stdout(mark, palette),
+    };
+    highlight
+}
 
-    let scale = matches.value_of("scale");
-    if let Some(scale) = scale {
-        validate_scale(scale);
+fn is_valid_size(val: String) -> Result<(), String> {
+    match val.parse::<u32>() {
+        Ok(res) if res > 0 && res <= MAX_EXPORT_SIZE => Ok(()),
+        _ => Err(format!(
+            "must be a positive integer up to {}",
+            MAX_EXPORT_SIZE
+        )),
     }
+}
+
+/// checks a positive number up to `max`
+fn is_valid_factor(val: &str, max: f32) -> Result<(), String> {
+    match val.parse::<f32>() {
+        Ok(res) if res > 0.0 && res <= max => Ok(()),
+        Ok(_) => Err(format!("must be positive and at most {}", max)),
+        Err(_) => Err(String::from("must be a number")),
+    }
+}
+
+fn is_valid_scale(val: String) -> Result<(), String> {
+    is_valid_factor(&val, MAX_EXPORT_SCALE)
+}
+
+fn is_valid_dpi(val: String) -> Result<(), String> {
+    is_valid_factor(&val, MAX_EXPORT_SCALE * 96.0)
+}
 
+fn get_dialect(matches: &ArgMatches) -> Dialect {
+    matches
+        .value_of("dialect")
+        .map_or(Dialect::Apostrophe, |d| d.parse().unwrap())
+}
+
+fn get_costs(matches: &ArgMatches) -> Costs {
+    let defaults = Costs::default();
+    let cost = |name, default| {
+        matches
+            .value_of(name)
+            .map_or(default, |c| c.parse().unwrap())
+    };
+    Costs {
+        plain: cost("plain-cost", defaults.plain),
+        bend: cost("bend-cost", defaults.bend),
+        overblow: cost("overblow-cost", defaults.overblow),
+        hole_jump: cost("jump-cost", defaults.hole_jump),
+    }
+}
+
+/// joins the values of an argument given as several words
+fn get_joined(matches: &ArgMatches, name: &str) -> String {
+    matches
+        .values_of(name)
+        .unwrap()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// prints tab for the melody, MIDI, MusicXML or ABC file given to the tab subcommand
+fn tab(matches: &ArgMatches) {
+    let settings = get_settings(matches);
+    let tuning = &get_tuning(matches, "tuning", settings.tuning.as_deref());
+    let key = matches.value_of("key");
+    let sharp = get_sharp(matches).or(settings.sharps);
+    let costs = get_costs(matches);
+    let dialect = get_dialect(matches);
+    let files = ScoreFiles {
+        musicxml: matches.value_of("musicxml-out"),
+        lilypond: matches.value_of("lilypond-out"),
+    };
+
+    if let Some(path) = matches.value_of("abc") {
+        run_abc(
+            tuning,
+            key,
+            matches.value_of("position").map(|p| p.parse().unwrap()),
+            sharp,
+            path,
+            matches.value_of("tune").map(|x| x.parse().unwrap()),
+            &costs,
+            dialect,
+            &files,
+        )
+        .unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
+    #[cfg(feature = "midi")]
+    if let Some(path) = matches.value_of("midi") {
+        let number = |name| {
+            matches
+                .value_of(name)
+                .map(|n: &str| n.parse::<usize>().unwrap() - 1)
+        };
+        let voice = number("track").map(|track| (track, number("channel").unwrap_or(0) as u8));
+        run_midi(tuning, key, sharp, path, voice, &costs, dialect, &files)
+            .unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
+    #[cfg(feature = "musicxml")]
+    if let Some(path) = matches.value_of("musicxml") {
+        run_musicxml(
+            tuning,
+            key,
+            sharp,
+            path,
+            matches.value_of("part"),
+            &costs,
+            dialect,
+            &files,
+        )
+        .unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
+    let melody = get_joined(matches, "notes");
+    let key = key.or(settings.key.as_deref()).unwrap_or("C");
+    let res = if files != ScoreFiles::default() {
+        // without timing, every note is a quarter note
+        melody::parse_melody(&melody).and_then(|melody| {
+            let notes = (0..)
+                .zip(melody)
+                .map(|(i, pitch)| melody::TimedNote {
+                    pitch,
+                    start: i as f64,
+                    duration: 1.0,
+                })
+                .collect();
+            let score = melody::Score::new(notes);
+            run_score(tuning, Some(key), sharp, &score, &costs, dialect, &files)
+        })
+    } else {
+        run_melody(tuning, key, sharp, &melody, &costs, dialect)
+    };
+    res.unwrap_or_else(|e| exit_with_error(e));
+}
+
+/// opens the terminal UI given to the tui subcommand
+#[cfg(feature = "tui")]
+fn tui(matches: &ArgMatches) {
+    let settings = get_settings(matches);
+    let choice = get_choice(matches, &settings);
+    harptool::tui::run(
+        &choice.tuning,
+        choice.key,
+        choice.sharp,
+        &choice.setup,
+        matches.is_present("degrees"),
+        get_highlight(matches),
+    )
+    .unwrap_or_else(|e| exit_with_error(e));
+}
+
+/// prints tab given to the transpose subcommand rewritten for another tuning or position
+fn transpose(matches: &ArgMatches) {
+    let from = &get_tuning(matches, "from", config().defaults.tuning.as_deref());
+    let key = matches
+        .value_of("key")
+        .or(config().defaults.key.as_deref())
+        .unwrap_or("C");
+    let tab = get_joined(matches, "tab");
+    let res = if let Some(to) = matches.value_of("to") {
+        run_transpose_tuning(
+            from,
+            to,
+            key,
+            get_sharp(matches),
+            &tab,
+            &get_costs(matches),
+            get_dialect(matches),
+        )
+    } else {
+        let position = |name| matches.value_of(name).unwrap_or("1").parse().unwrap();
+        run_transpose_position(
+            from,
+            key,
+            get_sharp(matches),
+            &tab,
+            position("from-position"),
+            position("to-position"),
+            &get_costs(matches),
+            get_dialect(matches),
+        )
+    };
+    res.unwrap_or_else(|e| exit_with_error(e));
+}
+
+/// returns config.toml, which is read once
+fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(|| Config::load().unwrap_or_else(|e| exit_with_error(e)))
+}
+
+/// returns the defaults of config.toml, with the preset chosen by --preset over them
+fn get_settings(matches: &ArgMatches) -> Settings {
+    config()
+        .settings(matches.value_of("preset"))
+        .unwrap_or_else(|e| exit_with_error(e))
+}
+
+/// returns the file given with --tuning-file, as a path that isn't taken for a tuning name
+fn get_tuning_file<'a>(matches: &'a ArgMatches) -> Option<Cow<'a, str>> {
+    matches.value_of("tuning-file").map(|path| {
+        if Path::new(path).components().nth(1).is_some() {
+            Cow::Borrowed(path)
+        } else {
+            Cow::Owned(format!(".{}{}", MAIN_SEPARATOR, path))
+        }
+    })
+}
+
+/// returns the tuning file, the name of the tuning given by argument `name`, or else of
+/// the `default` tuning. Exits if the tuning doesn't exist.
+fn get_tuning<'a>(matches: &'a ArgMatches, name: &str, default: Option<&'a str>) -> Cow<'a, str> {
+    if let Some(path) = get_tuning_file(matches) {
+        return path;
+    }
+    let tuning = matches.value_of(name).or(default).unwrap_or("richter");
+    match find_tuning(tuning) {
+        Ok((name, _)) if name == tuning => Cow::Borrowed(tuning),
+        Ok((name, _)) => Cow::Owned(name),
+        Err(e) => exit_with_error(e),
+    }
+}
+
+/// the layout to show, chosen on the command line or else by the settings
+struct Choice<'a> {
+    tuning: Cow<'a, str>,
+    key: &'a str,
+    sharp: Option<bool>,
+    setup: Setup<'a>,
+}
+
+/// returns the layout to show, exits if the scale doesn't exist
+fn get_choice<'a>(matches: &'a ArgMatches, settings: &'a Settings) -> Choice<'a> {
+    let scale = matches
+        .value_of("scale")
+        .or(settings.scale.as_deref())
+        .map(|scale| scales::find_scale(scale).unwrap_or_else(|e| exit_with_error(e)));
     let position = matches
         .value_of("position")
-        .unwrap_or("1")
-        .parse::<usize>()
-        .unwrap();
-
-    let setup = Setup { scale, position };
-    let label_rows = !matches.is_present("no-label-rows");
+        .map(|position| position.parse::<usize>().unwrap())
+        .or(settings.position)
+        .unwrap_or(1);
+    Choice {
+        tuning: get_tuning(matches, "tuning", settings.tuning.as_deref()),
+        key: matches
+            .value_of("key")
+            .or(settings.key.as_deref())
+            .unwrap_or("C"),
+        sharp: get_sharp(matches).or(settings.sharps),
+        setup: Setup { scale, position },
+    }
+}
 
-    if matches.is_present("export") {
-        export(
+/// exports the layout given to the export subcommand, or to harptool with --export.
+/// Returns whether the export was written to stdout.
+fn export_image(matches: &ArgMatches) -> bool {
+    let settings = get_settings(matches);
+    let Choice {
+        tuning,
+        key,
+        sharp,
+        setup,
+    } = get_choice(matches, &settings);
+    let tuning = tuning.as_ref();
+    let label_rows =
+        !matches.is_present("no-label-rows") && config().export.label_rows.unwrap_or(true);
+    let options = get_export_options(matches).unwrap_or_else(|e| exit_with_error(e));
+    match matches.value_of("booklet") {
+        #[cfg(feature = "pdf")]
+        Some(booklet) => {
+            let booklet = match booklet {
+                "keys" => Booklet::Keys,
+                "tunings" => Booklet::Tunings,
+                _ => Booklet::Positions,
+            };
+            export_booklet(
+                tuning,
+                key,
+                sharp,
+                &setup,
+                matches.is_present("degrees"),
+                label_rows,
+                booklet,
+                &options,
+            )
+        }
+        _ => export(
             tuning,
             key,
             sharp,
             &setup,
             matches.is_present("degrees"),
             label_rows,
-        );
+            &options,
+        ),
+    }
+    .unwrap_or_else(|e| exit_with_error(e));
+    options.path == Some("-")
+}
+
+/// prints the layout given to the layout subcommand, or to harptool without a subcommand
+fn layout(matches: &ArgMatches) {
+    if matches.is_present("list tunings") {
+        list_tunings();
+        return;
+    }
+    if matches.is_present("list scales") {
+        list_scales();
+        return;
     }
 
-    if matches.is_present("degrees") {
-        run_degrees(tuning, setup);
+    let settings = get_settings(matches);
+    let Choice {
+        tuning,
+        key,
+        sharp,
+        setup,
+    } = get_choice(matches, &settings);
+    let tuning = tuning.as_ref();
+
+    // the export was written to stdout instead of the layout
+    if matches.is_present("export") && export_image(matches) {
+        return;
+    }
+
+    let a4 = matches
+        .value_of("a4")
+        .map_or(DEFAULT_A4, |a4| a4.parse::<f64>().unwrap());
+
+    #[cfg(feature = "json")]
+    if matches.value_of("format") == Some("json") {
+        run_json(tuning, key, sharp, setup).unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
+    if matches.value_of("format") == Some("lilypond") {
+        let label = if matches.is_present("degrees") {
+            Label::Degree
+        } else if matches.is_present("pitches") {
+            Label::Pitch
+        } else if matches.is_present("midi") {
+            Label::Midi
+        } else if matches.is_present("frequencies") {
+            Label::Frequency(a4)
+        } else {
+            Label::Note
+        };
+        run_lilypond(tuning, key, sharp, setup, label).unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
+    let highlight = get_highlight(matches);
+    let res = if matches.is_present("degrees") {
+        run_degrees(tuning, setup, highlight)
+    } else if matches.is_present("pitches") {
+        run_labeled(tuning, key, sharp, setup, Label::Pitch, highlight)
+    } else if matches.is_present("midi") {
+        run_labeled(tuning, key, sharp, setup, Label::Midi, highlight)
+    } else if matches.is_present("frequencies") {
+        run_labeled(tuning, key, sharp, setup, Label::Frequency(a4), highlight)
     } else {
-        run(tuning, key, sharp, setup);
+        run(tuning, key, sharp, setup, highlight)
+    };
+    res.unwrap_or_else(|e| exit_with_error(e));
+}
+
+/// lists, shows, adds or checks tunings for the tunings subcommand
+fn tunings(matches: &ArgMatches) {
+    match matches.subcommand() {
+        ("show", Some(matches)) => {
+            let tuning = matches.value_of("tuning").unwrap();
+            let contents = read_tuning_file(tuning).unwrap_or_else(|e| exit_with_error(e));
+            print!("{}", contents);
+            if !contents.ends_with('\n') {
+                println!();
+            }
+        }
+        ("add", Some(matches)) => {
+            let file = matches.value_of("file").unwrap();
+            check_tuning(file);
+            let path = add_tuning(file, matches.value_of("name"), matches.is_present("force"))
+                .unwrap_or_else(|e| exit_with_error(e));
+            println!("added {}", path.to_string_lossy());
+        }
+        ("check", Some(matches)) => check_tuning(matches.value_of("file").unwrap()),
+        _ => list_tunings(),
+    }
+}
+
+/// creates the tuning directory with an example tuning for the init subcommand
+fn init() {
+    let path = harptool::init().unwrap_or_else(|e| exit_with_error(e));
+    println!("tunings are read from {}", path.to_string_lossy());
+}
+
+/// lists or shows scales for the scales subcommand
+fn scales(matches: &ArgMatches) {
+    match matches.subcommand() {
+        ("show", Some(matches)) => {
+            let position = matches.value_of("position").unwrap_or("1").parse().unwrap();
+            run_scale(
+                matches.value_of("scale").unwrap(),
+                matches.value_of("key").unwrap_or("C"),
+                get_sharp(matches),
+                position,
+            )
+            .unwrap_or_else(|e| exit_with_error(e));
+        }
+        _ => list_scales(),
+    }
+}
+
+/// reads the melody given to the analyze subcommand, with its tonic if the file tells it
+fn read_melody(matches: &ArgMatches) -> Result<(Vec<Pitch>, Option<usize>), Error> {
+    if let Some(path) = matches.value_of("abc") {
+        let tune = read_abc_tune(path, matches.value_of("tune").map(|x| x.parse().unwrap()))?;
+        if let Some(title) = &tune.score.title {
+            println!("{}", title);
+        }
+        return Ok((tune.score.pitches(), tune.key.map(|key| key.tonic)));
+    }
+
+    #[cfg(feature = "midi")]
+    if let Some(path) = matches.value_of("midi") {
+        let number = |name| {
+            matches
+                .value_of(name)
+                .map(|n: &str| n.parse::<usize>().unwrap() - 1)
+        };
+        let voice = number("track").map(|track| (track, number("channel").unwrap_or(0) as u8));
+        let (score, voice) = read_midi(path, voice)?;
+        println!("{}", voice);
+        return Ok((score.pitches(), None));
+    }
+
+    #[cfg(feature = "musicxml")]
+    if let Some(path) = matches.value_of("musicxml") {
+        let score = musicxml::import(&fs::read_to_string(path)?, matches.value_of("part"))?;
+        if let Some(title) = &score.title {
+            println!("{}", title);
+        }
+        return Ok((score.pitches(), None));
+    }
+
+    Ok((melody::parse_melody(&get_joined(matches, "notes"))?, None))
+}
+
+/// prints how well the melody given to the analyze subcommand fits a harp in every key
+fn analyze(matches: &ArgMatches) {
+    let tuning = &get_tuning(matches, "tuning", config().defaults.tuning.as_deref());
+    read_melody(matches)
+        .and_then(|(melody, tonic)| run_analyze(tuning, &melody, tonic, &get_costs(matches)))
+        .unwrap_or_else(|e| exit_with_error(e));
+}
+
+/// the subcommand checking a tuning file, named `name`
+fn check_tuning_subcommand<'a, 'b>(name: &str) -> App<'a, 'b> {
+    SubCommand::with_name(name)
+        .about("check a tuning file for problems")
+        .arg(
+            Arg::with_name("file")
+                .value_name("FILE")
+                .required(true)
+                .help("tuning file to check"),
+        )
+}
+
+/// the argument selecting the tuning of a subcommand that reads a melody
+fn tuning_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("tuning")
+            .short("t")
+            .long("tuning")
+            .value_name("TUNING")
+            .help("select tuning"),
+        tuning_file_arg(),
+    ]
+}
+
+/// the argument reading the tuning from a file instead of by name
+fn tuning_file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("tuning-file")
+        .long("tuning-file")
+        .value_name("FILE")
+        .conflicts_with("tuning")
+        .help("read the tuning from a tuning file")
+}
+
+/// the argument taking the notes of a melody, unless a file is read
+fn notes_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("notes")
+        .value_name("NOTES")
+        .required_unless_one(&["midi", "musicxml", "abc"])
+        .multiple(true)
+        .help("notes of the melody, e.g. G4 A4 B4 D5")
+}
+
+fn main() {
+    let matches = App::new("harptool")
+        .about("print harmonica note layouts")
+        .after_help("Without a subcommand, harptool prints the layout like the layout subcommand.")
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .args(&flat_args())
+        .subcommand(
+            SubCommand::with_name("layout")
+                .about("print the note layout of a tuning")
+                .args(&layout_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("export an image or PDF of the note layout")
+                .args(&setup_args())
+                .arg(export_format_arg("format").alias("export-format"))
+                .args(&image_args())
+                .args(&mark_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("create the tuning directory with an example tuning file"),
+        )
+        .subcommand(
+            SubCommand::with_name("tunings")
+                .about("list, show, add and check tunings")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").about("list available tunings"))
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("print the tuning file of a tuning")
+                        .arg(
+                            Arg::with_name("tuning")
+                                .value_name("TUNING")
+                                .required(true)
+                                .help("tuning to show"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("check a tuning file and copy it to the tuning directory")
+                        .arg(
+                            Arg::with_name("file")
+                                .value_name("FILE")
+                                .required(true)
+                                .help("tuning file to add"),
+                        )
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .value_name("NAME")
+                                .help("name the tuning (default the name: field of the file, or its file name)"),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("replace a tuning file with the same name"),
+                        ),
+                )
+                .subcommand(check_tuning_subcommand("check")),
+        )
+        .subcommand(
+            SubCommand::with_name("scales")
+                .about("list and show scales")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").about("list available scales"))
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("print the degrees of a scale and its notes on a harp")
+                        .arg(
+                            Arg::with_name("scale")
+                                .value_name("SCALE")
+                                .required(true)
+                                .help("scale to show"),
+                        )
+                        .arg(
+                            Arg::with_name("key")
+                                .short("k")
+                                .long("key")
+                                .value_name("KEY")
+                                .help("select key of the harp (default C)"),
+                        )
+                        .arg(Arg::with_name("sharps").long("sharps").help("use sharps"))
+                        .arg(Arg::with_name("flats").long("flats").help("use flats"))
+                        .arg(
+                            Arg::with_name("position")
+                                .long("position")
+                                .short("p")
+                                .value_name("POSITION")
+                                .help("set position (default 1)")
+                                .validator(is_valid_position),
+                        ),
+                ),
+        )
+        // kept for scripts written before tunings check
+        .subcommand(check_tuning_subcommand("check-tuning").setting(AppSettings::Hidden))
+        .subcommand(
+            SubCommand::with_name("tab")
+                .about("convert a melody to tab")
+                .arg(notes_arg())
+                .args(&midi_args())
+                .args(&musicxml_args())
+                .args(&abc_args())
+                .arg(
+                    Arg::with_name("position")
+                        .short("p")
+                        .long("position")
+                        .value_name("POSITION")
+                        .requires("abc")
+                        .conflicts_with("key")
+                        .validator(is_valid_position)
+                        .help("select position to play the tune in, chooses the harp key"),
+                )
+                .args(&score_out_args())
+                .args(&tuning_args())
+                .args(&tab_args())
+                .arg(preset_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("rank the harp keys and positions a melody can be played in")
+                .arg(notes_arg())
+                .args(&midi_args())
+                .args(&musicxml_args())
+                .args(&abc_args())
+                .args(&tuning_args())
+                .args(&cost_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("transpose")
+                .about("rewrite tab for another tuning or position")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("tab")
+                        .value_name("TAB")
+                        .required(true)
+                        .multiple(true)
+                        .allow_hyphen_values(true)
+                        .help("tab to rewrite, e.g. -4 -5 6 -6'"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("TUNING")
+                        .help("select tuning the tab is written for (default richter)"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("TUNING")
+                        .required_unless("to-position")
+                        .conflicts_with("to-position")
+                        .help("select tuning to rewrite the tab for"),
+                )
+                .arg(
+                    Arg::with_name("from-position")
+                        .long("from-position")
+                        .value_name("POSITION")
+                        .validator(is_valid_position)
+                        .help("select position the tab is played in (default 1)"),
+                )
+                .arg(
+                    Arg::with_name("to-position")
+                        .long("to-position")
+                        .value_name("POSITION")
+                        .validator(is_valid_position)
+                        .help("select position to move the tab to on the same harp"),
+                )
+                .args(&tab_args()),
+        )
+        .subcommands(tui_subcommands())
+        .get_matches();
+
+    match matches.subcommand() {
+        ("layout", Some(matches)) => layout(matches),
+        ("export", Some(matches)) => {
+            export_image(matches);
+        }
+        ("tunings", Some(matches)) => tunings(matches),
+        ("init", _) => init(),
+        ("scales", Some(matches)) => scales(matches),
+        ("check-tuning", Some(matches)) => check_tuning(matches.value_of("file").unwrap()),
+        ("tab", Some(matches)) => tab(matches),
+        ("analyze", Some(matches)) => analyze(matches),
+        ("transpose", Some(matches)) => transpose(matches),
+        #[cfg(feature = "tui")]
+        ("tui", Some(matches)) => tui(matches),
+        _ => layout(&matches),
     }
 }
@@ -0,0 +1,523 @@
+//! Conversion of melodies to tab.
+//!
+//! Every note of a melody is matched to the cells of a layout with the same pitch,
+//! which includes the duplicates `notes_in_order` leaves out (e.g. `-2` and `3`).
+//! The tab with the lowest total cost is chosen, where each technique has a cost and
+//! moving between holes costs extra.
+
+use crate::layout::{Layout, Technique};
+use crate::pitch::Pitch;
+use crate::tab::{Dialect, TabNote};
+use crate::{ChromaticScale, Error, Setup, Tuning};
+use itertools::Itertools;
+use std::fmt;
+
+/// harp keys with the spelling they are usually sold in
+pub const HARP_KEYS: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+
+/// most measures of a score written as sheet music by `lilypond::export` and
+/// `musicxml::export`
+pub const MAX_MEASURES: u32 = 10_000;
+
+/// a note with its start and length in quarter note beats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedNote {
+    pub pitch: Pitch,
+    pub start: f64,
+    pub duration: f64,
+}
+
+/// a melody with timing, e.g. read from a MIDI or MusicXML file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    pub title: Option<String>,
+    /// time signature, e.g. (6, 8)
+    pub time: (u32, u32),
+    pub notes: Vec<TimedNote>,
+}
+
+impl Score {
+    /// a score in 4/4 without a title
+    pub fn new(notes: Vec<TimedNote>) -> Score {
+        Score {
+            title: None,
+            time: (4, 4),
+            notes,
+        }
+    }
+
+    pub fn pitches(&self) -> Vec<Pitch> {
+        self.notes.iter().map(|note| note.pitch).collect()
+    }
+
+    /// length of a measure in quarter note beats
+    pub fn measure(&self) -> f64 {
+        self.time.0 as f64 * 4.0 / self.time.1 as f64
+    }
+
+    /// returns the number of measures up to the end of the last note, or `None` if a
+    /// note or measure has no finite length or the score is longer than `MAX_MEASURES`
+    /// measures, or than that many measures of 4/4
+    pub fn measures(&self) -> Option<u32> {
+        let measure = self.measure();
+        if !measure.is_finite() || measure <= 0.0 {
+            return None;
+        }
+        let mut end: f64 = 0.0;
+        for note in &self.notes {
+            if !note.start.is_finite() || !note.duration.is_finite() {
+                return None;
+            }
+            end = end.max(note.start + note.duration);
+        }
+        let measures = (end / measure).ceil();
+        if measures > MAX_MEASURES as f64 || measures * measure > MAX_MEASURES as f64 * 4.0 {
+            return None;
+        }
+        Some(measures as u32)
+    }
+}
+
+/// costs used to pick the best tab for a melody
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Costs {
+    /// plain blow or draw note
+    pub plain: u32,
+    /// per semitone of a draw or blow bend
+    pub bend: u32,
+    /// overblow or overdraw
+    pub overblow: u32,
+    /// per hole moved between two notes
+    pub hole_jump: u32,
+}
+
+impl Default for Costs {
+    fn default() -> Costs {
+        Costs {
+            plain: 0,
+            bend: 3,
+            overblow: 6,
+            hole_jump: 1,
+        }
+    }
+}
+
+impl Costs {
+    pub fn technique(&self, technique: Technique) -> u32 {
+        match technique {
+            Technique::Plain => self.plain,
+            Technique::Bend(n) | Technique::BlowBend(n) => self.bend.saturating_mul(n as u32),
+            Technique::Overblow | Technique::Overdraw => self.overblow,
+        }
+    }
+
+    fn jump(&self, from: &TabNote, to: &TabNote) -> u32 {
+        self.hole_jump
+            .saturating_mul((from.hole as i64 - to.hole as i64).unsigned_abs() as u32)
+    }
+}
+
+/// why a note of a melody can't be played
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unplayable {
+    BelowRange,
+    AboveRange,
+    /// in the range of the harp, but not on any hole
+    Missing,
+}
+
+impl fmt::Display for Unplayable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unplayable::BelowRange => write!(f, "below the range of the harp"),
+            Unplayable::AboveRange => write!(f, "above the range of the harp"),
+            Unplayable::Missing => write!(f, "not playable on this tuning"),
+        }
+    }
+}
+
+/// a note of a melody and the tab it is played with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TabbedNote {
+    pub pitch: Pitch,
+    pub tab: Result<TabNote, Unplayable>,
+}
+
+/// tab for a melody
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MelodyTab {
+    pub notes: Vec<TabbedNote>,
+    /// total cost of the playable notes
+    pub cost: u32,
+}
+
+impl MelodyTab {
+    /// prints the tab separated by spaces, notes that can't be played are shown as "X"
+    pub fn tab(&self, dialect: Dialect) -> String {
+        self.notes
+            .iter()
+            .map(|note| match note.tab {
+                Ok(tab) => tab.format(dialect),
+                Err(_) => String::from("X"),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// returns the index, pitch and reason of every note that can't be played
+    pub fn unplayable(&self) -> impl Iterator<Item = (usize, Pitch, Unplayable)> + '_ {
+        self.notes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, note)| note.tab.err().map(|reason| (i, note.pitch, reason)))
+    }
+}
+
+/// parses whitespace separated scientific pitch names like "G4 A4 B4 D5", bar lines (`|`) are skipped
+pub fn parse_melody(input: &str) -> Result<Vec<Pitch>, Error> {
+    input
+        .split_whitespace()
+        .filter(|token| *token != "|")
+        .map(|token| token.parse())
+        .collect()
+}
+
+/// finds the tab with the lowest cost for a melody
+pub fn melody_to_tab(melody: &[Pitch], layout: &Layout, costs: &Costs) -> MelodyTab {
+    let range = layout.range();
+    let candidates: Vec<Result<Vec<TabNote>, Unplayable>> = melody
+        .iter()
+        .map(|&pitch| {
+            let tabs: Vec<TabNote> = layout
+                .cells()
+                .filter(|cell| cell.pitch == pitch)
+                .map(TabNote::from)
+                .collect();
+            match range {
+                _ if !tabs.is_empty() => Ok(tabs),
+                Some((low, _)) if pitch < low => Err(Unplayable::BelowRange),
+                Some((_, high)) if pitch > high => Err(Unplayable::AboveRange),
+                _ => Err(Unplayable::Missing),
+            }
+        })
+        .collect();
+
+    // for every playable note, the lowest cost of the melody up to it for each
+    // candidate, and the candidate of the previous playable note that leads there
+    let mut steps: Vec<(usize, Vec<(u32, usize)>)> = Vec::new();
+    for (i, tabs) in candidates.iter().enumerate() {
+        let tabs = match tabs {
+            Ok(tabs) => tabs,
+            Err(_) => continue,
+        };
+        let step = tabs
+            .iter()
+            .map(|tab| {
+                let own = costs.technique(tab.technique);
+                match steps.last() {
+                    None => (own, 0),
+                    Some((prev, prev_costs)) => {
+                        let prev_tabs = candidates[*prev].as_ref().unwrap();
+                        prev_costs
+                            .iter()
+                            .zip(prev_tabs)
+                            .enumerate()
+                            .map(|(j, ((cost, _), prev_tab))| {
+                                let jump = costs.jump(prev_tab, tab);
+                                (cost.saturating_add(own).saturating_add(jump), j)
+                            })
+                            .min()
+                            .unwrap()
+                    }
+                }
+            })
+            .collect();
+        steps.push((i, step));
+    }
+
+    let mut chosen = vec![None; melody.len()];
+    let mut cost = 0;
+    let mut next = steps
+        .last()
+        .and_then(|(_, step)| step.iter().enumerate().min_by_key(|(_, (c, _))| *c))
+        .map(|(j, (c, _))| {
+            cost = *c;
+            j
+        });
+    for (i, step) in steps.iter().rev() {
+        if let Some(j) = next {
+            chosen[*i] = Some(candidates[*i].as_ref().unwrap()[j]);
+            next = Some(step[j].1);
+        }
+    }
+
+    let notes = melody
+        .iter()
+        .zip(candidates)
+        .zip(chosen)
+        .map(|((&pitch, candidates), tab)| TabbedNote {
+            pitch,
+            tab: candidates.map(|_| tab.unwrap()),
+        })
+        .collect();
+    MelodyTab { notes, cost }
+}
+
+/// a harp key and position to play a melody in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub key: ChromaticScale,
+    pub position: usize,
+    /// octaves the melody has to be moved by to fit the harp
+    pub octaves: i32,
+    pub tab: MelodyTab,
+}
+
+/// returns the position that has `tonic` as its root on a harp in `key`, both pitch classes
+pub fn position_of(tonic: usize, key: usize) -> usize {
+    (tonic % 12 + 12 - key % 12) * 7 % 12 + 1
+}
+
+/// tabs a melody on a harp in `key`, moving it by octaves only if that makes more notes
+/// playable. `tonic` is the pitch class of the root of the melody, used to find the position.
+pub fn fit_key(
+    melody: &[Pitch],
+    tuning: &Tuning,
+    key: &ChromaticScale,
+    tonic: usize,
+    costs: &Costs,
+) -> Suggestion {
+    let setup = Setup {
+        scale: None,
+        position: 1,
+    };
+    let layout = tuning.layout(key, &setup);
+    (-2..=2)
+        .map(|octaves| {
+            let moved: Vec<Pitch> = melody.iter().map(|p| Pitch(p.0 + 12 * octaves)).collect();
+            Suggestion {
+                position: position_of(tonic, key.root_pitch_class()),
+                key: key.clone(),
+                octaves,
+                tab: melody_to_tab(&moved, &layout, costs),
+            }
+        })
+        .min_by_key(rank)
+        .unwrap()
+}
+
+/// finds the harp key with the fewest unplayable notes for a melody, then the one moving it
+/// by the fewest octaves and then the lowest cost, see `fit_key`. The last note of the
+/// melody is taken as its tonic.
+pub fn suggest_key(melody: &[Pitch], tuning: &Tuning, costs: &Costs) -> Option<Suggestion> {
+    let tonic = melody.last()?.pitch_class();
+    rank_keys(melody, tuning, tonic, costs).into_iter().next()
+}
+
+/// tabs a melody on a harp in every key, best first, see `suggest_key`
+pub fn rank_keys(
+    melody: &[Pitch],
+    tuning: &Tuning,
+    tonic: usize,
+    costs: &Costs,
+) -> Vec<Suggestion> {
+    HARP_KEYS
+        .iter()
+        .map(|key| {
+            let key = ChromaticScale::new(key, None).unwrap();
+            fit_key(melody, tuning, &key, tonic, costs)
+        })
+        .sorted_by_key(rank)
+        .collect()
+}
+
+fn rank(suggestion: &Suggestion) -> (usize, i32, u32) {
+    (
+        suggestion.tab.unplayable().count(),
+        suggestion.octaves.abs(),
+        suggestion.tab.cost,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChromaticScale, Setup, Tuning};
+    use std::convert::TryFrom;
+
+    fn layout(key: &str) -> Layout {
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        Tuning::default().layout(&ChromaticScale::new(key, None).unwrap(), &setup)
+    }
+
+    fn tab(melody: &str, layout: &Layout, costs: &Costs) -> String {
+        let melody = parse_melody(melody).unwrap();
+        melody_to_tab(&melody, layout, costs).tab(Dialect::Apostrophe)
+    }
+
+    #[test]
+    fn test_parse_melody() {
+        assert_eq!(
+            parse_melody("G4 | Bb3 C#5 B#3 Cb4 C-1").unwrap(),
+            vec![
+                Pitch(67),
+                Pitch(58),
+                Pitch(73),
+                Pitch(60),
+                Pitch(59),
+                Pitch(0)
+            ]
+        );
+        for melody in [
+            "G",
+            "H4",
+            "4",
+            "g4",
+            "Gx4",
+            "C999999999",
+            "B#2147483647",
+            "Cb-1",
+            "G#9",
+        ] {
+            assert!(matches!(parse_melody(melody), Err(Error::InvalidNote(_))));
+        }
+    }
+
+    #[test]
+    fn test_melody_to_tab() {
+        let c = layout("C");
+        let costs = Costs::default();
+        assert_eq!(
+            tab("C4 D4 E4 F4 E4 D4 C4", &c, &costs),
+            "1 -1 2 -2'' 2 -1 1"
+        );
+        // G4 is played as 3 next to 4, and as -2 next to -1
+        assert_eq!(tab("C5 G4 C5", &c, &costs), "4 3 4");
+        assert_eq!(tab("D4 G4 D4", &c, &costs), "-1 -2 -1");
+        assert_eq!(tab("C6 D#6 F#5", &c, &costs), "7 8' 5o");
+
+        let costs = Costs {
+            bend: 2,
+            overblow: 10,
+            ..Costs::default()
+        };
+        assert_eq!(costs.technique(Technique::Plain), 0);
+        assert_eq!(costs.technique(Technique::Bend(2)), 4);
+        assert_eq!(costs.technique(Technique::BlowBend(1)), 2);
+        assert_eq!(costs.technique(Technique::Overdraw), 10);
+        assert_eq!(
+            melody_to_tab(&parse_melody("C4 G4 F4").unwrap(), &c, &costs).cost,
+            1 + 4
+        );
+
+        // a G harp starts on G3
+        assert_eq!(tab("G3 D4 G3", &layout("G"), &costs), "1 -2 1");
+
+        // costs stop at u32::MAX instead of overflowing
+        let costs = Costs {
+            bend: 4_000_000_000,
+            ..Costs::default()
+        };
+        let res = melody_to_tab(&parse_melody("C4 Eb4 Bb4 Db5").unwrap(), &c, &costs);
+        assert_eq!(res.cost, u32::MAX);
+    }
+
+    #[test]
+    fn test_unplayable() {
+        let c = layout("C");
+        let melody = parse_melody("B3 C4 C#4 A6 D7").unwrap();
+        let res = melody_to_tab(&melody, &c, &Costs::default());
+        assert_eq!(res.tab(Dialect::Apostrophe), "X 1 -1' -10 X");
+        assert_eq!(
+            res.unplayable().collect::<Vec<_>>(),
+            vec![
+                (0, Pitch(59), Unplayable::BelowRange),
+                (4, Pitch(98), Unplayable::AboveRange)
+            ]
+        );
+
+        let country: Tuning =
+            Tuning::try_from("C E G C E G C E G C\nD G B D F# A B D F A\n").unwrap();
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        let layout = country.layout(&ChromaticScale::new("C", None).unwrap(), &setup);
+        let res = melody_to_tab(&parse_melody("F5 F#5").unwrap(), &layout, &Costs::default());
+        assert_eq!(res.tab(Dialect::Apostrophe), "-5' -5");
+        assert_eq!(res.unplayable().count(), 0);
+        assert!(melody_to_tab(&[], &layout, &Costs::default())
+            .notes
+            .is_empty());
+
+        let no_overblow =
+            Tuning::try_from("version: 1\nblow: C E G C\ndraw: D G B D\noverride: overblows 1 -\n")
+                .unwrap();
+        let layout = no_overblow.layout(&ChromaticScale::new("C", None).unwrap(), &setup);
+        let res = melody_to_tab(
+            &parse_melody("D4 D#4 E4").unwrap(),
+            &layout,
+            &Costs::default(),
+        );
+        assert_eq!(res.tab(Dialect::Apostrophe), "-1 X 2");
+        assert_eq!(
+            res.unplayable().collect::<Vec<_>>(),
+            vec![(1, Pitch(63), Unplayable::Missing)]
+        );
+    }
+
+    #[test]
+    fn test_suggest_key() {
+        assert_eq!(position_of(7, 0), 2);
+        assert_eq!(position_of(2, 0), 3);
+        assert_eq!(position_of(9, 0), 4);
+        assert_eq!(position_of(5, 0), 12);
+        assert_eq!(position_of(2, 7), 2);
+
+        // a blues lick in D fits a G harp in 2nd position
+        let melody = parse_melody("D4 F4 G4 G#4 A4 C5 D5").unwrap();
+        let res = suggest_key(&melody, &Tuning::default(), &Costs::default()).unwrap();
+        assert_eq!(res.key, ChromaticScale::new("G", None).unwrap());
+        assert_eq!(res.position, 2);
+        assert_eq!(res.octaves, 0);
+        assert_eq!(res.tab.unplayable().count(), 0);
+
+        // too low for any harp
+        let melody = parse_melody("C2 E2 G2").unwrap();
+        let res = suggest_key(&melody, &Tuning::default(), &Costs::default()).unwrap();
+        assert_eq!(res.octaves, 2);
+        assert_eq!(res.tab.unplayable().count(), 0);
+
+        assert!(suggest_key(&[], &Tuning::default(), &Costs::default()).is_none());
+
+        // an F harp plays it without bends an octave higher, but an A harp needs no octave
+        let melody = parse_melody("A3 A3 C4").unwrap();
+        let res = suggest_key(&melody, &Tuning::default(), &Costs::default()).unwrap();
+        assert_eq!(res.key, ChromaticScale::new("A", None).unwrap());
+        assert_eq!((res.octaves, res.tab.cost), (0, 6));
+        let f = fit_key(
+            &melody,
+            &Tuning::default(),
+            &ChromaticScale::new("F", None).unwrap(),
+            0,
+            &Costs::default(),
+        );
+        assert_eq!((f.octaves, f.tab.cost), (1, 0));
+    }
+
+    #[test]
+    fn test_rank_keys() {
+        let melody = parse_melody("D4 F4 G4 G#4 A4 C5 D5").unwrap();
+        let ranked = rank_keys(&melody, &Tuning::default(), 2, &Costs::default());
+        assert_eq!(ranked.len(), 12);
+        assert_eq!(ranked[0].key, ChromaticScale::new("G", None).unwrap());
+        assert!(ranked
+            .windows(2)
+            .all(|pair| pair[0].tab.unplayable().count() <= pair[1].tab.unplayable().count()));
+    }
+}
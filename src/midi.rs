@@ -0,0 +1,250 @@
+//! Reading melodies from Standard MIDI Files.
+//!
+//! Notes are collected per track and channel (a voice). A voice is monophonic if none
+//! of its notes overlap; for other voices the highest note sounding at any time is used.
+
+use crate::melody::TimedNote;
+use crate::pitch::Pitch;
+use crate::Error;
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+/// channel used for drums in General MIDI, counting from 0
+const DRUM_CHANNEL: u8 = 9;
+
+/// the notes of one channel in one track
+#[derive(Debug, Clone, PartialEq)]
+pub struct Voice {
+    /// track number, counting from 0
+    pub track: usize,
+    /// channel number, counting from 0
+    pub channel: u8,
+    /// no two notes sound at the same time
+    pub monophonic: bool,
+    /// notes sorted by start
+    pub notes: Vec<TimedNote>,
+}
+
+impl Voice {
+    /// returns the melody, the highest note at any time if the voice isn't monophonic
+    pub fn melody(&self) -> Vec<TimedNote> {
+        let mut melody: Vec<TimedNote> = Vec::new();
+        for note in &self.notes {
+            match melody.last_mut() {
+                Some(last) if last.start + last.duration > note.start => {
+                    if note.pitch <= last.pitch {
+                        continue;
+                    }
+                    if last.start == note.start {
+                        *last = *note;
+                        continue;
+                    }
+                    last.duration = note.start - last.start;
+                }
+                _ => {}
+            }
+            melody.push(*note);
+        }
+        melody
+    }
+}
+
+/// a Standard MIDI File split into voices
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiFile {
+    /// first time signature of the file
+    pub time: Option<(u32, u32)>,
+    pub voices: Vec<Voice>,
+}
+
+impl MidiFile {
+    /// reads the voices of a file. Files timed in SMPTE frames use seconds instead of beats.
+    pub fn parse(bytes: &[u8]) -> Result<MidiFile, Error> {
+        let smf = Smf::parse(bytes).map_err(|e| Error::Midi(e.to_string()))?;
+        let ticks_per_beat = match smf.header.timing {
+            Timing::Metrical(ticks) => ticks.as_int() as f64,
+            Timing::Timecode(fps, ticks) => fps.as_f32() as f64 * ticks as f64,
+        };
+
+        let mut time = None;
+        let mut voices = Vec::new();
+        for (track, events) in smf.tracks.iter().enumerate() {
+            // (channel, key, start tick, end tick), end is None while the note is held
+            let mut notes: Vec<(u8, u8, u64, Option<u64>)> = Vec::new();
+            let mut tick = 0;
+            for event in events {
+                tick += event.delta.as_int() as u64;
+                let (channel, message) = match event.kind {
+                    TrackEventKind::Midi { channel, message } => (channel.as_int(), message),
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(beats, unit, _, _)) => {
+                        // the unit is a power of 2, ignore signatures that don't fit
+                        if let Some(unit) = 1u32.checked_shl(unit as u32) {
+                            if beats > 0 {
+                                time.get_or_insert((beats as u32, unit));
+                            }
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+                let (key, on) = match message {
+                    MidiMessage::NoteOn { key, vel } => (key.as_int(), vel > 0),
+                    MidiMessage::NoteOff { key, .. } => (key.as_int(), false),
+                    _ => continue,
+                };
+                if on {
+                    notes.push((channel, key, tick, None));
+                } else if let Some(note) = notes
+                    .iter_mut()
+                    .find(|(c, k, _, end)| *c == channel && *k == key && end.is_none())
+                {
+                    note.3 = Some(tick);
+                }
+            }
+
+            let mut channels: Vec<u8> = notes.iter().map(|note| note.0).collect();
+            channels.sort_unstable();
+            channels.dedup();
+            for channel in channels {
+                let mut voice: Vec<TimedNote> = notes
+                    .iter()
+                    .filter(|note| note.0 == channel)
+                    .map(|&(_, key, start, end)| TimedNote {
+                        pitch: Pitch(key as i32),
+                        start: start as f64 / ticks_per_beat,
+                        duration: (end.unwrap_or(tick) - start) as f64 / ticks_per_beat,
+                    })
+                    .collect();
+                voice.sort_by(|a, b| a.start.total_cmp(&b.start).then(b.pitch.cmp(&a.pitch)));
+                let monophonic = voice
+                    .windows(2)
+                    .all(|pair| pair[0].start + pair[0].duration <= pair[1].start);
+                voices.push(Voice {
+                    track,
+                    channel,
+                    monophonic,
+                    notes: voice,
+                });
+            }
+        }
+        Ok(MidiFile { time, voices })
+    }
+
+    pub fn voice(&self, track: usize, channel: u8) -> Option<&Voice> {
+        self.voices
+            .iter()
+            .find(|voice| voice.track == track && voice.channel == channel)
+    }
+
+    /// returns the monophonic voice with the most notes, or the voice with the most notes
+    /// if none is monophonic. Drums are skipped.
+    pub fn default_voice(&self) -> Option<&Voice> {
+        self.voices
+            .iter()
+            .filter(|voice| voice.channel != DRUM_CHANNEL)
+            .max_by_key(|voice| (voice.monophonic, voice.notes.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{Format, Header, TrackEvent};
+
+    fn note(delta: u32, channel: u8, key: u8, on: bool) -> TrackEvent<'static> {
+        let key = key.into();
+        let message = if on {
+            MidiMessage::NoteOn {
+                key,
+                vel: 100.into(),
+            }
+        } else {
+            MidiMessage::NoteOff { key, vel: 0.into() }
+        };
+        TrackEvent {
+            delta: delta.into(),
+            kind: TrackEventKind::Midi {
+                channel: channel.into(),
+                message,
+            },
+        }
+    }
+
+    fn write(tracks: Vec<Vec<TrackEvent<'static>>>) -> Vec<u8> {
+        let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(480.into())));
+        smf.tracks = tracks;
+        let mut bytes = Vec::new();
+        smf.write_std(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_parse() {
+        let melody = vec![
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::TimeSignature(3, 2, 24, 8)),
+            },
+            note(0, 0, 67, true),
+            note(480, 0, 67, false),
+            note(0, 0, 69, true),
+            note(240, 0, 69, false),
+        ];
+        // a chord of C4 and E4 followed by G4, overlapping the chord
+        let chords = vec![
+            note(0, 1, 60, true),
+            note(0, 1, 64, true),
+            note(480, 1, 67, true),
+            note(240, 1, 60, false),
+            note(0, 1, 64, false),
+            note(240, 1, 67, false),
+        ];
+        let drums = vec![
+            note(0, 9, 36, true),
+            note(0, 9, 36, false),
+            note(0, 9, 38, true),
+            note(0, 9, 38, false),
+            note(0, 9, 42, true),
+            note(0, 9, 42, false),
+        ];
+        let file = MidiFile::parse(&write(vec![melody, chords, drums])).unwrap();
+        assert_eq!(file.voices.len(), 3);
+        assert_eq!(file.time, Some((3, 4)));
+
+        let voice = file.default_voice().unwrap();
+        assert_eq!((voice.track, voice.channel, voice.monophonic), (0, 0, true));
+        assert_eq!(
+            voice.melody(),
+            vec![
+                TimedNote {
+                    pitch: Pitch(67),
+                    start: 0.0,
+                    duration: 1.0
+                },
+                TimedNote {
+                    pitch: Pitch(69),
+                    start: 1.0,
+                    duration: 0.5
+                }
+            ]
+        );
+
+        let chords = file.voice(1, 1).unwrap();
+        assert!(!chords.monophonic);
+        let melody: Vec<(Pitch, f64, f64)> = chords
+            .melody()
+            .iter()
+            .map(|note| (note.pitch, note.start, note.duration))
+            .collect();
+        assert_eq!(melody, vec![(Pitch(64), 0.0, 1.0), (Pitch(67), 1.0, 1.0)]);
+
+        assert!(matches!(MidiFile::parse(b"MThd"), Err(Error::Midi(_))));
+
+        // a unit of 2^40 doesn't fit, the next time signature is used
+        let signature = |unit| TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::TimeSignature(6, unit, 24, 8)),
+        };
+        let file = MidiFile::parse(&write(vec![vec![signature(40), signature(3)]])).unwrap();
+        assert_eq!(file.time, Some((6, 8)));
+    }
+}
@@ -0,0 +1,440 @@
+//! Reading melodies from MusicXML and writing them with tab.
+//!
+//! Only partwise scores are read. Of a part, the first voice is used, and the highest
+//! note of chords. Tied notes are joined and grace notes are skipped.
+//!
+//! Written scores have a single part with the tab of every note as its lyric.
+
+use crate::melody::{MelodyTab, Score, TimedNote};
+use crate::pitch::Pitch;
+use crate::tab::Dialect;
+use crate::{pitch_class, ChromaticScale, Error};
+use roxmltree::{Document, Node, ParsingOptions};
+use std::fmt::Write;
+
+/// divisions of a quarter note in written scores
+const DIVISIONS: u32 = 480;
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|n| n.text()).map(str::trim)
+}
+
+fn number<T: std::str::FromStr>(node: Node, name: &str) -> Result<T, Error> {
+    let text =
+        child_text(node, name).ok_or_else(|| Error::MusicXml(format!("missing <{}>", name)))?;
+    text.parse()
+        .map_err(|_| Error::MusicXml(format!("invalid <{}> \"{}\"", name, text)))
+}
+
+/// reads the <duration> of a node in quarter notes
+fn read_duration(node: Node, divisions: f64) -> Result<f64, Error> {
+    let duration = number::<f64>(node, "duration")? / divisions;
+    if duration.is_finite() {
+        Ok(duration)
+    } else {
+        Err(Error::MusicXml(format!(
+            "invalid <duration> \"{}\"",
+            child_text(node, "duration").unwrap_or("")
+        )))
+    }
+}
+
+fn read_pitch(pitch: Node) -> Result<Pitch, Error> {
+    let step = child_text(pitch, "step").unwrap_or("");
+    let pitch_class =
+        pitch_class(step).ok_or_else(|| Error::MusicXml(format!("invalid <step> \"{}\"", step)))?;
+    let alter = match child(pitch, "alter") {
+        Some(_) => number::<f64>(pitch, "alter")?.round() as i32,
+        None => 0,
+    };
+    let octave: i32 = number(pitch, "octave")?;
+    Ok(Pitch((octave + 1) * 12 + pitch_class as i32 + alter))
+}
+
+/// reads the part with the given id, or the first part, of a partwise MusicXML score
+pub fn import(xml: &str, part: Option<&str>) -> Result<Score, Error> {
+    // MusicXML files usually have a DOCTYPE
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let doc =
+        Document::parse_with_options(xml, options).map_err(|e| Error::MusicXml(e.to_string()))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("score-partwise") {
+        return Err(Error::MusicXml(format!(
+            "expected <score-partwise>, found <{}>",
+            root.tag_name().name()
+        )));
+    }
+
+    let title = child(root, "work")
+        .and_then(|work| child_text(work, "work-title"))
+        .or_else(|| child_text(root, "movement-title"))
+        .map(String::from);
+    let part = root
+        .children()
+        .filter(|n| n.has_tag_name("part"))
+        .find(|n| part.is_none() || n.attribute("id") == part)
+        .ok_or_else(|| match part {
+            Some(id) => Error::MusicXml(format!("part \"{}\" not found", id)),
+            None => Error::MusicXml(String::from("no parts found")),
+        })?;
+
+    let mut time = None;
+    let mut divisions: f64 = 1.0;
+    let mut voice = None;
+    // position in the part in quarter notes
+    let mut position = 0.0;
+    let mut last_start = 0.0;
+    let mut notes: Vec<TimedNote> = Vec::new();
+    for measure in part.children().filter(|n| n.has_tag_name("measure")) {
+        for node in measure.children() {
+            match node.tag_name().name() {
+                "attributes" => {
+                    if child(node, "divisions").is_some() {
+                        divisions = number(node, "divisions")?;
+                        if !divisions.is_finite() || divisions <= 0.0 {
+                            return Err(Error::MusicXml(format!(
+                                "invalid <divisions> \"{}\"",
+                                child_text(node, "divisions").unwrap_or("")
+                            )));
+                        }
+                    }
+                    if let Some(t) = child(node, "time") {
+                        if time.is_none() {
+                            time = Some((number(t, "beats")?, number(t, "beat-type")?));
+                        }
+                    }
+                }
+                "backup" => position -= read_duration(node, divisions)?,
+                "forward" => position += read_duration(node, divisions)?,
+                "note" => {
+                    if child(node, "grace").is_some() {
+                        continue;
+                    }
+                    let duration = read_duration(node, divisions)?;
+                    let is_chord = child(node, "chord").is_some();
+                    if !is_chord {
+                        last_start = position;
+                        position += duration;
+                    }
+                    let start = last_start;
+
+                    let this_voice = child_text(node, "voice").unwrap_or("1");
+                    if *voice.get_or_insert(this_voice) != this_voice {
+                        continue;
+                    }
+                    let pitch = match child(node, "pitch") {
+                        Some(pitch) => read_pitch(pitch)?,
+                        None => continue,
+                    };
+                    let note = TimedNote {
+                        pitch,
+                        start,
+                        duration,
+                    };
+
+                    let tie_stop = node
+                        .children()
+                        .any(|n| n.has_tag_name("tie") && n.attribute("type") == Some("stop"));
+                    match notes.last_mut() {
+                        Some(last) if is_chord && last.start == start => {
+                            if pitch > last.pitch {
+                                *last = note;
+                            }
+                        }
+                        Some(last) if tie_stop && last.pitch == pitch => {
+                            last.duration += duration;
+                        }
+                        _ => notes.push(note),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(Score {
+        title,
+        time: time.unwrap_or((4, 4)),
+        notes,
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// returns the number of sharps, or flats if negative, of the major key signature
+fn fifths(key: &ChromaticScale) -> i32 {
+    let fifths = (key.root_pitch_class() * 7 % 12) as i32;
+    let is_flat = key.0.iter().any(|note| note.ends_with('b'));
+    if is_flat && fifths != 0 {
+        fifths - 12
+    } else {
+        fifths
+    }
+}
+
+/// writes a score as MusicXML with the tab of every note as its lyric. Notes are spelled
+/// like the notes of the harp key, whose major key signature is used, and notes
+/// crossing a barline are split into tied notes. Returns an error for scores longer
+/// than `MAX_MEASURES`.
+pub fn export(
+    score: &Score,
+    tab: &MelodyTab,
+    key: &ChromaticScale,
+    dialect: Dialect,
+) -> Result<String, Error> {
+    if score.measures().is_none() {
+        return Err(Error::ScoreTooLong);
+    }
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    out.push_str("<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \"http://www.musicxml.org/dtds/partwise.dtd\">\n");
+    out.push_str("<score-partwise version=\"4.0\">\n");
+    if let Some(title) = &score.title {
+        let _ = writeln!(
+            out,
+            "  <work>\n    <work-title>{}</work-title>\n  </work>",
+            escape(title)
+        );
+    }
+    out.push_str("  <part-list>\n    <score-part id=\"P1\">\n      <part-name>Harmonica</part-name>\n    </score-part>\n  </part-list>\n");
+    out.push_str("  <part id=\"P1\">\n");
+
+    // notes and rests as (start, end, note index) in divisions
+    let to_divisions = |beats: f64| (beats * DIVISIONS as f64).round().max(0.0) as u32;
+    let mut events: Vec<(u32, u32, Option<usize>)> = Vec::new();
+    let mut end: u32 = 0;
+    for (i, note) in score.notes.iter().enumerate() {
+        let start = to_divisions(note.start).max(end);
+        if start > end {
+            events.push((end, start, None));
+        }
+        end = start
+            .checked_add(to_divisions(note.duration).max(1))
+            .ok_or(Error::ScoreTooLong)?;
+        events.push((start, end, Some(i)));
+    }
+    let measure = to_divisions(score.measure()).max(1);
+    let measures = end.div_ceil(measure);
+    if measures.checked_mul(measure).is_none() {
+        return Err(Error::ScoreTooLong);
+    }
+
+    for number in 0..measures.max(1) {
+        let _ = writeln!(out, "    <measure number=\"{}\">", number + 1);
+        if number == 0 {
+            let _ = writeln!(
+                out,
+                "      <attributes>\n        <divisions>{}</divisions>\n        <key>\n          <fifths>{}</fifths>\n        </key>\n        <time>\n          <beats>{}</beats>\n          <beat-type>{}</beat-type>\n        </time>\n        <clef>\n          <sign>G</sign>\n          <line>2</line>\n        </clef>\n      </attributes>",
+                DIVISIONS,
+                fifths(key),
+                score.time.0,
+                score.time.1
+            );
+        }
+
+        let (bar_start, bar_end) = (number * measure, (number + 1) * measure);
+        let mut filled = bar_start;
+        for &(start, end, note) in &events {
+            let (clipped_start, clipped_end) = (start.max(bar_start), end.min(bar_end));
+            if clipped_start >= clipped_end {
+                continue;
+            }
+            filled = clipped_end;
+            let duration = clipped_end - clipped_start;
+            let note = match note {
+                Some(i) => i,
+                None => {
+                    let _ = writeln!(
+                        out,
+                        "      <note>\n        <rest/>\n        <duration>{}</duration>\n      </note>",
+                        duration
+                    );
+                    continue;
+                }
+            };
+
+            let pitch = score.notes[note].pitch;
+            let name = key.note_name(pitch.pitch_class());
+            let alter = match name.get(1..) {
+                Some("#") => 1,
+                Some("b") => -1,
+                _ => 0,
+            };
+            let _ = writeln!(
+                out,
+                "      <note>\n        <pitch>\n          <step>{}</step>",
+                &name[..1]
+            );
+            if alter != 0 {
+                let _ = writeln!(out, "          <alter>{}</alter>", alter);
+            }
+            let _ = writeln!(
+                out,
+                "          <octave>{}</octave>\n        </pitch>\n        <duration>{}</duration>",
+                Pitch(pitch.0 - alter).octave(),
+                duration
+            );
+
+            let ties_from = clipped_start > start;
+            let ties_to = clipped_end < end;
+            if ties_from {
+                out.push_str("        <tie type=\"stop\"/>\n");
+            }
+            if ties_to {
+                out.push_str("        <tie type=\"start\"/>\n");
+            }
+            if ties_from || ties_to {
+                out.push_str("        <notations>\n");
+                if ties_from {
+                    out.push_str("          <tied type=\"stop\"/>\n");
+                }
+                if ties_to {
+                    out.push_str("          <tied type=\"start\"/>\n");
+                }
+                out.push_str("        </notations>\n");
+            }
+            if !ties_from {
+                if let Some(Ok(tab)) = tab.notes.get(note).map(|n| n.tab) {
+                    let _ = writeln!(
+                        out,
+                        "        <lyric number=\"1\">\n          <syllabic>single</syllabic>\n          <text>{}</text>\n        </lyric>",
+                        escape(&tab.format(dialect))
+                    );
+                }
+            }
+            out.push_str("      </note>\n");
+        }
+        if filled < bar_end && number + 1 < measures {
+            let _ = writeln!(
+                out,
+                "      <note>\n        <rest/>\n        <duration>{}</duration>\n      </note>",
+                bar_end - filled
+            );
+        }
+        out.push_str("    </measure>\n");
+    }
+    out.push_str("  </part>\n</score-partwise>\n");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::melody::{melody_to_tab, Costs};
+    use crate::{Setup, Tuning};
+
+    const SCORE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<score-partwise version="3.1">
+  <movement-title>Test &amp; Tune</movement-title>
+  <part-list><score-part id="P1"/><score-part id="P2"/></part-list>
+  <part id="P1">
+    <measure number="1">
+      <attributes>
+        <divisions>2</divisions>
+        <time><beats>3</beats><beat-type>4</beat-type></time>
+      </attributes>
+      <note><grace/><pitch><step>A</step><octave>4</octave></pitch><voice>1</voice></note>
+      <note><pitch><step>G</step><octave>4</octave></pitch><duration>2</duration><voice>1</voice></note>
+      <note><chord/><pitch><step>B</step><octave>4</octave></pitch><duration>2</duration><voice>1</voice></note>
+      <note><rest/><duration>2</duration><voice>1</voice></note>
+      <note><pitch><step>B</step><alter>-1</alter><octave>4</octave></pitch><duration>2</duration><voice>1</voice><tie type="start"/></note>
+      <backup><duration>6</duration></backup>
+      <note><pitch><step>C</step><octave>3</octave></pitch><duration>6</duration><voice>2</voice></note>
+    </measure>
+    <measure number="2">
+      <note><pitch><step>B</step><alter>-1</alter><octave>4</octave></pitch><duration>1</duration><voice>1</voice><tie type="stop"/></note>
+      <note><pitch><step>C</step><alter>1</alter><octave>5</octave></pitch><duration>1</duration><voice>1</voice></note>
+    </measure>
+  </part>
+  <part id="P2">
+    <measure number="1">
+      <note><pitch><step>C</step><octave>4</octave></pitch><duration>1</duration></note>
+    </measure>
+  </part>
+</score-partwise>"#;
+
+    fn notes(score: &Score) -> Vec<(i32, f64, f64)> {
+        score
+            .notes
+            .iter()
+            .map(|note| (note.pitch.0, note.start, note.duration))
+            .collect()
+    }
+
+    #[test]
+    fn test_import() {
+        let score = import(SCORE, None).unwrap();
+        assert_eq!(score.title.as_deref(), Some("Test & Tune"));
+        assert_eq!(score.time, (3, 4));
+        assert_eq!(
+            notes(&score),
+            vec![(71, 0.0, 1.0), (70, 2.0, 1.5), (73, 3.5, 0.5)]
+        );
+
+        assert_eq!(
+            notes(&import(SCORE, Some("P2")).unwrap()),
+            vec![(60, 0.0, 1.0)]
+        );
+        for (xml, part) in [
+            (SCORE, Some("P3")),
+            ("<score-timewise/>", None),
+            ("<score-partwise", None),
+            ("<score-partwise><part><measure><note><duration>x</duration></note></measure></part></score-partwise>", None),
+            ("<score-partwise><part><measure><attributes><divisions>0</divisions></attributes></measure></part></score-partwise>", None),
+            ("<score-partwise><part><measure><note><duration>inf</duration></note></measure></part></score-partwise>", None),
+        ] {
+            assert!(matches!(import(xml, part), Err(Error::MusicXml(_))));
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let score = import(SCORE, None).unwrap();
+        let key = ChromaticScale::new("F", None).unwrap();
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        let layout = Tuning::default().layout(&key, &setup);
+        let tab = melody_to_tab(&score.pitches(), &layout, &Costs::default());
+        let xml = export(&score, &tab, &key, Dialect::Apostrophe).unwrap();
+
+        assert!(xml.contains("<fifths>-1</fifths>"));
+        assert!(xml.contains("<work-title>Test &amp; Tune</work-title>"));
+        // Bb4 and Db5 on an F harp
+        assert!(xml.contains("<text>-2''</text>"));
+        assert!(xml.contains("<text>-3'''</text>"));
+        assert!(xml.contains("<alter>-1</alter>"));
+        assert_eq!(xml.matches("<lyric").count(), 3);
+        // Bb4 crosses the barline
+        assert_eq!(xml.matches("<tie type=\"start\"/>").count(), 1);
+
+        let read = import(&xml, None).unwrap();
+        assert_eq!(read.title, score.title);
+        assert_eq!(notes(&read), notes(&score));
+
+        let mut long = score.clone();
+        long.notes[0].duration = 1e12;
+        assert!(matches!(
+            export(&long, &tab, &key, Dialect::Apostrophe),
+            Err(Error::ScoreTooLong)
+        ));
+        long.notes[0].duration = f64::INFINITY;
+        assert!(matches!(
+            export(&long, &tab, &key, Dialect::Apostrophe),
+            Err(Error::ScoreTooLong)
+        ));
+    }
+}
@@ -0,0 +1,92 @@
+//! Matching of tuning and scale names.
+//!
+//! Names are compared ignoring case, and spaces, `_` and `-` are the same, so
+//! "paddy_richter" and "Paddy-Richter" both find "paddy richter". Names that aren't
+//! found get suggestions of the names closest by edit distance.
+
+/// returns `name` in lowercase with `_` and `-` replaced by spaces and runs of spaces
+/// joined, e.g. "Minor_Pentatonic" -> "minor pentatonic"
+pub fn normalize(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '-')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// returns whether two names are the same after `normalize`
+pub fn matches(a: &str, b: &str) -> bool {
+    a == b || normalize(a) == normalize(b)
+}
+
+/// returns the number of characters to insert, delete or replace to turn `a` into `b`
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // distances from the first i characters of `a` to the first j characters of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replace = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replace.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// returns up to 3 of `candidates` closest to `name`, closest first. Candidates further
+/// away than 2 edits, or a third of their length if that is more, are left out.
+pub fn suggest<'a, I>(name: &str, candidates: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let name = normalize(name);
+    let mut close: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .map(|candidate| (levenshtein(&name, &normalize(candidate)), candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.chars().count() / 3).max(2))
+        .collect();
+    close.sort();
+    close.dedup_by(|a, b| a.1 == b.1);
+    close
+        .into_iter()
+        .take(3)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("paddy_richter"), "paddy richter");
+        assert_eq!(normalize("Minor-Pentatonic"), "minor pentatonic");
+        assert_eq!(normalize(" lucky 13  diminished "), "lucky 13 diminished");
+        assert!(matches("Wilde_Tuning", "wilde tuning"));
+        assert!(!matches("powerdraw", "power draw"));
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("richter", ""), 7);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("richter", "richtre"), 2);
+        assert_eq!(levenshtein("blues", "blues"), 0);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let scales = ["blues", "minor blues", "major blues", "dorian", "minor"];
+        assert_eq!(suggest("bluse", scales), vec!["blues"]);
+        assert_eq!(
+            suggest("Minor_Blues", scales),
+            vec!["minor blues", "major blues"]
+        );
+        assert!(suggest("xyz", scales).is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{pitch_class, ChromaticScale, Error};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// frequency of A4 in Hz used unless another reference is chosen
+pub const DEFAULT_A4: f64 = 440.0;
+
+/// an absolute pitch, stored as a MIDI note number (60 is C4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Pitch(pub i32);
+
+impl Pitch {
+    /// returns the pitch of hole 1 blow on a standard harp in the key with the
+    /// given pitch class. Harps in G to B start in the 3rd octave (G3), harps in
+    /// C to F# in the 4th (C4).
+    pub fn key_base(root_pitch_class: usize) -> Pitch {
+        let root_pitch_class = (root_pitch_class % 12) as i32;
+        if root_pitch_class >= 7 {
+            Pitch(48 + root_pitch_class)
+        } else {
+            Pitch(60 + root_pitch_class)
+        }
+    }
+
+    pub fn midi(self) -> i32 {
+        self.0
+    }
+
+    /// pitch class, 0 is C
+    pub fn pitch_class(self) -> usize {
+        self.0.rem_euclid(12) as usize
+    }
+
+    /// octave number in scientific pitch notation, C4 is middle C
+    pub fn octave(self) -> i32 {
+        self.0.div_euclid(12) - 1
+    }
+
+    /// returns the scientific pitch name, e.g. "C#4", spelled like the notes of `scale`
+    pub fn name(self, scale: &ChromaticScale) -> String {
+        format!("{}{}", scale.note_name(self.pitch_class()), self.octave())
+    }
+
+    /// returns the equal tempered frequency in Hz for the given A4 reference
+    pub fn frequency(self, a4: f64) -> f64 {
+        a4 * 2f64.powf((self.0 - 69) as f64 / 12.0)
+    }
+}
+
+impl FromStr for Pitch {
+    type Err = Error;
+
+    /// parses scientific pitch names like "G4", "Bb3" or "C#5", in the MIDI range C-1 to G9
+    fn from_str(s: &str) -> Result<Pitch, Error> {
+        let invalid = || Error::InvalidNote(s.to_string());
+        let split = s
+            .find(|c: char| c.is_ascii_digit() || c == '-')
+            .ok_or_else(invalid)?;
+        let (name, octave) = s.split_at(split);
+        let pitch_class = pitch_class(name).ok_or_else(invalid)? as i32;
+        let octave = octave.parse::<i32>().map_err(|_| invalid())?;
+
+        // B# and Cb belong to the neighbouring octave of their pitch class
+        let octave = match (name, pitch_class) {
+            ("B#", 0) => octave.checked_add(1),
+            ("Cb", 11) => octave.checked_sub(1),
+            _ => Some(octave),
+        };
+        let midi = octave
+            .and_then(|octave| octave.checked_add(1))
+            .and_then(|octave| octave.checked_mul(12))
+            .and_then(|midi| midi.checked_add(pitch_class))
+            .filter(|midi| (0..=127).contains(midi))
+            .ok_or_else(invalid)?;
+        Ok(Pitch(midi))
+    }
+}
@@ -1,6 +1,17 @@
+use crate::{names, Error};
 use std::collections::BTreeMap;
 use std::sync::OnceLock;
 
+/// scale degrees by their number of semitones above the root
+pub const DEGREES: [&str; 12] = [
+    "1", "b2", "2", "b3", "3", "4", "#4", "5", "b6", "6", "b7", "7",
+];
+
+/// returns the number of semitones above the root of a scale degree, e.g. 3 for "b3"
+pub fn semitones(degree: &str) -> Option<usize> {
+    DEGREES.iter().position(|d| *d == degree)
+}
+
 /// return a static reference to a BTreeMap of scale names to scales (vec of scale degres)
 #[rustfmt::skip]
 pub fn get_scales() -> &'static BTreeMap<String, Vec<&'static str>> {
@@ -49,12 +60,29 @@ pub fn get_scales() -> &'static BTreeMap<String, Vec<&'static str>> {
     })
 }
 
+/// returns the name of a scale as it is in `get_scales`, found by its name in any case
+/// and with spaces, `_` or `-` between words
+pub fn find_scale(name: &str) -> Result<&'static str, Error> {
+    let scales = get_scales();
+    if let Some((scale, _)) = scales.get_key_value(name) {
+        return Ok(scale);
+    }
+    scales
+        .keys()
+        .find(|scale| names::matches(scale, name))
+        .map(String::as_str)
+        .ok_or_else(|| Error::UnknownScale {
+            name: name.to_string(),
+            suggestions: names::suggest(name, scales.keys().map(String::as_str)),
+        })
+}
+
 /// returns whether scale degree "note" is in scale
 pub fn is_scale_note(note: &str, scale: &str) -> bool {
     let scales = get_scales();
 
-    if let Some(scale) = scales.get(scale) {
-        scale.contains(&note)
+    if let Ok(scale) = find_scale(scale) {
+        scales[scale].contains(&note)
     } else {
         false
     }
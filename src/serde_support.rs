@@ -0,0 +1,72 @@
+use crate::layout::{Breath, Cell, Technique};
+use crate::pitch::Pitch;
+use crate::scales::DEGREES;
+use crate::*;
+use serde::de::{self, Deserializer};
+
+const NOTE_NAMES: [&str; 17] = [
+    "C", "C#", "Db", "D", "D#", "Eb", "E", "F", "F#", "Gb", "G", "G#", "Ab", "A", "A#", "Bb", "B",
+];
+
+/// `Cell` with owned strings, which are mapped back to the static note and degree names
+#[derive(Deserialize)]
+struct CellRepr {
+    hole: usize,
+    breath: Breath,
+    technique: Technique,
+    pitch_class: usize,
+    pitch: Pitch,
+    note: String,
+    degree: String,
+    is_scale_note: bool,
+}
+
+impl TryFrom<CellRepr> for Cell {
+    type Error = String;
+
+    fn try_from(cell: CellRepr) -> Result<Cell, String> {
+        let note = NOTE_NAMES
+            .iter()
+            .find(|n| **n == cell.note)
+            .ok_or_else(|| format!("unknown note \"{}\"", cell.note))?;
+        let degree = DEGREES
+            .iter()
+            .find(|d| **d == cell.degree)
+            .ok_or_else(|| format!("unknown scale degree \"{}\"", cell.degree))?;
+
+        Ok(Cell {
+            hole: cell.hole,
+            breath: cell.breath,
+            technique: cell.technique,
+            pitch_class: cell.pitch_class,
+            pitch: cell.pitch,
+            note,
+            degree,
+            is_scale_note: cell.is_scale_note,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Cell {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Cell, D::Error> {
+        let cell = CellRepr::deserialize(deserializer)?;
+        Cell::try_from(cell).map_err(de::Error::custom)
+    }
+}
+
+impl TryFrom<Vec<String>> for ChromaticScale {
+    type Error = Error;
+
+    fn try_from(notes: Vec<String>) -> Result<ChromaticScale, Error> {
+        let root = notes
+            .first()
+            .ok_or_else(|| Error::UnknownRoot(String::new()))?;
+        let sharp = !notes.iter().any(|n| n.ends_with('b'));
+        let scale = ChromaticScale::new(root, Some(sharp))?;
+        if scale.0.iter().eq(notes.iter()) {
+            Ok(scale)
+        } else {
+            Err(Error::UnknownRoot(notes.join(" ")))
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! Parsing and printing of harmonica tab.
+//!
+//! Tab is parsed in any of the common dialects, which can be mixed:
+//!
+//! - blow: `4`, `+4`, `B4`
+//! - draw: `-4`, `D4`
+//! - bends, one mark per semitone: `-4'`, `-3''`, `4b`, `-3bb`, `D4b`, `8'`, `(4)`, `((3))`
+//! - overblows and overdraws: `4o`, `+4o`, `4ob`, `-7o`, `7od`
+//!
+//! A bend, overblow or overdraw without a breath direction, like `4b`, `(4)` or `7o`,
+//! is played on whichever reed of the hole can play it.
+
+use crate::layout::{Breath, Cell, Layout, Row, Technique};
+use crate::{Error, Tuning};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// a note of harmonica tab
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TabNote {
+    /// hole number, starting at 1
+    pub hole: usize,
+    pub breath: Breath,
+    pub technique: Technique,
+}
+
+/// a way of writing tab, named after how it writes bends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// `4`, `-4`, `-4'`, `8'`, `4o`, `-7o`, as used in `str_to_notes_in_order`
+    Apostrophe,
+    /// `+4`, `-4`, `-4'`, `+8'`, `+4o`, `-7o`
+    Plus,
+    /// `4`, `-4`, `-4b`, `8b`, `4ob`, `7od`
+    Suffix,
+    /// `B4`, `D4`, `D4b`, `B8b`, `B4o`, `D7o`
+    Letters,
+    /// `4`, `-4`, `(-4)`, `((-3))`, `(8)`, `4o`, `-7o`
+    Parens,
+}
+
+impl Dialect {
+    pub const ALL: [Dialect; 5] = [
+        Dialect::Apostrophe,
+        Dialect::Plus,
+        Dialect::Suffix,
+        Dialect::Letters,
+        Dialect::Parens,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dialect::Apostrophe => "apostrophe",
+            Dialect::Plus => "plus",
+            Dialect::Suffix => "suffix",
+            Dialect::Letters => "letters",
+            Dialect::Parens => "parens",
+        }
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Dialect, String> {
+        Dialect::ALL
+            .iter()
+            .copied()
+            .find(|d| d.name() == s)
+            .ok_or_else(|| format!("unknown tab dialect \"{}\"", s))
+    }
+}
+
+/// what a token asks for before it is matched to a tuning
+struct Parsed {
+    hole: usize,
+    breath: Option<Breath>,
+    bends: u8,
+    /// `Some(None)` for an overblow or overdraw without a breath direction
+    over: Option<Option<Breath>>,
+}
+
+fn parse_token(token: &str) -> Result<Parsed, String> {
+    let mut s = token;
+    let mut bends: u8 = 0;
+    while s.len() >= 2 && s.starts_with('(') && s.ends_with(')') {
+        bends = bends
+            .checked_add(1)
+            .ok_or_else(|| String::from("too many bends"))?;
+        s = &s[1..s.len() - 1];
+    }
+
+    let breath = match s.chars().next() {
+        Some('+') | Some('B') => Some(Breath::Blow),
+        Some('-') | Some('D') => Some(Breath::Draw),
+        _ => None,
+    };
+    if breath.is_some() {
+        s = &s[1..];
+    }
+
+    let digits = s.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return Err(String::from("missing hole number"));
+    }
+    let hole = s[..digits]
+        .parse()
+        .map_err(|_| String::from("invalid hole number"))?;
+    let mut rest = &s[digits..];
+
+    let over = if let Some(r) = rest.strip_prefix("ob") {
+        rest = r;
+        Some(Some(Breath::Blow))
+    } else if let Some(r) = rest.strip_prefix("od") {
+        rest = r;
+        Some(Some(Breath::Draw))
+    } else if let Some(r) = rest.strip_prefix('o') {
+        rest = r;
+        Some(None)
+    } else {
+        None
+    };
+
+    if !rest.is_empty() {
+        if rest.chars().all(|c| c == '\'') || rest.chars().all(|c| c == 'b') {
+            bends = u8::try_from(rest.len())
+                .ok()
+                .and_then(|marks| bends.checked_add(marks))
+                .ok_or_else(|| String::from("too many bends"))?;
+        } else {
+            return Err(format!("unexpected \"{}\"", rest));
+        }
+    }
+
+    match over {
+        Some(_) if bends > 0 => Err(String::from("overblows and overdraws can't be bent in tab")),
+        Some(Some(over)) if breath.is_some_and(|b| b != over) => Err(String::from(
+            "breath direction does not match the overblow or overdraw",
+        )),
+        _ => Ok(Parsed {
+            hole,
+            breath,
+            bends,
+            over,
+        }),
+    }
+}
+
+fn describe(breath: Breath, technique: Technique) -> String {
+    let breath = match breath {
+        Breath::Blow => "blow",
+        Breath::Draw => "draw",
+    };
+    match technique {
+        Technique::Plain => breath.to_string(),
+        Technique::Bend(n) | Technique::BlowBend(n) => format!(
+            "{} bend by {} semitone{}",
+            breath,
+            n,
+            if n == 1 { "" } else { "s" }
+        ),
+        Technique::Overblow => String::from("overblow"),
+        Technique::Overdraw => String::from("overdraw"),
+    }
+}
+
+impl TabNote {
+    /// parses a single tab token and matches it to a note of the tuning
+    pub fn parse(token: &str, tuning: &Tuning) -> Result<TabNote, Error> {
+        let invalid = |reason: String| Error::InvalidTab {
+            tab: token.to_string(),
+            reason,
+        };
+        let parsed = parse_token(token).map_err(invalid)?;
+        if parsed.hole == 0 || parsed.hole > tuning.blow.len() {
+            return Err(invalid(format!(
+                "hole {} does not exist on this tuning",
+                parsed.hole
+            )));
+        }
+
+        let overblow = (Breath::Blow, Technique::Overblow);
+        let overdraw = (Breath::Draw, Technique::Overdraw);
+        let draw_bend = (Breath::Draw, Technique::Bend(parsed.bends));
+        let blow_bend = (Breath::Blow, Technique::BlowBend(parsed.bends));
+        let candidates = match (parsed.over, parsed.breath) {
+            (Some(Some(Breath::Blow)), _) | (Some(None), Some(Breath::Blow)) => vec![overblow],
+            (Some(Some(Breath::Draw)), _) | (Some(None), Some(Breath::Draw)) => vec![overdraw],
+            (Some(None), None) => vec![overblow, overdraw],
+            (None, breath) if parsed.bends == 0 => {
+                vec![(breath.unwrap_or(Breath::Blow), Technique::Plain)]
+            }
+            (None, Some(Breath::Draw)) => vec![draw_bend],
+            (None, Some(Breath::Blow)) => vec![blow_bend],
+            (None, None) => vec![draw_bend, blow_bend],
+        };
+
+        candidates
+            .iter()
+            .map(|&(breath, technique)| TabNote {
+                hole: parsed.hole,
+                breath,
+                technique,
+            })
+            .find(|note| note.note(tuning).is_some())
+            .ok_or_else(|| {
+                let (breath, technique) = candidates[0];
+                invalid(format!(
+                    "hole {} has no {}",
+                    parsed.hole,
+                    describe(breath, technique)
+                ))
+            })
+    }
+
+    /// returns the note in semitones above the root of the key, see `Tuning::new`
+    pub fn note(&self, tuning: &Tuning) -> Option<usize> {
+        let row = Row::find(self.breath, self.technique)?;
+        *tuning.row(row).get(self.hole.checked_sub(1)?)?
+    }
+
+    /// returns the cell of a layout this note plays
+    pub fn cell<'a>(&self, layout: &'a Layout) -> Option<&'a Cell> {
+        let row = Row::find(self.breath, self.technique)?;
+        layout.row(row).get(self.hole.checked_sub(1)?)?.as_ref()
+    }
+
+    pub fn format(&self, dialect: Dialect) -> String {
+        let hole = self.hole;
+        let is_draw = self.breath == Breath::Draw;
+        let sign = match (dialect, is_draw) {
+            (Dialect::Letters, false) => "B",
+            (Dialect::Letters, true) => "D",
+            (Dialect::Plus, false) => "+",
+            (_, false) => "",
+            (_, true) => "-",
+        };
+        let bend_mark = match dialect {
+            Dialect::Apostrophe | Dialect::Plus => "'",
+            _ => "b",
+        };
+
+        match self.technique {
+            Technique::Plain => format!("{}{}", sign, hole),
+            Technique::Bend(n) | Technique::BlowBend(n) => match dialect {
+                Dialect::Parens => format!(
+                    "{}{}{}{}",
+                    "(".repeat(n as usize),
+                    sign,
+                    hole,
+                    ")".repeat(n as usize)
+                ),
+                _ => format!("{}{}{}", sign, hole, bend_mark.repeat(n as usize)),
+            },
+            Technique::Overblow | Technique::Overdraw => match dialect {
+                Dialect::Suffix => format!("{}{}", hole, if is_draw { "od" } else { "ob" }),
+                _ => format!("{}{}o", sign, hole),
+            },
+        }
+    }
+}
+
+impl fmt::Display for TabNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(Dialect::Apostrophe))
+    }
+}
+
+impl From<&Cell> for TabNote {
+    fn from(cell: &Cell) -> TabNote {
+        TabNote {
+            hole: cell.hole,
+            breath: cell.breath,
+            technique: cell.technique,
+        }
+    }
+}
+
+/// parses whitespace separated tab, bar lines (`|`) are skipped
+pub fn parse_tab(input: &str, tuning: &Tuning) -> Result<Vec<TabNote>, Error> {
+    input
+        .split_whitespace()
+        .filter(|token| *token != "|")
+        .map(|token| TabNote::parse(token, tuning))
+        .collect()
+}
+
+/// prints tab separated by spaces
+pub fn format_tab(notes: &[TabNote], dialect: Dialect) -> String {
+    notes
+        .iter()
+        .map(|note| note.format(dialect))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::str_to_notes_in_order;
+
+    #[test]
+    fn test_notes_in_order_round_trip() {
+        let richter = "C E G C E G C E G C\nD G B D F A B D F A\n";
+        let tuning = Tuning::default();
+        let (notes, duplicated) = str_to_notes_in_order(richter).unwrap();
+        for (i, tab) in notes.iter().enumerate() {
+            let note = TabNote::parse(tab, &tuning).unwrap();
+            assert_eq!(&note.to_string(), tab);
+            assert_eq!(note.note(&tuning), Some(i));
+        }
+        // removed duplicate overblows and overdraws don't parse
+        for tab in duplicated.iter().step_by(2).filter(|t| t.contains('o')) {
+            assert!(TabNote::parse(tab, &tuning).is_err(), "{}", tab);
+        }
+    }
+
+    #[test]
+    fn test_dialects() {
+        let tuning = Tuning::default();
+        let note = |hole, breath, technique| TabNote {
+            hole,
+            breath,
+            technique,
+        };
+        for (tabs, expected) in [
+            (
+                vec!["4", "+4", "B4"],
+                note(4, Breath::Blow, Technique::Plain),
+            ),
+            (vec!["-4", "D4"], note(4, Breath::Draw, Technique::Plain)),
+            (
+                vec!["-4'", "4b", "-4b", "D4b", "(4)", "(-4)", "4'"],
+                note(4, Breath::Draw, Technique::Bend(1)),
+            ),
+            (
+                vec!["-3''", "3bb", "D3bb", "((3))"],
+                note(3, Breath::Draw, Technique::Bend(2)),
+            ),
+            (
+                vec!["8'", "+8'", "8b", "B8b", "(8)"],
+                note(8, Breath::Blow, Technique::BlowBend(1)),
+            ),
+            (
+                vec!["4o", "+4o", "4ob", "B4o"],
+                note(4, Breath::Blow, Technique::Overblow),
+            ),
+            (
+                vec!["-7o", "7o", "7od", "D7o"],
+                note(7, Breath::Draw, Technique::Overdraw),
+            ),
+        ] {
+            for tab in tabs {
+                assert_eq!(TabNote::parse(tab, &tuning).unwrap(), expected, "{}", tab);
+            }
+            for dialect in Dialect::ALL.iter() {
+                let printed = expected.format(*dialect);
+                assert_eq!(
+                    TabNote::parse(&printed, &tuning).unwrap(),
+                    expected,
+                    "{}",
+                    printed
+                );
+            }
+        }
+
+        let tab = parse_tab("-2 -3' | 4 -4 5", &tuning).unwrap();
+        assert_eq!(format_tab(&tab, Dialect::Letters), "D2 D3b B4 D4 B5");
+        assert_eq!(format_tab(&tab, Dialect::Plus), "-2 -3' +4 -4 +5");
+    }
+
+    #[test]
+    fn test_invalid_tab() {
+        let tuning = Tuning::default();
+        for tab in [
+            "", "x", "-", "11", "0", "4x", "4'b", "4o'", "-4ob", "+5'", "-5'", "5o'", "2o", "-1o",
+            "-3''''",
+        ] {
+            assert!(
+                matches!(TabNote::parse(tab, &tuning), Err(Error::InvalidTab { .. })),
+                "{}",
+                tab
+            );
+        }
+        let marks = "'".repeat(300);
+        let parens = format!("{}-4{}", "(".repeat(300), ")".repeat(300));
+        for tab in [
+            format!("-4{}", marks),
+            format!("(-4{})", &marks[..255]),
+            parens,
+        ] {
+            assert_eq!(
+                TabNote::parse(&tab, &tuning).unwrap_err().to_string(),
+                format!("invalid tab \"{}\": too many bends", tab)
+            );
+        }
+        assert_eq!(
+            TabNote::parse("-5'", &tuning).unwrap_err().to_string(),
+            "invalid tab \"-5'\": hole 5 has no draw bend by 1 semitone"
+        );
+    }
+}
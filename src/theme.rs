@@ -0,0 +1,444 @@
+//! Colors and font sizes of exported layout charts.
+//!
+//! Five themes are built in: `light` (the default), `dark`, `high-contrast`,
+//! `grayscale` for printing and `colorblind`, which uses colors that can be told apart
+//! with red-green colorblindness and hatches scale notes. Custom themes are read from files of `field: value` lines,
+//! starting from a built-in theme and changing some of its fields:
+//!
+//! ```text
+//! # blue notes on a dark background
+//! base: dark
+//! scale_note: #3b82f6
+//! other_note: #3b82f650
+//! caption_font_size: 24
+//! ```
+//!
+//! Colors are written as `#rrggbb`, `#rrggbbaa` with an alpha value, or `none`.
+//! Fields are:
+//!
+//! - `base`: built-in theme to start from, `light` if not given
+//! - `background`: the whole image
+//! - `label_background`: behind the row labels
+//! - `text`: row labels
+//! - `stroke` and `stroke_width`: outline of every cell
+//! - `scale_note`, `other_note` and `hole_number`: fill of notes in the scale, other
+//!   notes and hole numbers, and `scale_note_text`, `other_note_text` and
+//!   `hole_number_text` for their text
+//! - `scale_mark`: `none`, `pattern`, `underline` or `bold`, marking scale notes
+//!   without relying on color
+//! - `note_font_size` and `label_font_size`: text of cells and row labels
+//! - `caption`: `show` or `hide`, and `caption_font_size`, `caption_color` and
+//!   `caption_background`
+//!
+//! Blank lines and lines starting with `#` are ignored.
+
+use crate::Error;
+use std::fs;
+use std::path::Path;
+
+/// an RGB color with an alpha value, 255 is opaque
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::rgba(r, g, b, 255)
+    }
+
+    pub const NONE: Color = Color::rgba(0, 0, 0, 0);
+
+    /// alpha value from 0.0 to 1.0
+    pub fn opacity(&self) -> f32 {
+        self.a as f32 / 255.0
+    }
+
+    /// returns the color drawn over another, opaque color
+    pub fn over(&self, below: Color) -> Color {
+        let blend = |c: u8, b: u8| {
+            (c as f32 * self.opacity() + b as f32 * (1.0 - self.opacity())).round() as u8
+        };
+        Color::rgb(
+            blend(self.r, below.r),
+            blend(self.g, below.g),
+            blend(self.b, below.b),
+        )
+    }
+
+    /// parses "#rrggbb", "#rrggbbaa" or "none"
+    pub fn parse(text: &str) -> Option<Color> {
+        if text == "none" {
+            return Some(Color::NONE);
+        }
+        let hex = text.strip_prefix('#')?;
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return None;
+        }
+        let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        let a = if hex.len() == 8 { byte(6)? } else { 255 };
+        Some(Color::rgba(byte(0)?, byte(2)?, byte(4)?, a))
+    }
+}
+
+/// fill and text color of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellStyle {
+    pub fill: Color,
+    pub text: Color,
+}
+
+/// marks scale notes in addition to their fill color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMark {
+    None,
+    /// diagonal lines across the cell
+    Pattern,
+    /// a line under the note
+    Underline,
+    Bold,
+}
+
+/// the line above the chart, e.g. "C richter harmonica, 1st position"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptionStyle {
+    pub visible: bool,
+    pub font_size: f32,
+    pub color: Color,
+    pub background: Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub background: Color,
+    pub label_background: Color,
+    /// color of row labels
+    pub text: Color,
+    pub stroke: Color,
+    pub stroke_width: f32,
+    pub scale_note: CellStyle,
+    pub other_note: CellStyle,
+    pub hole_number: CellStyle,
+    pub scale_mark: ScaleMark,
+    pub note_font_size: f32,
+    pub label_font_size: f32,
+    pub caption: CaptionStyle,
+}
+
+/// names of the built-in themes
+pub const THEMES: [&str; 5] = ["light", "dark", "high-contrast", "grayscale", "colorblind"];
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::light()
+    }
+}
+
+impl Theme {
+    /// green notes and cyan hole numbers on a transparent background
+    pub fn light() -> Theme {
+        let black = Color::rgb(0, 0, 0);
+        let white = Color::rgba(255, 255, 255, 150);
+        Theme {
+            background: Color::NONE,
+            label_background: white,
+            text: black,
+            stroke: black,
+            stroke_width: 1.0,
+            scale_note: CellStyle {
+                fill: Color::rgb(129, 255, 124),
+                text: black,
+            },
+            other_note: CellStyle {
+                fill: Color::rgba(129, 255, 124, 100),
+                text: black,
+            },
+            hole_number: CellStyle {
+                fill: Color::rgba(39, 215, 245, 200),
+                text: black,
+            },
+            scale_mark: ScaleMark::None,
+            note_font_size: 30.0,
+            label_font_size: 18.0,
+            caption: CaptionStyle {
+                visible: true,
+                font_size: 30.0,
+                color: black,
+                background: white,
+            },
+        }
+    }
+
+    pub fn dark() -> Theme {
+        let light_gray = Color::rgb(230, 230, 230);
+        Theme {
+            background: Color::rgb(32, 32, 36),
+            label_background: Color::NONE,
+            text: light_gray,
+            stroke: Color::rgb(120, 120, 120),
+            stroke_width: 1.0,
+            scale_note: CellStyle {
+                fill: Color::rgb(46, 160, 67),
+                text: Color::rgb(255, 255, 255),
+            },
+            other_note: CellStyle {
+                fill: Color::rgba(46, 160, 67, 60),
+                text: Color::rgb(190, 190, 190),
+            },
+            hole_number: CellStyle {
+                fill: Color::rgb(20, 110, 140),
+                text: Color::rgb(255, 255, 255),
+            },
+            caption: CaptionStyle {
+                color: light_gray,
+                background: Color::NONE,
+                ..Theme::light().caption
+            },
+            ..Theme::light()
+        }
+    }
+
+    /// black and white with thick outlines and yellow hole numbers
+    pub fn high_contrast() -> Theme {
+        let black = Color::rgb(0, 0, 0);
+        let white = Color::rgb(255, 255, 255);
+        Theme {
+            background: white,
+            label_background: Color::NONE,
+            stroke_width: 2.0,
+            scale_note: CellStyle {
+                fill: black,
+                text: white,
+            },
+            other_note: CellStyle {
+                fill: white,
+                text: black,
+            },
+            hole_number: CellStyle {
+                fill: Color::rgb(255, 221, 0),
+                text: black,
+            },
+            label_font_size: 20.0,
+            caption: CaptionStyle {
+                background: Color::NONE,
+                ..Theme::light().caption
+            },
+            ..Theme::light()
+        }
+    }
+
+    /// shades of gray on white, for printing
+    pub fn grayscale() -> Theme {
+        Theme {
+            background: Color::rgb(255, 255, 255),
+            label_background: Color::NONE,
+            scale_note: CellStyle {
+                fill: Color::rgb(170, 170, 170),
+                text: Color::rgb(0, 0, 0),
+            },
+            other_note: CellStyle {
+                fill: Color::rgb(240, 240, 240),
+                text: Color::rgb(0, 0, 0),
+            },
+            hole_number: CellStyle {
+                fill: Color::rgb(90, 90, 90),
+                text: Color::rgb(255, 255, 255),
+            },
+            caption: CaptionStyle {
+                background: Color::NONE,
+                ..Theme::light().caption
+            },
+            ..Theme::light()
+        }
+    }
+
+    /// blue scale notes with hatching and orange hole numbers, from the Okabe-Ito palette
+    pub fn colorblind() -> Theme {
+        let black = Color::rgb(0, 0, 0);
+        Theme {
+            background: Color::rgb(255, 255, 255),
+            label_background: Color::NONE,
+            scale_note: CellStyle {
+                fill: Color::rgb(86, 180, 233),
+                text: black,
+            },
+            other_note: CellStyle {
+                fill: Color::rgb(255, 255, 255),
+                text: black,
+            },
+            hole_number: CellStyle {
+                fill: Color::rgb(230, 159, 0),
+                text: black,
+            },
+            scale_mark: ScaleMark::Pattern,
+            caption: CaptionStyle {
+                background: Color::NONE,
+                ..Theme::light().caption
+            },
+            ..Theme::light()
+        }
+    }
+
+    /// returns a built-in theme by name
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "light" => Some(Theme::light()),
+            "dark" => Some(Theme::dark()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            "grayscale" => Some(Theme::grayscale()),
+            "colorblind" => Some(Theme::colorblind()),
+            _ => None,
+        }
+    }
+
+    /// returns a built-in theme, or reads a theme file given by its path or by its name
+    /// in the "themes" folder of the harptool config directory
+    pub fn load(name: &str) -> Result<Theme, Error> {
+        if let Some(theme) = Theme::builtin(name) {
+            return Ok(theme);
+        }
+        let path = if Path::new(name).is_file() {
+            Path::new(name).to_path_buf()
+        } else {
+            let mut path = dirs::config_dir().unwrap_or_default();
+            path.push("harptool");
+            path.push("themes");
+            path.push(name);
+            path
+        };
+        let contents = fs::read_to_string(&path).map_err(|_| {
+            Error::Theme(format!(
+                "\"{}\" not found, use one of {} or create \"{}\"",
+                name,
+                THEMES.join(", "),
+                path.to_string_lossy()
+            ))
+        })?;
+        Theme::parse(&contents)
+    }
+
+    /// parses a theme file, see the module documentation
+    pub fn parse(text: &str) -> Result<Theme, Error> {
+        let mut fields = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (field, value) = line.split_once(':').ok_or_else(|| {
+                Error::Theme(format!("line {}: expected \"field: value\"", i + 1))
+            })?;
+            fields.push((i + 1, field.trim(), value.trim()));
+        }
+
+        let mut theme = match fields.iter().find(|(_, field, _)| *field == "base") {
+            Some((line, _, base)) => Theme::builtin(base).ok_or_else(|| {
+                Error::Theme(format!("line {}: unknown base theme \"{}\"", line, base))
+            })?,
+            None => Theme::light(),
+        };
+
+        for (line, field, value) in fields {
+            let invalid = |expected: &str| {
+                Error::Theme(format!(
+                    "line {}: invalid {} \"{}\", expected {}",
+                    line, field, value, expected
+                ))
+            };
+            let color = || Color::parse(value).ok_or_else(|| invalid("a color like #81ff7c"));
+            let size = || match value.parse::<f32>() {
+                Ok(size) if size > 0.0 && size.is_finite() => Ok(size),
+                _ => Err(invalid("a positive number")),
+            };
+            match field {
+                "base" => {}
+                "background" => theme.background = color()?,
+                "label_background" => theme.label_background = color()?,
+                "text" => theme.text = color()?,
+                "stroke" => theme.stroke = color()?,
+                "stroke_width" => theme.stroke_width = size()?,
+                "scale_note" => theme.scale_note.fill = color()?,
+                "scale_note_text" => theme.scale_note.text = color()?,
+                "other_note" => theme.other_note.fill = color()?,
+                "other_note_text" => theme.other_note.text = color()?,
+                "hole_number" => theme.hole_number.fill = color()?,
+                "hole_number_text" => theme.hole_number.text = color()?,
+                "scale_mark" => {
+                    theme.scale_mark = match value {
+                        "none" => ScaleMark::None,
+                        "pattern" => ScaleMark::Pattern,
+                        "underline" => ScaleMark::Underline,
+                        "bold" => ScaleMark::Bold,
+                        _ => return Err(invalid("none, pattern, underline or bold")),
+                    }
+                }
+                "note_font_size" => theme.note_font_size = size()?,
+                "label_font_size" => theme.label_font_size = size()?,
+                "caption" => {
+                    theme.caption.visible = match value {
+                        "show" => true,
+                        "hide" => false,
+                        _ => return Err(invalid("show or hide")),
+                    }
+                }
+                "caption_font_size" => theme.caption.font_size = size()?,
+                "caption_color" => theme.caption.color = color()?,
+                "caption_background" => theme.caption.background = color()?,
+                _ => {
+                    return Err(Error::Theme(format!(
+                        "line {}: unknown field \"{}\"",
+                        line, field
+                    )))
+                }
+            }
+        }
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(Color::parse("#81ff7c"), Some(Color::rgb(129, 255, 124)));
+        assert_eq!(
+            Color::parse("#27D7F5C8"),
+            Some(Color::rgba(39, 215, 245, 200))
+        );
+        assert_eq!(Color::parse("none"), Some(Color::NONE));
+        assert_eq!(Color::parse("81ff7c"), None);
+        assert_eq!(Color::parse("#81ff7"), None);
+        assert_eq!(Color::parse("#81ff7g"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let theme = Theme::parse(
+            "# blue\nbase: dark\n\nscale_note: #3b82f6\ncaption: hide\nnote_font_size: 24\nscale_mark: underline\n",
+        )
+        .unwrap();
+        assert_eq!(theme.scale_note.fill, Color::rgb(59, 130, 246));
+        assert_eq!(theme.background, Theme::dark().background);
+        assert!(!theme.caption.visible);
+        assert_eq!(theme.note_font_size, 24.0);
+        assert_eq!(theme.scale_mark, ScaleMark::Underline);
+
+        assert_eq!(Theme::parse("").unwrap(), Theme::light());
+        let err = Theme::parse("base: light\nstroke: black").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "could not load theme: line 2: invalid stroke \"black\", expected a color like #81ff7c"
+        );
+        assert!(Theme::parse("colour: #000000").is_err());
+        assert!(Theme::parse("base: sepia").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! Rewriting tab for another tuning or position.
+
+use crate::layout::{Layout, Technique};
+use crate::melody::{self, Costs, Unplayable};
+use crate::pitch::Pitch;
+use crate::tab::TabNote;
+
+/// a note of tab and what it became after transposing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransposedNote {
+    pub from: TabNote,
+    pub pitch: Pitch,
+    pub to: Result<TabNote, Unplayable>,
+}
+
+impl TransposedNote {
+    /// returns true if the note is played with a different breath or technique, e.g. `-5` that became `-5'`
+    pub fn technique_changed(&self) -> bool {
+        self.to
+            .is_ok_and(|to| (to.breath, to.technique) != (self.from.breath, self.from.technique))
+    }
+}
+
+/// returns the pitch of every note of a tab
+///
+/// # Panics
+/// if a note isn't in the layout, which can't happen for tab parsed with the layout's tuning
+pub fn tab_to_melody(tab: &[TabNote], layout: &Layout) -> Vec<Pitch> {
+    tab.iter()
+        .map(|note| {
+            note.cell(layout)
+                .unwrap_or_else(|| panic!("{} is not in the layout", note))
+                .pitch
+        })
+        .collect()
+}
+
+/// rewrites tab from one layout for another at the same pitch,
+/// usually the layouts of two tunings in the same key. Notes that play the same pitch
+/// on both layouts are kept, every run of the other notes is tabbed again.
+pub fn transpose_tuning(
+    tab: &[TabNote],
+    from: &Layout,
+    to: &Layout,
+    costs: &Costs,
+) -> Vec<TransposedNote> {
+    let melody = tab_to_melody(tab, from);
+    let kept: Vec<bool> = tab
+        .iter()
+        .zip(&melody)
+        .map(|(note, pitch)| note.cell(to).is_some_and(|cell| cell.pitch == *pitch))
+        .collect();
+
+    let mut res = Vec::with_capacity(tab.len());
+    let mut start = 0;
+    while start < tab.len() {
+        let end = (start..tab.len())
+            .find(|&i| kept[i] != kept[start])
+            .unwrap_or(tab.len());
+        if kept[start] {
+            res.extend((start..end).map(|i| TransposedNote {
+                from: tab[i],
+                pitch: melody[i],
+                to: Ok(tab[i]),
+            }));
+        } else {
+            let moved = melody::melody_to_tab(&melody[start..end], to, costs);
+            res.extend(
+                moved
+                    .notes
+                    .iter()
+                    .zip(&tab[start..end])
+                    .map(|(note, &from)| TransposedNote {
+                        from,
+                        pitch: note.pitch,
+                        to: note.tab,
+                    }),
+            );
+        }
+        start = end;
+    }
+    res
+}
+
+/// number of notes of a tab that need bends, overblows or overdraws
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Load {
+    pub bends: usize,
+    /// overblows and overdraws
+    pub overblows: usize,
+}
+
+impl Load {
+    pub fn new<'a>(tab: impl IntoIterator<Item = &'a TabNote>) -> Load {
+        let mut load = Load::default();
+        for note in tab {
+            match note.technique {
+                Technique::Plain => {}
+                Technique::Bend(_) | Technique::BlowBend(_) => load.bends += 1,
+                Technique::Overblow | Technique::Overdraw => load.overblows += 1,
+            }
+        }
+        load
+    }
+}
+
+/// tab moved to another position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionTransposition {
+    pub notes: Vec<TransposedNote>,
+    /// semitones the melody was moved by
+    pub semitones: i32,
+    /// load of the playable notes after moving
+    pub load: Load,
+    pub cost: u32,
+}
+
+/// moves tab from one position to another on the same harp, keeping the melody.
+/// Each position is a fifth above the previous one, the octave is chosen so that as many
+/// notes as possible can be played, and then by the lowest cost.
+pub fn transpose_position(
+    tab: &[TabNote],
+    layout: &Layout,
+    from: usize,
+    to: usize,
+    costs: &Costs,
+) -> PositionTransposition {
+    let melody = tab_to_melody(tab, layout);
+    let fifths = (to as i32 - from as i32) * 7;
+    let semitones = fifths.rem_euclid(12);
+
+    let (semitones, res) = (-4..=4)
+        .map(|octave| semitones + 12 * octave)
+        .map(|shift| {
+            let moved: Vec<Pitch> = melody.iter().map(|p| Pitch(p.0 + shift)).collect();
+            (shift, melody::melody_to_tab(&moved, layout, costs))
+        })
+        .min_by_key(|(shift, res)| (res.unplayable().count(), res.cost, shift.abs()))
+        .unwrap();
+
+    let notes: Vec<TransposedNote> = res
+        .notes
+        .iter()
+        .zip(tab)
+        .map(|(note, &from)| TransposedNote {
+            from,
+            pitch: note.pitch,
+            to: note.tab,
+        })
+        .collect();
+    let load = Load::new(notes.iter().filter_map(|note| note.to.as_ref().ok()));
+    PositionTransposition {
+        notes,
+        semitones,
+        load,
+        cost: res.cost,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tab::{format_tab, parse_tab, Dialect};
+    use crate::{ChromaticScale, Setup, Tuning};
+    use std::convert::TryFrom;
+
+    fn layout(tuning: &Tuning) -> Layout {
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        tuning.layout(&ChromaticScale::new("C", None).unwrap(), &setup)
+    }
+
+    #[test]
+    fn test_transpose_tuning() {
+        let richter = Tuning::default();
+        let country = Tuning::try_from("C E G C E G C E G C\nD G B D F# A B D F A\n").unwrap();
+        let tab = parse_tab("-4 -5 5o 6 -6'", &richter).unwrap();
+        let res = transpose_tuning(
+            &tab,
+            &layout(&richter),
+            &layout(&country),
+            &Costs::default(),
+        );
+        let to: Vec<TabNote> = res.iter().map(|note| note.to.unwrap()).collect();
+        assert_eq!(format_tab(&to, Dialect::Apostrophe), "-4 -5' -5 6 -6'");
+        assert_eq!(
+            res.iter()
+                .map(|note| note.technique_changed())
+                .collect::<Vec<bool>>(),
+            vec![false, true, true, false, false]
+        );
+
+        let short = Tuning::try_from("C E G C\nD G B D\n").unwrap();
+        let res = transpose_tuning(&tab, &layout(&richter), &layout(&short), &Costs::default());
+        assert_eq!(res[0].to.unwrap().to_string(), "-4");
+        assert_eq!(res[3].to, Err(Unplayable::AboveRange));
+        assert_eq!(res[3].pitch, Pitch(79));
+        assert!(!res[3].technique_changed());
+
+        // on the same tuning nothing changes, even where other holes would cost less
+        let tab = parse_tab("3 -1 -2 -3'' 4 5o -7", &richter).unwrap();
+        let res = transpose_tuning(
+            &tab,
+            &layout(&richter),
+            &layout(&richter),
+            &Costs::default(),
+        );
+        let to: Vec<TabNote> = res.iter().map(|note| note.to.unwrap()).collect();
+        assert_eq!(to, tab);
+        assert!(res.iter().all(|note| !note.technique_changed()));
+    }
+
+    #[test]
+    fn test_transpose_position() {
+        let richter = Tuning::default();
+        let c = layout(&richter);
+        // C major scale in 1st position becomes G major in 2nd position
+        let tab = parse_tab("4 -4 5 -5 6 -6 -7 7", &richter).unwrap();
+        assert_eq!(Load::new(&tab), Load::default());
+        let res = transpose_position(&tab, &c, 1, 2, &Costs::default());
+        let to: Vec<TabNote> = res.notes.iter().map(|note| note.to.unwrap()).collect();
+        assert_eq!(format_tab(&to, Dialect::Apostrophe), "6 -6 -7 7 -8 8 9' 9");
+        assert_eq!(res.semitones, 7);
+        assert_eq!(
+            res.load,
+            Load {
+                bends: 1,
+                overblows: 0
+            }
+        );
+
+        // G A B in 2nd position is C D E in 1st, moved up a fourth rather than down a fifth
+        let tab = parse_tab("-2 -3'' -3", &richter).unwrap();
+        let res = transpose_position(&tab, &c, 2, 1, &Costs::default());
+        assert_eq!(res.semitones, 5);
+        assert!(res.notes.iter().all(|note| note.to.is_ok()));
+
+        // 12th position is a fifth below 1st, the melody moves to where it needs no bends
+        let tab = parse_tab("1 -1 2", &richter).unwrap();
+        let res = transpose_position(&tab, &c, 1, 12, &Costs::default());
+        assert_eq!(res.semitones, 17);
+        assert_eq!(res.load, Load::default());
+
+        // the whole range of the harp can't be moved, the notes that don't fit are kept
+        let tab = parse_tab("1 10", &richter).unwrap();
+        let res = transpose_position(&tab, &c, 1, 2, &Costs::default());
+        assert_eq!(res.notes.len(), 2);
+        assert_eq!(res.notes.iter().filter(|note| note.to.is_err()).count(), 1);
+    }
+}
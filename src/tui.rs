@@ -0,0 +1,463 @@
+//! Interactive terminal UI for exploring layouts, see `run`.
+
+use crate::layout::{Cell, Label, Layout};
+use crate::melody::HARP_KEYS;
+use crate::pitch::DEFAULT_A4;
+use crate::scales::get_scales;
+use crate::tab::TabNote;
+use crate::*;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Layout as Split};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{Frame, Terminal};
+
+const HELP: &str =
+    "arrows move  k/K key  t/T tuning  p/P position  s/S scale  d notes/degrees  q quit";
+
+/// the layout shown in the terminal UI, and the hole under the cursor
+pub struct Explorer {
+    tunings: Vec<(String, Tuning)>,
+    tuning: usize,
+    /// index into `HARP_KEYS`
+    key: usize,
+    sharp: Option<bool>,
+    position: usize,
+    scales: Vec<&'static str>,
+    /// index into `scales`, or no scale
+    scale: Option<usize>,
+    pub use_degrees: bool,
+    /// index into the rows of the layout and hole, both starting at 0
+    cursor: (usize, usize),
+}
+
+/// returns the index after or before `index`, wrapping around at `len`
+fn cycle(index: usize, len: usize, forward: bool) -> usize {
+    if forward {
+        (index + 1) % len
+    } else {
+        (index + len - 1) % len
+    }
+}
+
+impl Explorer {
+    /// starts with a tuning, key and setup. The tuning is listed together with the
+    /// built-in tunings, even if it is read from a file.
+    pub fn new(
+        tuning_name: &str,
+        key: &str,
+        sharp: Option<bool>,
+        setup: &Setup,
+        use_degrees: bool,
+    ) -> Result<Explorer, Error> {
+        ChromaticScale::new(key, sharp)?;
+        let key = pitch_class(key)
+            .and_then(|pc| HARP_KEYS.iter().position(|k| pitch_class(k) == Some(pc)))
+            .ok_or_else(|| Error::UnknownRoot(key.to_string()))?;
+
+        let mut tunings: Vec<(String, Tuning)> = get_tunings()
+            .keys()
+            .sorted()
+            .map(|name| Ok((name.to_string(), read_tuning_from_hashmap_or_file(name)?)))
+            .collect::<Result<_, Error>>()?;
+        let tuning = match tunings.iter().position(|(name, _)| name == tuning_name) {
+            Some(tuning) => tuning,
+            None => {
+                let tuning = read_tuning_from_hashmap_or_file(tuning_name)?;
+                tunings.push((tuning_name.to_string(), tuning));
+                tunings.len() - 1
+            }
+        };
+
+        let scales: Vec<&'static str> = get_scales().keys().map(String::as_str).collect();
+        let scale = match setup.scale {
+            Some(scale) => {
+                let scale = scales::find_scale(scale)?;
+                scales.iter().position(|s| *s == scale)
+            }
+            None => None,
+        };
+
+        let blow_row = Row::ALL.iter().position(|row| *row == Row::Blow).unwrap();
+        Ok(Explorer {
+            tunings,
+            tuning,
+            key,
+            sharp,
+            position: setup.position,
+            scales,
+            scale,
+            use_degrees,
+            cursor: (blow_row, 0),
+        })
+    }
+
+    pub fn tuning_name(&self) -> &str {
+        &self.tunings[self.tuning].0
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        &self.tunings[self.tuning].1
+    }
+
+    /// returns the notes of the key, falling back to the key's own accidentals if
+    /// sharps or flats were asked for but don't fit it
+    pub fn root(&self) -> ChromaticScale {
+        let key = HARP_KEYS[self.key];
+        ChromaticScale::new(key, self.sharp)
+            .or_else(|_| ChromaticScale::new(key, None))
+            .unwrap()
+    }
+
+    pub fn setup(&self) -> Setup<'static> {
+        Setup {
+            scale: self.scale.map(|scale| self.scales[scale]),
+            position: self.position,
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.tuning().layout(&self.root(), &self.setup())
+    }
+
+    pub fn label(&self) -> Label {
+        if self.use_degrees {
+            Label::Degree
+        } else {
+            Label::Note
+        }
+    }
+
+    pub fn caption(&self) -> String {
+        get_caption(self.tuning_name(), &Some(self.root()), &self.setup())
+    }
+
+    pub fn cycle_key(&mut self, forward: bool) {
+        self.key = cycle(self.key, HARP_KEYS.len(), forward);
+    }
+
+    /// selects another tuning, keeping the cursor on a hole it has
+    pub fn cycle_tuning(&mut self, forward: bool) {
+        self.tuning = cycle(self.tuning, self.tunings.len(), forward);
+        let holes = self.tuning().blow.len();
+        self.cursor.1 = self.cursor.1.min(holes.saturating_sub(1));
+    }
+
+    pub fn cycle_position(&mut self, forward: bool) {
+        self.position = cycle(self.position - 1, 12, forward) + 1;
+    }
+
+    /// selects the next or previous scale, with no scale between the last and the first
+    pub fn cycle_scale(&mut self, forward: bool) {
+        let len = self.scales.len();
+        self.scale = match (self.scale, forward) {
+            (None, true) => Some(0),
+            (None, false) => Some(len - 1),
+            (Some(scale), true) if scale + 1 == len => None,
+            (Some(0), false) => None,
+            (Some(scale), forward) => Some(cycle(scale, len, forward)),
+        };
+    }
+
+    /// moves the cursor by rows and holes, stopping at the edges of the layout
+    pub fn move_cursor(&mut self, rows: isize, holes: isize) {
+        let clamp = |index: usize, by: isize, len: usize| {
+            (index as isize + by).clamp(0, len as isize - 1) as usize
+        };
+        self.cursor = (
+            clamp(self.cursor.0, rows, Row::ALL.len()),
+            clamp(self.cursor.1, holes, self.tuning().blow.len()),
+        );
+    }
+
+    /// returns the note under the cursor, if its hole can play one in that row
+    pub fn selected<'a>(&self, layout: &'a Layout) -> Option<&'a Cell> {
+        let (_, cells) = &layout.rows[self.cursor.0];
+        cells.get(self.cursor.1)?.as_ref()
+    }
+}
+
+/// returns the other ways to play the same pitch as `cell`
+pub fn alternatives<'a>(layout: &'a Layout, cell: &Cell) -> Vec<&'a Cell> {
+    layout
+        .cells()
+        .filter(|other| other.pitch == cell.pitch && *other != cell)
+        .collect()
+}
+
+/// returns the style of scale notes and hole numbers, like the printed layout
+fn styles(highlight: Highlight) -> (Style, Style) {
+    let style = Style::new();
+    if !highlight.ansi {
+        return (style, style);
+    }
+    match (highlight.mark, highlight.palette) {
+        (Mark::Color, Palette::Default) => (style.fg(Color::Green), style.fg(Color::Blue)),
+        (Mark::Color, Palette::Colorblind) => (
+            style.fg(Color::Rgb(230, 159, 0)),
+            style.fg(Color::Rgb(86, 180, 233)),
+        ),
+        (Mark::Bold, _) => (style.add_modifier(Modifier::BOLD), style),
+        (Mark::Underline, _) => (style.add_modifier(Modifier::UNDERLINED), style),
+        (Mark::Brackets, _) => (style, style),
+    }
+}
+
+/// returns the lines of the layout grid, with the cell under the cursor reversed
+fn grid(explorer: &Explorer, layout: &Layout, highlight: Highlight) -> Vec<Line<'static>> {
+    let label = explorer.label();
+    let width = layout
+        .cells()
+        .map(|cell| cell.label(label).len())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    let width = highlight.cell_width(width);
+    let (scale_style, number_style) = styles(highlight);
+
+    let mut lines = Vec::new();
+    for (row_index, (row, cells)) in layout.rows.iter().enumerate() {
+        let mut spans = vec![Span::raw(format!("{:20} ", row.label()))];
+        for (hole, cell) in cells.iter().enumerate() {
+            let (text, style) = match cell {
+                Some(cell) => {
+                    let note = cell.label(label);
+                    let text = match (highlight.mark, cell.is_scale_note) {
+                        (Mark::Brackets, true) => format!("[{}]", note),
+                        (Mark::Brackets, false) => format!(" {} ", note),
+                        _ => note,
+                    };
+                    let style = if cell.is_scale_note {
+                        scale_style
+                    } else {
+                        Style::new()
+                    };
+                    (text, style)
+                }
+                None => (String::new(), Style::new()),
+            };
+            let text = format!("{:width$}", text, width = width);
+            if explorer.cursor == (row_index, hole) {
+                spans.push(Span::styled(text, style.add_modifier(Modifier::REVERSED)));
+            } else {
+                spans.push(Span::styled(text, style));
+            }
+            spans.push(Span::raw(" "));
+        }
+        lines.push(Line::from(spans));
+
+        if *row == Row::Blow {
+            let indent = if highlight.mark == Mark::Brackets {
+                " "
+            } else {
+                ""
+            };
+            let numbers = (1..=layout.holes)
+                .map(|i| format!("{:width$} ", format!("{}{}", indent, i), width = width))
+                .collect::<String>();
+            lines.push(Line::from(vec![
+                Span::raw(format!("{:20} ", "")),
+                Span::styled(numbers, number_style),
+            ]));
+        }
+    }
+    lines
+}
+
+/// returns the lines describing the note under the cursor
+fn info(explorer: &Explorer, layout: &Layout) -> Vec<Line<'static>> {
+    let cell = match explorer.selected(layout) {
+        Some(cell) => cell,
+        None => return vec![Line::from("no note")],
+    };
+    let (row, _) = layout.rows[explorer.cursor.0];
+    let alternatives = alternatives(layout, cell)
+        .iter()
+        .map(|other| TabNote::from(*other).to_string())
+        .join(" ");
+    let scale_note = if cell.is_scale_note {
+        ", scale note"
+    } else {
+        ""
+    };
+    vec![
+        Line::from(format!(
+            "tab {}: hole {} {}",
+            TabNote::from(cell),
+            cell.hole,
+            row.label()
+        )),
+        Line::from(format!(
+            "pitch {} ({:.1} Hz), degree {}{}",
+            cell.pitch_name(),
+            cell.pitch.frequency(DEFAULT_A4),
+            cell.degree,
+            scale_note
+        )),
+        Line::from(if alternatives.is_empty() {
+            String::from("no other way to play it")
+        } else {
+            format!("also played as {}", alternatives)
+        }),
+    ]
+}
+
+fn draw(frame: &mut Frame, explorer: &Explorer, highlight: Highlight) {
+    let layout = explorer.layout();
+    let [grid_area, info_area, help_area] = Split::vertical([
+        Constraint::Length(layout.rows.len() as u16 + 3),
+        Constraint::Length(5),
+        Constraint::Min(1),
+    ])
+    .areas(frame.size());
+
+    frame.render_widget(
+        Paragraph::new(grid(explorer, &layout, highlight))
+            .block(Block::bordered().title(explorer.caption())),
+        grid_area,
+    );
+    frame.render_widget(
+        Paragraph::new(info(explorer, &layout)).block(Block::bordered()),
+        info_area,
+    );
+    frame.render_widget(Paragraph::new(HELP), help_area);
+}
+
+fn event_loop<B: Backend>(
+    terminal: &mut Terminal<B>,
+    explorer: &mut Explorer,
+    highlight: Highlight,
+) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, explorer, highlight))?;
+        let key = match event::read()? {
+            // ignore key releases on Windows
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Up => explorer.move_cursor(-1, 0),
+            KeyCode::Down => explorer.move_cursor(1, 0),
+            KeyCode::Left => explorer.move_cursor(0, -1),
+            KeyCode::Right => explorer.move_cursor(0, 1),
+            KeyCode::Char('k') => explorer.cycle_key(true),
+            KeyCode::Char('K') => explorer.cycle_key(false),
+            KeyCode::Char('t') => explorer.cycle_tuning(true),
+            KeyCode::Char('T') => explorer.cycle_tuning(false),
+            KeyCode::Char('p') => explorer.cycle_position(true),
+            KeyCode::Char('P') => explorer.cycle_position(false),
+            KeyCode::Char('s') => explorer.cycle_scale(true),
+            KeyCode::Char('S') => explorer.cycle_scale(false),
+            KeyCode::Char('d') => explorer.use_degrees = !explorer.use_degrees,
+            _ => {}
+        }
+    }
+}
+
+/// shows the layout of a tuning in the terminal until `q` is pressed. Key, tuning,
+/// position and scale can be changed with hotkeys, and the note under the cursor is
+/// shown with its tab, pitch and the other holes that play it.
+pub fn run(
+    tuning_name: &str,
+    key: &str,
+    sharp: Option<bool>,
+    setup: &Setup,
+    use_degrees: bool,
+    highlight: Highlight,
+) -> Result<(), Error> {
+    let mut explorer = Explorer::new(tuning_name, key, sharp, setup, use_degrees)?;
+
+    terminal::enable_raw_mode()?;
+    io::stdout().execute(EnterAlternateScreen)?;
+    let result = Terminal::new(CrosstermBackend::new(io::stdout()))
+        .and_then(|mut terminal| event_loop(&mut terminal, &mut explorer, highlight));
+    // restore the terminal even if drawing failed
+    terminal::disable_raw_mode()?;
+    io::stdout().execute(LeaveAlternateScreen)?;
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explorer() -> Explorer {
+        let setup = Setup {
+            scale: None,
+            position: 1,
+        };
+        Explorer::new("richter", "C", None, &setup, false).unwrap()
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut explorer = explorer();
+        explorer.cycle_key(false);
+        assert_eq!(explorer.root().0[0], "B");
+        explorer.cycle_key(true);
+        explorer.cycle_key(true);
+        assert_eq!(explorer.root().0[0], "Db");
+
+        explorer.cycle_position(false);
+        assert_eq!(explorer.setup().position, 12);
+
+        explorer.cycle_scale(false);
+        let last = explorer.setup().scale;
+        assert!(last.is_some());
+        explorer.cycle_scale(true);
+        assert_eq!(explorer.setup().scale, None);
+        explorer.cycle_scale(true);
+        assert_eq!(explorer.setup().scale, Some(explorer.scales[0]));
+    }
+
+    #[test]
+    fn test_selected() {
+        let mut explorer = explorer();
+        // 3 blow
+        explorer.move_cursor(0, 2);
+        let layout = explorer.layout();
+        let cell = explorer.selected(&layout).unwrap();
+        assert_eq!(cell.pitch_name(), "G4");
+        let tabs: Vec<String> = alternatives(&layout, cell)
+            .iter()
+            .map(|cell| TabNote::from(*cell).to_string())
+            .collect();
+        assert_eq!(tabs, vec!["-2"]);
+
+        // the cursor stops at the edges
+        explorer.move_cursor(-20, 20);
+        assert_eq!(explorer.cursor, (0, 9));
+        assert!(explorer.selected(&explorer.layout()).is_none());
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut explorer = explorer();
+        explorer.cycle_scale(true);
+        explorer.move_cursor(1, 1);
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 20)).unwrap();
+        terminal
+            .draw(|frame| draw(frame, &explorer, Highlight::default()))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let text: Vec<String> = (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol())
+                    .collect()
+            })
+            .collect();
+        assert!(text[0].contains("C richter harmonica, acoustic scale, 1st position"));
+        assert!(text[13].contains("tab -2: hole 2 draw"));
+        assert!(text[15].contains("also played as 3"));
+        // the cursor is on 2 draw
+        let cursor = buffer.get(26, 6);
+        assert_eq!(cursor.symbol(), "G");
+        assert!(cursor.modifier.contains(Modifier::REVERSED));
+    }
+}
@@ -0,0 +1,802 @@
+//! Parsing of tuning files.
+//!
+//! Two formats are read. The original format is two rows of notes, blow notes first,
+//! and uses the first blow note as the key the notes are written in:
+//!
+//! ```text
+//! C E G C E G C E G C
+//! D G B D F A B D F A
+//! ```
+//!
+//! Version 1 files start with a `version: 1` line, followed by `field: value` lines:
+//!
+//! ```text
+//! # pentaharp, written for a harp in C
+//! version: 1
+//! name: pentaharp
+//! aliases: penta, pentatonic
+//! description: minor pentatonic blow and draw notes
+//! key: C
+//! holes: 10
+//! blow: A D E A D E A D E A
+//! draw: C Eb G C Eb G C Eb G C
+//! override: overblows 1 -
+//! ```
+//!
+//! `aliases` are other names the tuning is found by, separated by commas.
+//! `blow` and `draw` are required. `key` is the key of the harp the notes are written
+//! for and defaults to the first blow note. `holes` checks the length of both rows.
+//! Each `override` line replaces a computed bend, overblow or overdraw: a row name as
+//! used in JSON output (e.g. `bends_half`), a hole number and a note, or `-` to remove
+//! the note.
+//!
+//! In both formats notes are separated by spaces or tabs, and blank lines and lines
+//! starting with `#` are ignored.
+
+use crate::layout::Row;
+use crate::{pitch_class, ChromaticScale, Error, Tuning};
+use std::fmt;
+
+/// a word of a tuning file with its position, line and column start at 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// replaces the computed note of a row on one hole
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Override {
+    pub row: Row,
+    pub hole: usize,
+    /// `None` removes the note
+    pub note: Option<Token>,
+    pub line: usize,
+    /// column of the hole number
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// the tuning cannot be used
+    Error,
+    /// the tuning can be used, but is probably not what was meant
+    Warning,
+}
+
+/// a problem found in a tuning file, line and column start at 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, severity, self.message
+        )
+    }
+}
+
+impl From<Diagnostic> for Error {
+    fn from(diagnostic: Diagnostic) -> Error {
+        Error::MalformedTuning(diagnostic.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TuningFile {
+    /// 0 for the original two row format
+    pub version: u32,
+    pub name: Option<String>,
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    pub key: Option<Token>,
+    pub holes: Option<usize>,
+    pub blow: Vec<Token>,
+    pub draw: Vec<Token>,
+    pub overrides: Vec<Override>,
+}
+
+fn malformed(line: usize, column: usize, message: String) -> Diagnostic {
+    Diagnostic {
+        line,
+        column,
+        severity: Severity::Error,
+        message,
+    }
+}
+
+fn warning(token: &Token, message: String) -> Diagnostic {
+    Diagnostic {
+        line: token.line,
+        column: token.column,
+        severity: Severity::Warning,
+        message,
+    }
+}
+
+/// splits `text` on whitespace, `column` is the column of the first character of `text`
+fn tokens(text: &str, line: usize, column: usize) -> Vec<Token> {
+    let mut res = Vec::new();
+    let mut start = None;
+    for (i, c) in text.chars().chain(std::iter::once(' ')).enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                res.push(Token {
+                    text: text.chars().skip(s).take(i - s).collect(),
+                    line,
+                    column: column + s,
+                });
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    res
+}
+
+/// returns the lines that are not blank or comments, with their line numbers
+fn content_lines(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+}
+
+/// checks a tuning file and returns every problem found. Problems with the structure
+/// of the file stop the check at the first one.
+pub fn check(contents: &str) -> Vec<Diagnostic> {
+    match TuningFile::parse_structure(contents) {
+        Ok(file) => {
+            let mut res = file.check();
+            if file.version == 0 {
+                if let Some((line, text)) = content_lines(contents).nth(2) {
+                    res.push(Diagnostic {
+                        line,
+                        column: text.len() - text.trim_start().len() + 1,
+                        severity: Severity::Warning,
+                        message: String::from(
+                            "only the first two rows are read, this line is ignored",
+                        ),
+                    });
+                }
+            }
+            res
+        }
+        Err(diagnostic) => vec![diagnostic],
+    }
+}
+
+impl TuningFile {
+    pub fn parse(contents: &str) -> Result<TuningFile, Error> {
+        Ok(TuningFile::parse_structure(contents)?)
+    }
+
+    fn parse_structure(contents: &str) -> Result<TuningFile, Diagnostic> {
+        let is_versioned = content_lines(contents)
+            .next()
+            .is_some_and(|(_, line)| line.trim_start().starts_with("version:"));
+
+        if is_versioned {
+            TuningFile::parse_versioned(contents)
+        } else {
+            TuningFile::parse_rows(contents)
+        }
+    }
+
+    fn parse_rows(contents: &str) -> Result<TuningFile, Diagnostic> {
+        let mut lines = content_lines(contents);
+        let (blow, draw) = match (lines.next(), lines.next()) {
+            (Some((i, blow)), Some((j, draw))) => (tokens(blow, i, 1), tokens(draw, j, 1)),
+            _ => {
+                return Err(malformed(
+                    1,
+                    1,
+                    String::from("expected a blow row and a draw row"),
+                ))
+            }
+        };
+        // further lines are ignored, as they always were, and only warned about by `check`
+        Ok(TuningFile {
+            blow,
+            draw,
+            ..TuningFile::default()
+        })
+    }
+
+    fn parse_versioned(contents: &str) -> Result<TuningFile, Diagnostic> {
+        let mut file = TuningFile::default();
+        let mut seen: Vec<&str> = Vec::new();
+        let mut last_line = 1;
+
+        for (i, line) in content_lines(contents) {
+            last_line = i;
+            let indent = line.len() - line.trim_start().len() + 1;
+            let (field, value) = line.split_once(':').ok_or_else(|| {
+                malformed(
+                    i,
+                    indent,
+                    format!("expected \"field: value\", got \"{}\"", line.trim()),
+                )
+            })?;
+            let column = line.len() - value.len();
+            let values = tokens(value, i, line[..column].chars().count() + 1);
+            let value_column = values.first().map_or(column + 1, |t| t.column);
+            let field = field.trim();
+            let value = value.trim();
+
+            if field != "override" {
+                if seen.contains(&field) {
+                    return Err(malformed(i, indent, format!("\"{}\" is set twice", field)));
+                }
+                seen.push(field);
+            }
+
+            match field {
+                "version" => {
+                    file.version = value.parse().map_err(|_| {
+                        malformed(i, value_column, format!("invalid version \"{}\"", value))
+                    })?;
+                    if file.version != 1 {
+                        return Err(malformed(
+                            i,
+                            value_column,
+                            format!("unsupported version {}, expected 1", file.version),
+                        ));
+                    }
+                }
+                "name" => file.name = Some(value.to_string()),
+                "aliases" => {
+                    file.aliases = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|alias| !alias.is_empty())
+                        .map(String::from)
+                        .collect()
+                }
+                "description" => file.description = Some(value.to_string()),
+                "key" => match values.as_slice() {
+                    [key] => file.key = Some(key.clone()),
+                    _ => {
+                        return Err(malformed(
+                            i,
+                            value_column,
+                            String::from("expected a single key"),
+                        ))
+                    }
+                },
+                "holes" => {
+                    file.holes = Some(value.parse().map_err(|_| {
+                        malformed(
+                            i,
+                            value_column,
+                            format!("invalid number of holes \"{}\"", value),
+                        )
+                    })?)
+                }
+                "blow" => file.blow = values,
+                "draw" => file.draw = values,
+                "override" => file
+                    .overrides
+                    .push(Override::parse(&values, i, value_column)?),
+                _ => return Err(malformed(i, indent, format!("unknown field \"{}\"", field))),
+            }
+        }
+
+        if file.blow.is_empty() || file.draw.is_empty() {
+            return Err(malformed(
+                last_line,
+                1,
+                String::from("\"blow\" and \"draw\" rows are required"),
+            ));
+        }
+        Ok(file)
+    }
+
+    /// returns the key the notes are written in
+    pub fn key(&self) -> &str {
+        match &self.key {
+            Some(key) => &key.text,
+            None => &self.blow[0].text,
+        }
+    }
+
+    /// returns every problem of a parsed tuning file: unknown notes, rows of different
+    /// lengths, overrides of missing holes, mixed sharps and flats, rows that are read in
+    /// a different octave than meant and notes that cannot be played
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut res = Vec::new();
+
+        if let Some(key) = &self.key {
+            if pitch_class(&key.text).is_none() {
+                res.push(unknown_note(key, "key"));
+            }
+        }
+        let overridden = self.overrides.iter().filter_map(|o| o.note.as_ref());
+        for note in self.blow.iter().chain(&self.draw).chain(overridden) {
+            if pitch_class(&note.text).is_none() {
+                res.push(unknown_note(note, "note"));
+            }
+        }
+
+        if self.blow.len() != self.draw.len() {
+            let (longer, shorter) = if self.blow.len() > self.draw.len() {
+                (&self.blow, &self.draw)
+            } else {
+                (&self.draw, &self.blow)
+            };
+            let token = &longer[shorter.len()];
+            res.push(malformed(
+                token.line,
+                token.column,
+                format!(
+                    "blow row has {} notes but draw row has {}",
+                    self.blow.len(),
+                    self.draw.len()
+                ),
+            ));
+        }
+        if let Some(holes) = self.holes {
+            for (row, notes) in [("blow", &self.blow), ("draw", &self.draw)] {
+                if notes.len() != holes {
+                    res.push(malformed(
+                        notes[0].line,
+                        notes[0].column,
+                        format!("{} row has {} notes, expected {}", row, notes.len(), holes),
+                    ));
+                }
+            }
+        }
+        for o in &self.overrides {
+            if o.hole == 0 || o.hole > self.blow.len() {
+                res.push(malformed(
+                    o.line,
+                    o.column,
+                    format!("hole {} does not exist", o.hole),
+                ));
+            }
+        }
+
+        res.extend(self.check_accidentals());
+
+        if res.iter().any(|d| d.severity == Severity::Error) {
+            return res;
+        }
+
+        let key = pitch_class(self.key()).unwrap();
+        let tuning = match self.build(key) {
+            Ok(tuning) => tuning,
+            Err(e) => {
+                let reason = match e {
+                    Error::MalformedTuning(reason) => reason,
+                    e => e.to_string(),
+                };
+                res.push(malformed(self.blow[0].line, self.blow[0].column, reason));
+                return res;
+            }
+        };
+        res.extend(self.check_octaves(&tuning));
+        res.extend(self.check_reachable(&tuning, key));
+        res
+    }
+
+    fn check_accidentals(&self) -> Vec<Diagnostic> {
+        let mut res = Vec::new();
+        let mut first: Option<&Token> = None;
+        let notes = self.blow.iter().chain(&self.draw);
+        for note in notes.filter(|n| n.text.len() == 2) {
+            match first {
+                None => first = Some(note),
+                Some(first) if first.text.ends_with('#') != note.text.ends_with('#') => {
+                    res.push(warning(
+                        note,
+                        format!(
+                            "\"{}\" mixes sharps and flats with \"{}\" on line {}, column {}",
+                            note.text, first.text, first.line, first.column
+                        ),
+                    ));
+                }
+                _ => (),
+            }
+        }
+        res
+    }
+
+    /// rows are read as rising by less than an octave from hole to hole, so a row
+    /// that falls or jumps is read in a different octave than the other row
+    fn check_octaves(&self, tuning: &Tuning) -> Vec<Diagnostic> {
+        let mut res = Vec::new();
+        let mut drifted = false;
+        for (i, (blow, draw)) in tuning.blow.iter().zip(&tuning.draw).enumerate() {
+            let (blow, draw) = match (blow, draw) {
+                (Some(blow), Some(draw)) => (*blow, *draw),
+                _ => continue,
+            };
+            let drifts = draw >= blow + 12 || blow >= draw + 12;
+            if drifts && !drifted {
+                let (token, other) = if draw > blow {
+                    (&self.draw[i], &self.blow[i])
+                } else {
+                    (&self.blow[i], &self.draw[i])
+                };
+                res.push(warning(
+                    token,
+                    format!(
+                        "\"{}\" of hole {} is read more than an octave above \"{}\", \
+                         every note must be less than an octave above the note of the previous hole",
+                        token.text,
+                        i + 1,
+                        other.text
+                    ),
+                ));
+            }
+            drifted = drifts;
+        }
+        res
+    }
+
+    /// reports the notes between the lowest and the highest note of the tuning that no
+    /// hole can play, which `notes_in_order` marks as "X"
+    fn check_reachable(&self, tuning: &Tuning, key: usize) -> Vec<Diagnostic> {
+        let notes: Vec<usize> = Row::ALL
+            .iter()
+            .flat_map(|row| tuning.row(*row).iter().flatten().copied())
+            .collect();
+        let (min, max) = match (notes.iter().min(), notes.iter().max()) {
+            (Some(min), Some(max)) => (*min, *max),
+            _ => return Vec::new(),
+        };
+        let uses_flats = self
+            .blow
+            .iter()
+            .chain(&self.draw)
+            .any(|n| n.text.ends_with('b'));
+        let names = ChromaticScale::new("C", Some(!uses_flats)).unwrap();
+
+        (min..max)
+            .filter(|note| !notes.contains(note))
+            .map(|note| {
+                let name = names.0[(note + key) % 12];
+                let hole = tuning
+                    .blow
+                    .iter()
+                    .zip(&tuning.draw)
+                    .position(|(b, d)| (*b).max(*d).is_some_and(|reed| reed > note));
+                // an override can go above every reed
+                let (hole, place) = match hole {
+                    Some(hole) => (hole, "below"),
+                    None => (self.blow.len() - 1, "above"),
+                };
+                warning(
+                    &self.blow[hole],
+                    format!(
+                        "\"{}\" {} hole {} cannot be played by any blow, draw, bend, overblow or overdraw",
+                        name,
+                        place,
+                        hole + 1
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    pub fn to_tuning(&self) -> Result<Tuning, Error> {
+        if let Some(diagnostic) = self
+            .check()
+            .into_iter()
+            .find(|d| d.severity == Severity::Error)
+        {
+            return Err(diagnostic.into());
+        }
+        self.build(pitch_class(self.key()).unwrap())
+    }
+
+    /// builds the tuning of a file without errors
+    fn build(&self, key: usize) -> Result<Tuning, Error> {
+        let top = relative_notes(&self.blow, key);
+        let bottom = relative_notes(&self.draw, key);
+        let mut tuning = Tuning::new(top, bottom)?;
+
+        for o in &self.overrides {
+            o.apply(&mut tuning, key);
+        }
+        Ok(tuning)
+    }
+}
+
+fn unknown_note(token: &Token, what: &str) -> Diagnostic {
+    let mut chars = token.text.chars();
+    let capitalized: String = chars
+        .next()
+        .map(|c| c.to_ascii_uppercase())
+        .into_iter()
+        .chain(chars)
+        .collect();
+    let hint = if pitch_class(&capitalized).is_some() {
+        format!(", did you mean \"{}\"?", capitalized)
+    } else {
+        String::new()
+    };
+    malformed(
+        token.line,
+        token.column,
+        format!("unknown {} \"{}\"{}", what, token.text, hint),
+    )
+}
+
+/// converts notes to pitch classes relative to the key
+fn relative_notes(notes: &[Token], key: usize) -> Vec<usize> {
+    notes
+        .iter()
+        .map(|note| (pitch_class(&note.text).unwrap() + 12 - key) % 12)
+        .collect()
+}
+
+impl Override {
+    fn parse(values: &[Token], line: usize, column: usize) -> Result<Override, Diagnostic> {
+        let (row, hole, note) = match values {
+            [row, hole, note] => (row, hole, note),
+            _ => {
+                return Err(malformed(
+                    line,
+                    column,
+                    String::from("expected \"override: <row> <hole> <note or ->\""),
+                ))
+            }
+        };
+        let row_id = row;
+        let row = match Row::from_id(&row.text) {
+            Some(Row::Blow) | Some(Row::Draw) => {
+                return Err(malformed(
+                    line,
+                    row_id.column,
+                    String::from("blow and draw notes are set by the \"blow\" and \"draw\" rows"),
+                ))
+            }
+            Some(row) => row,
+            None => {
+                return Err(malformed(
+                    line,
+                    row_id.column,
+                    format!("unknown row \"{}\"", row.text),
+                ))
+            }
+        };
+        let column = hole.column;
+        let hole = hole
+            .text
+            .parse()
+            .map_err(|_| malformed(line, column, format!("invalid hole \"{}\"", hole.text)))?;
+        let note = if note.text == "-" {
+            None
+        } else {
+            Some(note.clone())
+        };
+
+        Ok(Override {
+            row,
+            hole,
+            note,
+            line,
+            column,
+        })
+    }
+
+    fn apply(&self, tuning: &mut Tuning, key: usize) {
+        let index = self.hole - 1;
+        let value = self.note.as_ref().map(|note| {
+            let note = relative_notes(std::slice::from_ref(note), key)[0];
+            // place the note in the octave closest to the reed it is played on
+            let reed = tuning.row(Row::from_breath(self.row.breath()))[index].unwrap();
+            let up = reed + (note + 12 - reed % 12) % 12;
+            if up - reed > 6 && up >= 12 {
+                up - 12
+            } else {
+                up
+            }
+        });
+        tuning.row_mut(self.row)[index] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rows() {
+        let richter = "# richter\n\n  C E\tG C E G C E G C  \n\nD  G B D F A B D F A\n";
+        let file = TuningFile::parse(richter).unwrap();
+        assert_eq!(file.version, 0);
+        assert_eq!(file.key(), "C");
+        assert_eq!(file.blow.len(), 10);
+        assert_eq!(
+            file.blow[2],
+            Token {
+                text: String::from("G"),
+                line: 3,
+                column: 7
+            }
+        );
+        assert_eq!(file.to_tuning().unwrap(), Tuning::default());
+
+        assert!(TuningFile::parse("C E G\nD G B\nC E G").is_ok());
+    }
+
+    #[test]
+    fn test_parse_versioned() {
+        let contents = "# comment\nversion: 1\nname: richter\naliases: standard, major diatonic\n\
+                        description: standard tuning\n\
+                        key: G\nholes: 10\nblow: G B D G B D G B D G\n\
+                        draw:  A D F# A C E F# A C E\noverride: overblows 1 -\n";
+        let file = TuningFile::parse(contents).unwrap();
+        assert_eq!(file.version, 1);
+        assert_eq!(file.name.as_deref(), Some("richter"));
+        assert_eq!(file.aliases, vec!["standard", "major diatonic"]);
+        assert_eq!(file.description.as_deref(), Some("standard tuning"));
+        assert_eq!(file.key(), "G");
+        assert_eq!(file.draw[0].column, 8);
+
+        let mut expected = Tuning::default();
+        expected.overblows[0] = None;
+        assert_eq!(file.to_tuning().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_explicit_key() {
+        let pentaharp = "version: 1\nkey: C\nblow: A D E A\ndraw: C Eb G C\n";
+        let tuning = TuningFile::parse(pentaharp).unwrap().to_tuning().unwrap();
+        assert_eq!(tuning.blow, vec![Some(9), Some(14), Some(16), Some(21)]);
+        assert_eq!(tuning.draw, vec![Some(12), Some(15), Some(19), Some(24)]);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let contents = "version: 1\nblow: C E G C\ndraw: D G B D\n\
+                        override: bends_half 3 Bb\noverride: overdraws 2 A\n";
+        let tuning = TuningFile::parse(contents).unwrap().to_tuning().unwrap();
+        assert_eq!(tuning.bends_half[2], Some(10));
+        assert_eq!(tuning.overdraws[1], Some(9));
+    }
+
+    #[test]
+    fn test_check() {
+        let contents = "version: 1\nblow: C E G c\ndraw: D G Bb D F#\n";
+        let res: Vec<String> = check(contents).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            res,
+            vec![
+                "2:13: error: unknown note \"c\", did you mean \"C\"?",
+                "3:16: error: blow row has 4 notes but draw row has 5",
+                "3:16: warning: \"F#\" mixes sharps and flats with \"Bb\" on line 3, column 11",
+            ]
+        );
+
+        let contents = "C E G C\nD G B D\n";
+        assert!(check(contents).is_empty());
+
+        // like before version 1, the original format ignores lines after the two rows
+        let contents = "C E G C\nD G B D\n  notes by me\n";
+        assert_eq!(
+            TuningFile::parse(contents).unwrap().to_tuning().unwrap(),
+            TuningFile::parse("C E G C\nD G B D\n")
+                .unwrap()
+                .to_tuning()
+                .unwrap()
+        );
+        let res: Vec<String> = check(contents).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            res,
+            vec!["3:3: warning: only the first two rows are read, this line is ignored"]
+        );
+
+        // the draw row falls from B to G, so G is read an octave higher than meant
+        let contents = "C E G C\nD B G B\n";
+        let res: Vec<Diagnostic> = check(contents)
+            .into_iter()
+            .filter(|d| d.message.contains("octave"))
+            .collect();
+        assert_eq!(res.len(), 1);
+        assert_eq!((res[0].line, res[0].column), (2, 5));
+        assert_eq!(res[0].severity, Severity::Warning);
+
+        // D and D# can't be reached, hole 1 only has an overdraw
+        let contents = "C E G\nC G B\n";
+        let res: Vec<String> = check(contents).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            res,
+            vec![
+                "1:3: warning: \"D\" below hole 2 cannot be played by any blow, draw, bend, overblow or overdraw",
+                "1:3: warning: \"D#\" below hole 2 cannot be played by any blow, draw, bend, overblow or overdraw",
+            ]
+        );
+
+        // the overblow of hole 4 is above every reed, leaving D# and E unplayable
+        let contents = "version: 1\nblow: C E G C\ndraw: D G B D\noverride: overblows 4 F\n";
+        let res: Vec<String> = check(contents).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            res,
+            vec![
+                "2:13: warning: \"D#\" above hole 4 cannot be played by any blow, draw, bend, overblow or overdraw",
+                "2:13: warning: \"E\" above hole 4 cannot be played by any blow, draw, bend, overblow or overdraw",
+            ]
+        );
+
+        // the holes repeat the same notes, so they can't be put in order
+        let res: Vec<String> = check("C C C\nC C C\n")
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            res,
+            vec!["1:1: error: the notes of hole 2 can't be put in order with the holes before it"]
+        );
+        let contents = "version: 1\n\nblow: C C C\ndraw: C C C\n";
+        let res: Vec<String> = check(contents).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            res,
+            vec!["3:7: error: the notes of hole 2 can't be put in order with the holes before it"]
+        );
+        assert!(TuningFile::parse(contents).unwrap().to_tuning().is_err());
+    }
+
+    #[test]
+    fn test_builtin_tunings_check() {
+        for (name, contents) in crate::tunings::get_tunings() {
+            let errors: Vec<Diagnostic> = check(contents)
+                .into_iter()
+                .filter(|d| d.severity == Severity::Error)
+                .collect();
+            assert!(errors.is_empty(), "{}: {:?}", name, errors);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        for contents in [
+            "version: 2\nblow: C\ndraw: D\n",
+            "version: 1\nblow: C\n",
+            "version: 1\nkey: C D\nblow: C\ndraw: D\n",
+            "version: 1\ncolor: red\nblow: C\ndraw: D\n",
+            "version: 1\nblow: C\nblow: C\ndraw: D\n",
+            "version: 1\noverride: blow 1 C\nblow: C\ndraw: D\n",
+            "version: 1\noverride: overblows 1\nblow: C\ndraw: D\n",
+        ] {
+            assert!(
+                matches!(TuningFile::parse(contents), Err(Error::MalformedTuning(_))),
+                "{}",
+                contents
+            );
+        }
+
+        for contents in [
+            "version: 1\nholes: 3\nblow: C E\ndraw: D G\n",
+            "version: 1\nkey: H\nblow: C E\ndraw: D G\n",
+            "version: 1\nblow: C E\ndraw: D X\n",
+            "version: 1\noverride: overblows 3 D#\nblow: C E\ndraw: D G\n",
+        ] {
+            let file = TuningFile::parse(contents).unwrap();
+            assert!(
+                matches!(file.to_tuning(), Err(Error::MalformedTuning(_))),
+                "{}",
+                contents
+            );
+        }
+    }
+}
@@ -11,7 +11,7 @@ pub fn get_tunings() -> HashMap<&'static str, &'static str> {
         ("natural minor", "C Eb G C Eb G C Eb G C\nD G Bb D F A Bb D F A\n"),
         ("harmonic minor", "C Eb G C Eb G C Eb G C\nD G B D F Ab B D F Ab\n"),
         ("paddy richter", "C E A C E G C E G C\nD G B D F A B D F A\n"),
-        ("pentaharp", "A D E A D E A D E A\nC Eb G C Eb G C Eb G C"),
+        ("pentaharp", "version: 1\nname: pentaharp\nkey: C\nblow: A D E A D E A D E A\ndraw: C Eb G C Eb G C Eb G C\n"),
         ("powerdraw", "C E G C E G A C E A\nD G B D F A B D G C"),
         ("powerbender", "C E G C D F A C E A\nD G B D E G B D G C"),
         ("diminished", "C Eb Gb A C Eb Gb A C Eb\nD F Ab B D F Ab B D F"),
@@ -23,3 +23,19 @@ pub fn get_tunings() -> HashMap<&'static str, &'static str> {
         ("5 hole richter", "C E G C E\nD F A B D"),
     ])
 }
+
+/// other names of built-in tunings
+#[rustfmt::skip]
+pub fn get_tuning_aliases() -> HashMap<&'static str, &'static [&'static str]> {
+    HashMap::<&str, &[&str]>::from([
+        ("richter", &["standard", "major diatonic"][..]),
+        ("wilde tuning", &["wilde"][..]),
+        ("wilde minor tuning", &["wilde minor"][..]),
+        ("natural minor", &["minor"][..]),
+        ("paddy richter", &["paddy"][..]),
+        ("powerdraw", &["power draw"][..]),
+        ("powerbender", &["power bender"][..]),
+        ("lucky 13 diminished", &["lucky 13"][..]),
+        ("easy 3rd", &["easy third"][..]),
+    ])
+}